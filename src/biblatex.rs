@@ -1,137 +1,621 @@
 use std::io::{
     Read,
+    Write,
 };
+use std::collections::HashMap;
 use std::str;
 
 use log::{
     debug,
+    warn,
     error,
 };
 use biblatex::{
     Bibliography,
     Type,
     Entry as Entry,
+    Chunk,
+    Chunks,
+    ChunksExt,
+    Spanned,
+    EntryType,
+    DateValue,
 };
 
-use crate::meta::MetaData;
-use crate::error::ParseError;
+use crate::meta::{
+    MetaData,
+    PublishDate,
+    publish_date_valid,
+    publish_date_to_string,
+};
+use crate::dc::Creator;
+use crate::dc::creator;
+use crate::error::{
+    Error,
+    Format,
+};
 use crate::digest::RecordDigest;
-use crate::digest::from_urn;
+use crate::digest::urn as digest_urn;
+
+/// Returns the end of the field value starting at `i`, which is either delimited by braces or
+/// quotes, or a bare word.
+fn raw_value_end(b: &[u8], mut i: usize) -> usize {
+    let mut depth: usize = 0;
+    let quoted = b.get(i) == Some(&b'"');
+    if quoted {
+        i += 1;
+    }
+    while i < b.len() {
+        match b[i] {
+            b'{' => {
+                depth += 1;
+            },
+            b'}' if depth == 0 => {
+                return i;
+            },
+            b'}' => {
+                depth -= 1;
+                if depth == 0 && !quoted {
+                    return i + 1;
+                }
+            },
+            b'"' if quoted && depth == 0 => {
+                return i + 1;
+            },
+            b',' | b'#' if !quoted && depth == 0 => {
+                return i;
+            },
+            _ => {},
+        };
+        i += 1;
+    }
+    i
+}
+
+/// Returns the values of all `note` fields of each entry in the `bibtex` source, by citation key.
+///
+/// The biblatex parser keeps only the last of several fields with the same name, so the source is
+/// scanned for them directly.
+fn raw_notes(src: &str) -> HashMap<String, Vec<String>> {
+    let mut r: HashMap<String, Vec<String>> = HashMap::new();
+    let b = src.as_bytes();
+    let mut i: usize = 0;
+    while let Some(n) = src[i..].find('@') {
+        i += n + 1;
+        let open = match src[i..].find(['{', '(']) {
+            Some(v) => {
+                i + v
+            },
+            None => {
+                break;
+            },
+        };
+        let key_end = match src[open..].find([',', '}']) {
+            Some(v) => {
+                open + v
+            },
+            None => {
+                break;
+            },
+        };
+        let key = src[open + 1..key_end].trim().to_string();
+        i = key_end;
+        while i < b.len() && b[i] == b',' {
+            i += 1;
+            let eq = match src[i..].find(['=', '}', ')']) {
+                Some(v) => {
+                    i + v
+                },
+                None => {
+                    break;
+                },
+            };
+            if b[eq] != b'=' {
+                i = eq;
+                break;
+            }
+            let name = src[i..eq].trim().to_lowercase();
+            let mut value = String::new();
+            i = eq + 1;
+            loop {
+                while i < b.len() && b[i].is_ascii_whitespace() {
+                    i += 1;
+                }
+                let end = raw_value_end(b, i);
+                value.push_str(&src[i..end]);
+                i = end;
+                while i < b.len() && b[i].is_ascii_whitespace() {
+                    i += 1;
+                }
+                if i < b.len() && b[i] == b'#' {
+                    i += 1;
+                    continue;
+                }
+                break;
+            }
+            if name == "note" {
+                r.entry(key.clone()).or_insert(vec!()).push(value);
+            }
+        }
+    }
+    r
+}
+
+/// Parse digest URNs from the `note` fields of the entry.
+///
+/// Multiple digests may be given, separated by whitespace, and in more than one `note` field.
+/// `notes` are the values of all `note` fields of the entry in the source, see
+/// [raw_notes](raw_notes). If there are none, the parsed `note` field is used. Words that are not
+/// valid digest URNs are ignored.
+fn parse_digests(entry: &Entry, notes: Option<&Vec<String>>) -> Vec<RecordDigest> {
+    let mut r: Vec<RecordDigest> = vec!();
+    let notes: Vec<String> = match notes {
+        Some(v) if !v.is_empty() => {
+            v.clone()
+        },
+        _ => {
+            let note = match entry.get("note") {
+                Some(v) => {
+                    v
+                },
+                None => {
+                    return r;
+                },
+            };
+            match String::from_chunks(note) {
+                Ok(v) => {
+                    vec!(v)
+                },
+                Err(e) => {
+                    debug!("invalid note in entry {}: {:?}", entry.key, e);
+                    return r;
+                },
+            }
+        },
+    };
+    for note_s in notes.iter() {
+        for v in note_s.split_whitespace() {
+            let v = v.trim_matches(|c| c == '{' || c == '}' || c == '"');
+            match digest_urn::parse(v) {
+                Ok(RecordDigest::Empty) => {},
+                Ok(digest) => {
+                    if !r.contains(&digest) {
+                        r.push(digest);
+                    }
+                },
+                Err(e) => {
+                    debug!("skipping non-digest note content {:?}: {:?}", v, e);
+                },
+            };
+        }
+    }
+    r
+}
 
-fn parse_digest(entry: &Entry) -> RecordDigest {
-    let note = match entry.get("note") {
-        Some(v) => {
+/// Returns the value of the first of the given fields defined in the entry.
+fn field_value(entry: &Entry, keys: &[&str]) -> Option<String> {
+    for k in keys {
+        if let Some(v) = entry.get(k) {
+            match String::from_chunks(v) {
+                Ok(s) => {
+                    return Some(s);
+                },
+                Err(e) => {
+                    debug!("invalid value for field {}: {:?}", k, e);
+                },
+            };
+        }
+    }
+    None
+}
+
+/// Returns the identifier of the entry, in order of preference from `doi`, `isbn`, `issn` and `url`.
+fn parse_identifier(entry: &Entry) -> Option<String> {
+    if let Some(v) = field_value(entry, &["doi"]) {
+        return Some(format!("doi:{}", v));
+    }
+    if let Some(v) = field_value(entry, &["isbn"]) {
+        return Some(format!("urn:isbn:{}", v));
+    }
+    if let Some(v) = field_value(entry, &["issn"]) {
+        return Some(format!("urn:issn:{}", v));
+    }
+    field_value(entry, &["url"])
+}
+
+/// Returns the extent of the entry from the `pagetotal` or `pages` fields.
+fn parse_extent(entry: &Entry) -> Option<String> {
+    if let Some(v) = field_value(entry, &["pagetotal"]) {
+        return Some(format!("{} pages", v));
+    }
+    field_value(entry, &["pages"]).map(|v| {
+        format!("pp. {}", v)
+    })
+}
+
+/// Returns the publication date of the entry from the `date` field, or the `year` and `month` fields.
+///
+/// For date ranges, the start of the range is used.
+fn parse_publish_date(entry: &Entry) -> Option<PublishDate> {
+    let date = match entry.date() {
+        Ok(v) => {
             v
         },
-        None => {
-            return RecordDigest::Empty;
+        Err(e) => {
+            debug!("no valid date in entry {}: {:?}", entry.key, e);
+            return None;
+        },
+    };
+    let v = match date.value {
+        DateValue::At(v) => {
+            v
+        },
+        DateValue::After(v) => {
+            v
+        },
+        DateValue::Before(v) => {
+            v
+        },
+        DateValue::Between(v, _) => {
+            v
         },
     };
-    let note_s = String::from_chunks(note).unwrap();
-    from_urn(note_s.as_str()).unwrap()
+    if v.year < 1 {
+        debug!("unsupported year {} in entry {}", v.year, entry.key);
+        return None;
+    }
+    // the biblatex crate silently drops a day that does not exist in the month of an iso date.
+    if v.day.is_none() {
+        if let Some(raw) = entry.get("date") {
+            let raw = raw.format_verbatim();
+            let start = raw.split(['/', 'T']).next().unwrap_or("");
+            if start.trim().split('-').count() > 2 {
+                debug!("invalid date {} in entry {}", raw, entry.key);
+                return None;
+            }
+        }
+    }
+    let month = v.month.map(|m| m + 1).unwrap_or(0);
+    let day = match month {
+        0 => {
+            0
+        },
+        _ => {
+            v.day.map(|d| d + 1).unwrap_or(0)
+        },
+    };
+    let date = (day, month, v.year as u32);
+    if !publish_date_valid(&date) {
+        debug!("invalid date {:?} in entry {}", date, entry.key);
+        return None;
+    }
+    Some(date)
 }
 
 /// Read one or more metadata entries from the `bibtex` source.
 ///
-/// Will return `ParseError` if any of the records are invalid.
+/// Will return [Error::Parse](Error::Parse) if the source cannot be parsed, or if any of the
+/// records have no title or no digest.
 ///
 /// # Arguments 
 ///
 /// * `r` - reader implementation providing the source.
-pub fn read_all(mut r: impl Read, digests: &Vec<RecordDigest>) -> Result<Vec<MetaData>, ParseError> {
+pub fn read_all(mut r: impl Read, digests: &Vec<RecordDigest>) -> Result<Vec<MetaData>, Error> {
     let mut s = String::new();
-    let c = r.read_to_string(&mut s);
+    r.read_to_string(&mut s)?;
     let bib = match Bibliography::parse(&s) {
         Ok(v) => {
             v
         },
         Err(e) => {
             error!("parse error for biblatex");
-            return Err(ParseError::new("Not a biblatex source"));
+            return Err(Error::parse(Format::Biblatex, &e.to_string()));
         },
     };
 
+    if bib.len() == 0 {
+        return Err(Error::parse(Format::Biblatex, "no entries"));
+    }
+
     if bib.len() > 1 && digests.len() > 0 {
         error!("more than one biblatex entry parsed while static digest provided");
-        return Err(ParseError::new("more than one biblatex entry parsed while static digest provided"));
+        return Err(Error::parse(Format::Biblatex, "more than one biblatex entry parsed while static digest provided"));
     }
 
+    let notes = raw_notes(&s);
     let mut rr: Vec<MetaData> = vec!();
 
     for e in bib.iter() {
-        let authors = e.author()
-            .unwrap()
-            .into_iter()
-            .map(|v| {
-            format!("{} {}", v.given_name, v.name)
-        });
-        let authors_s = authors.fold(String::new(), |x, y| {
-            if x.len() == 0 {
-                return y
-            }
-            format!("{}, {}", x, y)
-        });
-
-        let mut use_digests: Vec<RecordDigest> = vec!();
-
-        // use clone instead
-        let digest = parse_digest(&e);
-        match digest {
-            RecordDigest::Sha512(r) => {
-                use_digests.push(RecordDigest::Sha512(r));
-            },
-            RecordDigest::Sha256(r) => {
-                use_digests.push(RecordDigest::Sha256(r));
+        let creators: Vec<Creator> = match name_list_value(e, "author") {
+            Some(v) => {
+                creator::parse_source_list(&v)
             },
-            RecordDigest::MD5(r) => {
-                use_digests.push(RecordDigest::MD5(r));
+            None => {
+                debug!("no authors in entry {}", e.key);
+                vec!()
             },
-            RecordDigest::SwarmHash(r) => {
-                use_digests.push(RecordDigest::SwarmHash(r));
-            },
-            _ => {
-            },
-        }
+        };
+
+        let mut use_digests = parse_digests(e, notes.get(&e.key));
 
         for v in digests {
             use_digests.push(v.clone());
         }
-        let title = e.title().unwrap();
-        let title_s = String::from_chunks(title).unwrap();
+        let title_s = match e.title().map(String::from_chunks) {
+            Ok(Ok(v)) => {
+                v
+            },
+            _ => {
+                return Err(Error::parse(Format::Biblatex, &format!("missing title in entry {}", e.key)));
+            },
+        };
 
         if use_digests.len() == 0 {
-            return Err(ParseError::new("no digests found")); 
+            return Err(Error::parse(Format::Biblatex, &format!("no digests found in entry {}", e.key)));
         }
 
+        let mut m = MetaData::new(title_s.as_str(), "", e.entry_type.clone(), RecordDigest::Empty, None);
+        m.set_creators(creators);
         for dd in use_digests.into_iter() {
-            let mut m = MetaData::new(title_s.as_str(), authors_s.as_str(), e.entry_type.clone(), dd, None);
+            m.add_digest(dd);
+        }
 
-            match e.keywords() {
-                Ok(v) => {
-                    let s = String::from_chunks(v).unwrap();
+        if let Ok(v) = e.keywords() {
+            match String::from_chunks(v) {
+                Ok(s) => {
                     m.set_subject(s.as_str());
                 },
-                _ => {},
+                Err(err) => {
+                    debug!("invalid keywords in entry {}: {:?}", e.key, err);
+                },
             };
+        }
 
-            match e.language() {
-                Ok(v) => {
-                    m.set_language(v.as_str());
-                },
-                _ => {},
+        if let Ok(v) = e.language() {
+            if let Err(err) = m.set_language(v.as_str()) {
+                warn!("skipping invalid language in entry {}: {}", e.key, err);
             }
+        }
+
+        let editors: Vec<Creator> = ["editor", "editora", "editorb", "editorc"].iter()
+            .filter_map(|k| {
+                name_list_value(e, k)
+            })
+            .flat_map(|v| {
+                creator::parse_source_list(&v)
+            })
+            .collect();
+        if !editors.is_empty() {
+            m.set_contributors(&editors);
+        }
+
+        if let Some(v) = field_value(e, &["publisher"]) {
+            m.set_publisher(v.as_str());
+        }
+
+        if let Some(v) = parse_publish_date(e) {
+            m.set_publish_date(v);
+        }
+
+        if let Some(v) = parse_identifier(e) {
+            m.set_identifier(v.as_str());
+        }
 
-            debug!("read metadata {:?}", &m);
-            rr.push(m);
+        if let Some(v) = field_value(e, &["abstract"]) {
+            m.set_description(v.as_str());
         }
+
+        if let Some(v) = field_value(e, &["rights", "license"]) {
+            m.set_rights(v.as_str());
+        }
+
+        if let Some(v) = field_value(e, &["journaltitle", "journal", "booktitle", "series"]) {
+            m.set_part_of(v.as_str());
+        }
+
+        if let Some(v) = parse_extent(e) {
+            m.set_extent(v.as_str());
+        }
+
+        debug!("read metadata {:?}", &m);
+        rr.push(m);
     }
     Ok(rr)
 }
 
+/// Returns the value of a name list field in BibTeX name format, with the verbatim parts enclosed
+/// in curly braces.
+///
+/// Unlike the names parsed by [Entry::author](Entry::author), this keeps the names of
+/// organisations, which are enclosed in curly braces, apart from the names of persons.
+fn name_list_value(e: &Entry, key: &str) -> Option<String> {
+    let chunks = e.get(key)?;
+    let r: Vec<String> = chunks.iter()
+        .map(|v| {
+            match &v.v {
+                Chunk::Normal(s) => {
+                    s.clone()
+                },
+                Chunk::Verbatim(s) => {
+                    format!("{{{}}}", s)
+                },
+                Chunk::Math(s) => {
+                    format!("${}$", s)
+                },
+            }
+        })
+        .collect();
+    Some(r.join(""))
+}
+
+fn chunks_from_str(s: &str) -> Chunks {
+    vec!(Spanned::detached(Chunk::Normal(String::from(s))))
+}
+
+/// Returns the creators as an `and`-separated name list.
+///
+/// Names of organisations and the parts of names enclosed in curly braces are verbatim, so that
+/// they are not split into name parts.
+fn chunks_from_creators(creators: &[Creator]) -> Chunks {
+    let mut r: Chunks = vec!();
+    for (i, v) in creators.iter().enumerate() {
+        if i > 0 {
+            r.push(Spanned::detached(Chunk::Normal(String::from(" and "))));
+        }
+        match v {
+            Creator::Organisation(name) => {
+                r.push(Spanned::detached(Chunk::Verbatim(name.clone())));
+            },
+            _ => {
+                r.extend(creator::bibtex_chunks(&v.to_bibtex()));
+            },
+        };
+    }
+    r
+}
+
+/// Generate a `biblatex` entry from a metadata record.
+///
+/// The citation key is the hex digest of the record, and the URNs of all digests of the record
+/// are stored in the `note` field, so that the output can be imported again with
+/// [read_all](read_all).
+pub fn to_entry(entry: &MetaData) -> Entry {
+    let mut e = Entry::new(entry.fingerprint(), entry.typ());
+    e.set_title(chunks_from_str(entry.title().as_str()));
+    e.set("author", chunks_from_creators(&entry.creators()));
+
+    if let Some(v) = entry.subject() {
+        e.set_keywords(chunks_from_str(v.as_str()));
+    }
+
+    if let Some(v) = entry.language() {
+        e.set("language", chunks_from_str(v.to_string().as_str()));
+    }
+
+    let editors = entry.contributors();
+    if !editors.is_empty() {
+        e.set("editor", chunks_from_creators(&editors));
+    }
+
+    if let Some(v) = entry.publisher() {
+        e.set("publisher", chunks_from_str(v.as_str()));
+    }
+
+    if let Some(v) = entry.publish_date() {
+        e.set("date", chunks_from_str(publish_date_to_string(&v).as_str()));
+    }
+
+    if let Some(v) = entry.identifier() {
+        let (k, id) = if let Some(id) = v.strip_prefix("doi:") {
+            ("doi", id)
+        } else if let Some(id) = v.strip_prefix("urn:isbn:") {
+            ("isbn", id)
+        } else if let Some(id) = v.strip_prefix("urn:issn:") {
+            ("issn", id)
+        } else {
+            ("url", v.as_str())
+        };
+        e.set(k, chunks_from_str(id));
+    }
+
+    if let Some(v) = entry.description() {
+        e.set("abstract", chunks_from_str(v.as_str()));
+    }
+
+    if let Some(v) = entry.rights() {
+        e.set("rights", chunks_from_str(v.as_str()));
+    }
+
+    if let Some(v) = entry.part_of() {
+        let k = match entry.typ() {
+            EntryType::Article => {
+                "journaltitle"
+            },
+            _ => {
+                "booktitle"
+            },
+        };
+        e.set(k, chunks_from_str(v.as_str()));
+    }
+
+    if let Some(v) = entry.extent() {
+        if let Some(n) = v.strip_suffix(" pages") {
+            e.set("pagetotal", chunks_from_str(n));
+        } else if let Some(pages) = v.strip_prefix("pp. ") {
+            e.set("pages", chunks_from_str(pages));
+        } else {
+            debug!("cannot represent extent {:?} in bibtex", v);
+        }
+    }
+
+    let note: Vec<String> = entry.digests().iter()
+        .map(|v| {
+            v.urn()
+        })
+        .collect();
+    e.set_note(chunks_from_str(note.join(" ").as_str()));
+    e
+}
+
+/// Write metadata entry in `bibtex` format.
+///
+/// The publication date is written as an ISO 8601 `date` field, as defined by `biblatex`.
+///
+/// On success, returns the number of bytes written.
+///
+/// # Arguments 
+///
+/// * `entry` - metadata to write.
+/// * `w` - writer implementation providing the destination.
+pub fn write(entry: &MetaData, mut w: impl Write) -> Result<usize, Error> {
+    let e = to_entry(entry);
+    let s = e.to_biblatex_string();
+    // keep bibtex type names such as `techreport`, which biblatex accepts as aliases.
+    let s = match s.split_once('\n') {
+        Some((_, fields)) => {
+            format!("@{}{{{},\n{}", e.entry_type, e.key, fields)
+        },
+        None => {
+            s
+        },
+    };
+    w.write_all(s.as_bytes())?;
+    w.write_all(b"\n\n")?;
+    debug!("wrote bibtex entry {:?}", entry);
+    Ok(s.len() + 2)
+}
+
+/// Write one or more metadata entries in `bibtex` format.
+///
+/// On success, returns the total number of bytes written.
+///
+/// # Arguments 
+///
+/// * `entries` - metadata to write.
+/// * `w` - writer implementation providing the destination.
+pub fn write_all(entries: &Vec<MetaData>, mut w: impl Write) -> Result<usize, Error> {
+    let mut c: usize = 0;
+    for m in entries {
+        c += write(m, &mut w)?;
+    }
+    Ok(c)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::read_all;
+    use super::{
+        read_all,
+        write_all,
+    };
+    use std::fs::File;
+    use biblatex::EntryType;
     use crate::digest;
+    use crate::meta::MetaData;
+    use crate::dc::Creator;
+    use crate::error::{
+        Error,
+        Format,
+    };
     use env_logger;
 
     #[test]
@@ -162,9 +646,179 @@ mod tests {
     author={Guybrush Threepwood},
 }
 ";
-        let digests = vec!(d, d_sha);
+        let digests = vec!(d.clone(), d_sha.clone());
         let r = read_all(biblatex_src.as_bytes(), &digests).unwrap();
 
+        assert_eq!(r.len(), 1);
+        assert_eq!(r[0].digests(), vec!(d, d_sha));
+    }
+
+    #[test]
+    fn test_note_digests() {
+        let f = File::open("testdata/meta.biblatex").unwrap();
+        let digests = vec!();
+        let r = read_all(&f, &digests).unwrap();
+
         assert_eq!(r.len(), 2);
+        assert_eq!(r[1].digests().len(), 2);
+        assert_eq!(r[1].urn(), "sha256:7d76e977bbc0b5ca652c058e62be2baf04d2b2106424ff76c7a2b71bff803301");
+    }
+
+    #[test]
+    fn test_duplicate_notes() {
+        let biblatex_src = "@techreport{
+	kenya_dataprotectionact,
+	title = \"The Data Protection Act, 2019\",
+	note = \"sha256:7d76e977bbc0b5ca652c058e62be2baf04d2b2106424ff76c7a2b71bff803301\",
+	pages = \"901--948\",
+	note = \"sha512:9136bb8c656f0ac208b1802ee23980c3b761ef4153c09f723c4724c841c048c159ee61be8222d202b698b718768b3d4931046e977f4e858dc46ae9d2d8ac4afa\",
+}
+
+@article{foo,
+    title={bar, {baz}},
+    note={see also {sha256:2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae}},
+    note=\"sha256:2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae\",
+}
+";
+        let r = read_all(biblatex_src.as_bytes(), &vec!()).unwrap();
+        assert_eq!(r.len(), 2);
+        assert_eq!(r[0].urn(), "sha256:7d76e977bbc0b5ca652c058e62be2baf04d2b2106424ff76c7a2b71bff803301");
+        assert_eq!(r[0].digests().len(), 2);
+        assert_eq!(r[1].digests().len(), 1);
+        assert_eq!(r[1].urn(), "sha256:2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae");
+    }
+
+    #[test]
+    fn test_write_roundtrip() {
+        let f = File::open("testdata/meta.biblatex").unwrap();
+        let digests = vec!();
+        let r = read_all(&f, &digests).unwrap();
+
+        let mut v: Vec<u8> = vec!();
+        write_all(&r, &mut v).unwrap();
+        let r_check = read_all(v.as_slice(), &digests).unwrap();
+
+        assert_eq!(r.len(), r_check.len());
+        for (m, m_check) in r.iter().zip(r_check.iter()) {
+            assert_eq!(m.digests(), m_check.digests());
+            assert_eq!(m.title(), m_check.title());
+            assert_eq!(m.author(), m_check.author());
+            assert_eq!(m.creators(), m_check.creators());
+            assert_eq!(m.typ(), m_check.typ());
+            assert_eq!(m.subject(), m_check.subject());
+            assert_eq!(m.language(), m_check.language());
+            assert_eq!(m.publisher(), m_check.publisher());
+            assert_eq!(m.publish_date(), m_check.publish_date());
+            assert_eq!(m.identifier(), m_check.identifier());
+            assert_eq!(m.part_of(), m_check.part_of());
+            assert_eq!(m.extent(), m_check.extent());
+        }
+    }
+
+    #[test]
+    fn test_write_creators() {
+        let digest_sha = digest::from_vec(vec!(0x2a; 64)).unwrap();
+        let mut m = MetaData::new("foo", "", EntryType::Article, digest_sha, None);
+        m.set_creators(vec!(
+            Creator::person("", "Van Gogh"),
+            Creator::organisation("Government of Kenya"),
+            Creator::person("Satoshi", "Nakamoto"),
+            Creator::person("Ursula K.", "Le Guin"),
+        ));
+        m.set_contributors(&[Creator::person("", "Van Gogh")]);
+
+        let mut v: Vec<u8> = vec!();
+        write_all(&vec!(m.clone()), &mut v).unwrap();
+        let r = read_all(v.as_slice(), &vec!()).unwrap();
+        assert_eq!(r[0].creators(), m.creators());
+        assert_eq!(r[0].contributors(), m.contributors());
+    }
+
+    #[test]
+    fn test_publish_date() {
+        let biblatex_src = "@article{
+    foo,
+    title={bar},
+    author={Guybrush Threepwood},
+    date={2008-10-31},
+    note={sha512:f7fbba6e0636f890e56fbbf3283e524c6fa3204ae298382d624741d0dc6638326e282c41be5e4254d8820772c5518a2c5a8c0c7f7eda19594a7eb539453e1ed7},
+}
+";
+        let digests = vec!();
+        let r = read_all(biblatex_src.as_bytes(), &digests).unwrap();
+        assert_eq!(r[0].publish_date().unwrap(), (31, 10, 2008));
+
+        let mut v: Vec<u8> = vec!();
+        write_all(&r, &mut v).unwrap();
+        let s = String::from_utf8(v.clone()).unwrap();
+        assert!(s.contains("2008-10-31"));
+        assert!(!s.contains("day"));
+        let r_check = read_all(v.as_slice(), &digests).unwrap();
+        assert_eq!(r_check[0].publish_date().unwrap(), (31, 10, 2008));
+
+        let biblatex_src = "@article{
+    foo,
+    title={bar},
+    author={Guybrush Threepwood},
+    date={2019-02-31},
+    note={sha512:f7fbba6e0636f890e56fbbf3283e524c6fa3204ae298382d624741d0dc6638326e282c41be5e4254d8820772c5518a2c5a8c0c7f7eda19594a7eb539453e1ed7},
+}
+";
+        let r = read_all(biblatex_src.as_bytes(), &digests).unwrap();
+        assert!(r[0].publish_date().is_none());
+    }
+
+    #[test]
+    fn test_editors() {
+        let biblatex_src = "@book{
+    foo,
+    title={bar},
+    author={Guybrush Threepwood},
+    editor={Knuth and Lamport, Leslie and {Bell and Howell}},
+    note={sha512:f7fbba6e0636f890e56fbbf3283e524c6fa3204ae298382d624741d0dc6638326e282c41be5e4254d8820772c5518a2c5a8c0c7f7eda19594a7eb539453e1ed7},
+}
+";
+        let digests = vec!();
+        let r = read_all(biblatex_src.as_bytes(), &digests).unwrap();
+        assert_eq!(r[0].contributor().unwrap(), "Knuth and Lamport, Leslie and {Bell and Howell}");
+        assert_eq!(r[0].contributors(), vec!(
+            Creator::person("", "Knuth"),
+            Creator::person("Leslie", "Lamport"),
+            Creator::organisation("Bell and Howell"),
+        ));
+
+        let mut v: Vec<u8> = vec!();
+        write_all(&r, &mut v).unwrap();
+        let r_check = read_all(v.as_slice(), &digests).unwrap();
+        assert_eq!(r_check[0].contributors(), r[0].contributors());
+    }
+
+    #[test]
+    fn test_text_fields() {
+        let f = File::open("testdata/meta.biblatex").unwrap();
+        let digests = vec!();
+        let r = read_all(&f, &digests).unwrap();
+
+        let m = &r[1];
+        assert_eq!(m.creators(), vec!(Creator::organisation("Government of Kenya")));
+        assert_eq!(r[0].creators(), vec!(Creator::person("Satoshi", "Nakamoto")));
+        assert_eq!(m.publisher().unwrap(), "The Government Printer, Nairobi");
+        assert_eq!(m.publish_date().unwrap(), (0, 11, 2019));
+        assert_eq!(m.identifier().unwrap(), "http://kenyalaw.org/kl/fileadmin/pdfdownloads/Acts/2019/TheDataProtectionAct__No24of2019.pdf");
+        assert_eq!(m.part_of().unwrap(), "Kenya Gazette Supplement, ACTS 2019");
+        assert_eq!(m.extent().unwrap(), "pp. 901--948");
+        assert_eq!(r[0].publisher(), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let digests = vec!(digest::RecordDigest::Sha256(vec!(0x2a; 32)));
+        let e = read_all("@article{foo,\n    author={bar},\n}\n".as_bytes(), &digests).unwrap_err();
+        assert!(matches!(e, Error::Parse{format: Format::Biblatex, ..}));
+        let e = read_all("@article{foo,\n    title={bar},\n}\n".as_bytes(), &vec!()).unwrap_err();
+        assert_eq!(e.to_string(), "invalid biblatex data: no digests found in entry foo");
+
+        let r = read_all("@article{foo,\n    title={bar},\n    language={xx-},\n}\n".as_bytes(), &digests).unwrap();
+        assert_eq!(r[0].language(), None);
     }
 }
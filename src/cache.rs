@@ -0,0 +1,435 @@
+//! Persistent cache of file digests.
+//!
+//! Digests are keyed by the identity of the file version they were calculated for, that is
+//! device, inode, size and modification time. A file that has not changed since its digests were
+//! calculated does not need to be read again.
+use std::collections::HashMap;
+use std::fs::{
+    File,
+    create_dir_all,
+    rename,
+};
+use std::io::{
+    BufRead,
+    BufReader,
+    BufWriter,
+    Write,
+};
+use std::ffi::OsString;
+use std::os::unix::ffi::{
+    OsStrExt,
+    OsStringExt,
+};
+use std::os::unix::fs::MetadataExt;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::sync::Mutex;
+
+use log::{
+    debug,
+};
+
+use crate::digest::{
+    DigestType,
+    RecordDigest,
+    digests_for,
+    from_urn,
+};
+use crate::error::Error;
+
+/// Identity of a version of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileKey {
+    /// Device the file is on.
+    pub dev: u64,
+    /// Inode of the file.
+    pub ino: u64,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// Modification time of the file, in nanoseconds since the epoch.
+    pub mtime_ns: i128,
+}
+
+impl FileKey {
+    /// Returns the identity of the current version of the file at `p`, following symbolic links.
+    pub fn from_path(p: &Path) -> Result<FileKey, std::io::Error> {
+        let st = p.metadata()?;
+        Ok(FileKey{
+            dev: st.dev(),
+            ino: st.ino(),
+            size: st.size(),
+            mtime_ns: st.mtime() as i128 * 1_000_000_000 + st.mtime_nsec() as i128,
+        })
+    }
+}
+
+struct CacheEntry {
+    /// Path of the file when the digests were calculated.
+    path: PathBuf,
+    digests: Vec<RecordDigest>,
+}
+
+/// Digest cache backed by a file.
+///
+/// Each line of the file is a tab-separated entry of device, inode, size, modification time,
+/// space-separated digest URNs and file path. Backslashes, control characters and bytes outside
+/// of ASCII in the file path are escaped with a backslash, e.g. `\n` and `\xff`.
+///
+/// Changes are only written to the file by [DigestCache::save](DigestCache::save).
+pub struct DigestCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<FileKey, CacheEntry>>,
+    dirty: Mutex<bool>,
+}
+
+/// Returns the path as a single line of ASCII text.
+///
+/// Backslash, newline, carriage return and tab are escaped as `\\`, `\n`, `\r` and `\t`. Other
+/// control characters and bytes outside of ASCII are escaped as `\xNN`.
+fn escape_path(p: &Path) -> String {
+    let mut s = String::new();
+    for b in p.as_os_str().as_bytes() {
+        match b {
+            b'\\' => {
+                s.push_str("\\\\");
+            },
+            b'\n' => {
+                s.push_str("\\n");
+            },
+            b'\r' => {
+                s.push_str("\\r");
+            },
+            b'\t' => {
+                s.push_str("\\t");
+            },
+            0x20..=0x7e => {
+                s.push(*b as char);
+            },
+            _ => {
+                s.push_str(format!("\\x{:02x}", b).as_str());
+            },
+        };
+    }
+    s
+}
+
+/// Reverses [escape_path](escape_path). Returns `None` if the text contains an invalid escape.
+fn unescape_path(s: &str) -> Option<PathBuf> {
+    let mut r: Vec<u8> = vec!();
+    let b = s.as_bytes();
+    let mut i: usize = 0;
+    while i < b.len() {
+        if b[i] != b'\\' {
+            r.push(b[i]);
+            i += 1;
+            continue;
+        }
+        match b.get(i + 1)? {
+            b'\\' => {
+                r.push(b'\\');
+            },
+            b'n' => {
+                r.push(b'\n');
+            },
+            b'r' => {
+                r.push(b'\r');
+            },
+            b't' => {
+                r.push(b'\t');
+            },
+            b'x' => {
+                let v = s.get(i + 2..i + 4)?;
+                if !v.bytes().all(|c| c.is_ascii_hexdigit()) {
+                    return None;
+                }
+                r.push(u8::from_str_radix(v, 16).ok()?);
+                i += 2;
+            },
+            _ => {
+                return None;
+            },
+        };
+        i += 2;
+    }
+    Some(PathBuf::from(OsString::from_vec(r)))
+}
+
+fn parse_line(line: &str) -> Option<(FileKey, CacheEntry)> {
+    let v: Vec<&str> = line.splitn(6, "\t").collect();
+    if v.len() != 6 {
+        return None;
+    }
+    let key = FileKey{
+        dev: v[0].parse().ok()?,
+        ino: v[1].parse().ok()?,
+        size: v[2].parse().ok()?,
+        mtime_ns: v[3].parse().ok()?,
+    };
+    let mut digests: Vec<RecordDigest> = vec!();
+    for urn in v[4].split_whitespace() {
+        digests.push(from_urn(urn).ok()?);
+    }
+    Some((key, CacheEntry{
+        path: unescape_path(v[5])?,
+        digests,
+    }))
+}
+
+impl DigestCache {
+    /// Open the cache in the file at `p`.
+    ///
+    /// The cache is empty if the file does not exist. Invalid entries in the file are skipped.
+    pub fn open(p: &Path) -> Result<DigestCache, Error> {
+        let mut entries: HashMap<FileKey, CacheEntry> = HashMap::new();
+        match File::open(p) {
+            Ok(f) => {
+                for line in BufReader::new(f).lines() {
+                    let line = line?;
+                    match parse_line(&line) {
+                        Some((k, v)) => {
+                            entries.insert(k, v);
+                        },
+                        None => {
+                            debug!("skipping invalid digest cache entry: {}", line);
+                        },
+                    };
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => {
+                return Err(Error::Io(e));
+            },
+        };
+        debug!("loaded {} digest cache entries from {:?}", entries.len(), p);
+        Ok(DigestCache{
+            path: p.to_path_buf(),
+            entries: Mutex::new(entries),
+            dirty: Mutex::new(false),
+        })
+    }
+
+    /// Returns the location of the cache file.
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// Returns the number of file versions in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Returns true if the cache has no file versions.
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().unwrap().is_empty()
+    }
+
+    /// Returns the cached digest of the given type for the file version, if any.
+    pub fn get(&self, key: &FileKey, digest_type: DigestType) -> Option<RecordDigest> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(key)
+            .and_then(|v| {
+                v.digests.iter()
+                    .find(|d| {
+                        d.digest_type() == Some(digest_type)
+                    })
+                    .cloned()
+            })
+    }
+
+    /// Add digests for the file version at `filepath` to the cache.
+    pub fn insert(&self, key: FileKey, filepath: &Path, digests: &Vec<RecordDigest>) {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry(key).or_insert(CacheEntry{
+            path: filepath.to_path_buf(),
+            digests: vec!(),
+        });
+        entry.path = filepath.to_path_buf();
+        for v in digests {
+            if v.digest_type().is_some() && !entry.digests.contains(v) {
+                entry.digests.push(v.clone());
+            }
+        }
+        *self.dirty.lock().unwrap() = true;
+    }
+
+    /// Calculates digests of all given types for a file, using cached digests where available.
+    ///
+    /// Digests not in the cache are calculated in a single pass over the file, and added to the
+    /// cache. The digests are returned in the order of the given digest types, without duplicates.
+    ///
+    /// If the file changes while the digests are calculated, all of them are calculated again
+    /// from the changed file, and none are cached.
+    pub fn digests_from_path(&self, filepath: &Path, digest_types: &Vec<DigestType>) -> Result<Vec<RecordDigest>, Error> {
+        let key = FileKey::from_path(filepath)?;
+        let mut missing: Vec<DigestType> = vec!();
+        for v in digest_types {
+            if !missing.contains(v) && self.get(&key, *v).is_none() {
+                missing.push(*v);
+            }
+        }
+        if !missing.is_empty() {
+            debug!("digest cache miss for {:?} on {:?}", filepath, missing);
+            let f = File::open(filepath)?;
+            let r = digests_for(f, &missing)?;
+            if FileKey::from_path(filepath)? == key {
+                self.insert(key, filepath, &r);
+            } else {
+                debug!("file {:?} changed while calculating digests, not caching", filepath);
+                let f = File::open(filepath)?;
+                return Ok(digests_for(f, digest_types)?);
+            }
+        }
+
+        let mut r: Vec<RecordDigest> = vec!();
+        for v in digest_types {
+            let d = self.get(&key, *v).unwrap();
+            if !r.contains(&d) {
+                r.push(d);
+            }
+        }
+        Ok(r)
+    }
+
+    /// Remove entries for files which no longer exist, or have changed since their digests were
+    /// calculated.
+    ///
+    /// Returns the number of entries removed.
+    pub fn prune(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let c = entries.len();
+        entries.retain(|k, v| {
+            match FileKey::from_path(&v.path) {
+                Ok(current) => {
+                    current == *k
+                },
+                Err(_) => {
+                    false
+                },
+            }
+        });
+        let removed = c - entries.len();
+        if removed > 0 {
+            *self.dirty.lock().unwrap() = true;
+        }
+        debug!("pruned {} digest cache entries", removed);
+        removed
+    }
+
+    /// Write the cache to its file, if it has changed.
+    pub fn save(&self) -> Result<(), Error> {
+        let mut dirty = self.dirty.lock().unwrap();
+        if !*dirty {
+            return Ok(());
+        }
+        if let Some(v) = self.path.parent() {
+            if !v.as_os_str().is_empty() {
+                create_dir_all(v)?;
+            }
+        }
+        let mut p_tmp = self.path.clone().into_os_string();
+        p_tmp.push(".tmp");
+        let entries = self.entries.lock().unwrap();
+        let mut w = BufWriter::new(File::create(&p_tmp)?);
+        for (k, v) in entries.iter() {
+            let urns: Vec<String> = v.digests.iter()
+                .map(|d| {
+                    d.urn()
+                })
+                .collect();
+            writeln!(w, "{}\t{}\t{}\t{}\t{}\t{}", k.dev, k.ino, k.size, k.mtime_ns, urns.join(" "), escape_path(&v.path))?;
+        }
+        w.flush()?;
+        rename(&p_tmp, &self.path)?;
+        *dirty = false;
+        debug!("saved {} digest cache entries to {:?}", entries.len(), &self.path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::write;
+    use tempfile::tempdir;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use super::{
+        DigestCache,
+        FileKey,
+        escape_path,
+        unescape_path,
+    };
+    use crate::digest::DigestType;
+
+    #[test]
+    fn test_cache_digests() {
+        let dir = tempdir().unwrap();
+        let fp = dir.path().join("foo");
+        write(&fp, "foo").unwrap();
+        let cache_path = dir.path().join("cache").join("digests");
+
+        let cache = DigestCache::open(&cache_path).unwrap();
+        let r = cache.digests_from_path(&fp, &vec!(DigestType::Sha256)).unwrap();
+        assert_eq!(r[0].urn(), "sha256:2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae");
+        let r = cache.digests_from_path(&fp, &vec!(DigestType::Sha512, DigestType::Sha256)).unwrap();
+        assert_eq!(r.len(), 2);
+        assert_eq!(r[0].digest_type(), Some(DigestType::Sha512));
+        assert_eq!(cache.len(), 1);
+        cache.save().unwrap();
+
+        let cache = DigestCache::open(&cache_path).unwrap();
+        let key = FileKey::from_path(&fp).unwrap();
+        assert_eq!(cache.get(&key, DigestType::Sha512), Some(r[0].clone()));
+        assert_eq!(cache.get(&key, DigestType::Sha256), Some(r[1].clone()));
+    }
+
+    #[test]
+    fn test_cache_prune() {
+        let dir = tempdir().unwrap();
+        let fp_foo = dir.path().join("foo");
+        let fp_bar = dir.path().join("bar");
+        write(&fp_foo, "foo").unwrap();
+        write(&fp_bar, "bar").unwrap();
+
+        let cache = DigestCache::open(&dir.path().join("digests")).unwrap();
+        cache.digests_from_path(&fp_foo, &vec!(DigestType::Sha512)).unwrap();
+        cache.digests_from_path(&fp_bar, &vec!(DigestType::Sha512)).unwrap();
+        assert_eq!(cache.prune(), 0);
+
+        std::fs::remove_file(&fp_bar).unwrap();
+        assert_eq!(cache.prune(), 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_cache_escape_path() {
+        let p = Path::new(OsStr::from_bytes(b"foo\nbar\\baz\t\xff/xyzzy.txt"));
+        let s = escape_path(p);
+        assert_eq!(s, "foo\\nbar\\\\baz\\t\\xff/xyzzy.txt");
+        assert_eq!(unescape_path(&s).unwrap(), p);
+        assert_eq!(unescape_path("foo\\q"), None);
+        assert_eq!(unescape_path("foo\\x4"), None);
+        assert_eq!(unescape_path("foo\\x+f"), None);
+        assert_eq!(unescape_path("foo\\"), None);
+    }
+
+    #[test]
+    fn test_cache_newline_path() {
+        let dir = tempdir().unwrap();
+        let fp = dir.path().join("foo\n1\t2\t3\t4\tsha256:00\tbar");
+        write(&fp, "foo").unwrap();
+        let cache_path = dir.path().join("digests");
+
+        let cache = DigestCache::open(&cache_path).unwrap();
+        let r = cache.digests_from_path(&fp, &vec!(DigestType::Sha256)).unwrap();
+        cache.save().unwrap();
+
+        let cache = DigestCache::open(&cache_path).unwrap();
+        assert_eq!(cache.len(), 1);
+        let key = FileKey::from_path(&fp).unwrap();
+        assert_eq!(cache.get(&key, DigestType::Sha256), Some(r[0].clone()));
+        assert_eq!(cache.prune(), 0);
+    }
+}
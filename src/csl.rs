@@ -0,0 +1,636 @@
+//! Import from and export to [CSL-JSON](https://citeproc-js.readthedocs.io/en/latest/csl-json/markup.html),
+//! as used by e.g. Zotero and pandoc.
+//!
+//! Digests of a record are carried as whitespace-separated URNs in the `note` field, the same as
+//! the `note` field of [biblatex](crate::biblatex) entries. When reading, URNs in a `digests`
+//! array of the `custom` field are used as well.
+use std::io::{
+    Read,
+    Write,
+};
+
+use biblatex::EntryType;
+use serde_json::{
+    json,
+    Map,
+    Value,
+};
+use log::{
+    debug,
+    warn,
+};
+
+use crate::meta::{
+    MetaData,
+    PublishDate,
+};
+use crate::dc::Creator;
+use crate::error::{
+    Error,
+    Format,
+};
+use crate::digest::RecordDigest;
+use crate::digest::urn as digest_urn;
+
+/// Returns the entry type for a CSL item type.
+pub fn entry_type_from_csl(s: &str) -> EntryType {
+    match s {
+        "article" | "article-journal" | "article-magazine" | "article-newspaper" => {
+            EntryType::Article
+        },
+        "book" => {
+            EntryType::Book
+        },
+        "chapter" => {
+            EntryType::InCollection
+        },
+        "paper-conference" => {
+            EntryType::InProceedings
+        },
+        "entry" | "entry-dictionary" | "entry-encyclopedia" => {
+            EntryType::InReference
+        },
+        "report" => {
+            EntryType::Report
+        },
+        "thesis" => {
+            EntryType::Thesis
+        },
+        "webpage" | "post" | "post-weblog" => {
+            EntryType::Online
+        },
+        "software" => {
+            EntryType::Software
+        },
+        "dataset" => {
+            EntryType::Dataset
+        },
+        "patent" => {
+            EntryType::Patent
+        },
+        "periodical" => {
+            EntryType::Periodical
+        },
+        "manuscript" => {
+            EntryType::Unpublished
+        },
+        "pamphlet" => {
+            EntryType::Booklet
+        },
+        _ => {
+            EntryType::Misc
+        },
+    }
+}
+
+/// Returns the CSL item type for an entry type.
+pub fn entry_type_to_csl(typ: &EntryType) -> &'static str {
+    match typ {
+        EntryType::Article => {
+            "article-journal"
+        },
+        EntryType::Book | EntryType::MvBook | EntryType::Collection | EntryType::MvCollection | EntryType::Proceedings | EntryType::MvProceedings | EntryType::Reference | EntryType::MvReference => {
+            "book"
+        },
+        EntryType::InBook | EntryType::BookInBook | EntryType::SuppBook | EntryType::InCollection | EntryType::SuppCollection => {
+            "chapter"
+        },
+        EntryType::InProceedings => {
+            "paper-conference"
+        },
+        EntryType::InReference => {
+            "entry-encyclopedia"
+        },
+        EntryType::Report | EntryType::TechReport => {
+            "report"
+        },
+        EntryType::Thesis | EntryType::MastersThesis | EntryType::PhdThesis => {
+            "thesis"
+        },
+        EntryType::Online => {
+            "webpage"
+        },
+        EntryType::Software => {
+            "software"
+        },
+        EntryType::Dataset => {
+            "dataset"
+        },
+        EntryType::Patent => {
+            "patent"
+        },
+        EntryType::Periodical | EntryType::SuppPeriodical => {
+            "periodical"
+        },
+        EntryType::Unpublished => {
+            "manuscript"
+        },
+        EntryType::Booklet => {
+            "pamphlet"
+        },
+        _ => {
+            "document"
+        },
+    }
+}
+
+/// Returns the value of a string or number field of the item.
+fn field_value(item: &Map<String, Value>, key: &str) -> Option<String> {
+    match item.get(key) {
+        Some(Value::String(v)) => {
+            let v = v.trim();
+            if v.is_empty() {
+                return None;
+            }
+            Some(String::from(v))
+        },
+        Some(Value::Number(v)) => {
+            Some(v.to_string())
+        },
+        _ => {
+            None
+        },
+    }
+}
+
+/// Parse a CSL name variable, e.g. `author`.
+///
+/// Names given as `literal` are organisations.
+fn parse_names(item: &Map<String, Value>, key: &str) -> Vec<Creator> {
+    let names = match item.get(key) {
+        Some(Value::Array(v)) => {
+            v
+        },
+        _ => {
+            return vec!();
+        },
+    };
+    let mut r: Vec<Creator> = vec!();
+    for v in names {
+        let name = match v.as_object() {
+            Some(v) => {
+                v
+            },
+            None => {
+                debug!("skipping invalid name in {}: {}", key, v);
+                continue;
+            },
+        };
+        if let Some(v) = field_value(name, "literal") {
+            r.push(Creator::organisation(v.as_str()));
+            continue;
+        }
+        let prefix = field_value(name, "non-dropping-particle")
+            .or(field_value(name, "dropping-particle"))
+            .unwrap_or_default();
+        r.push(Creator::Person{
+            given: field_value(name, "given").unwrap_or_default(),
+            family: field_value(name, "family").unwrap_or_default(),
+            prefix,
+            suffix: field_value(name, "suffix").unwrap_or_default(),
+        });
+    }
+    r
+}
+
+fn names_value(creators: &[Creator]) -> Value {
+    let r: Vec<Value> = creators.iter()
+        .map(|v| {
+            match v {
+                Creator::Person{given, family, prefix, suffix} => {
+                    let mut name = Map::new();
+                    name.insert(String::from("family"), json!(family));
+                    if !given.is_empty() {
+                        name.insert(String::from("given"), json!(given));
+                    }
+                    if !prefix.is_empty() {
+                        name.insert(String::from("non-dropping-particle"), json!(prefix));
+                    }
+                    if !suffix.is_empty() {
+                        name.insert(String::from("suffix"), json!(suffix));
+                    }
+                    Value::Object(name)
+                },
+                Creator::Organisation(name) => {
+                    json!({"literal": name})
+                },
+            }
+        })
+        .collect();
+    Value::Array(r)
+}
+
+/// Returns the publication date of the item from the `issued` field.
+///
+/// For date ranges, the start of the range is used.
+fn parse_publish_date(item: &Map<String, Value>) -> Option<PublishDate> {
+    let issued = match item.get("issued") {
+        Some(Value::Object(v)) => {
+            v
+        },
+        _ => {
+            return None;
+        },
+    };
+    match issued.get("date-parts").and_then(|v| v.get(0)).and_then(|v| v.as_array()) {
+        Some(parts) => {
+            let mut r: Vec<u32> = vec!();
+            for v in parts.iter().take(3) {
+                let n = match v {
+                    Value::Number(n) => {
+                        n.as_u64().map(|n| n as u32)
+                    },
+                    Value::String(s) => {
+                        s.trim().parse::<u32>().ok()
+                    },
+                    _ => {
+                        None
+                    },
+                };
+                match n {
+                    Some(n) => {
+                        r.push(n);
+                    },
+                    None => {
+                        debug!("invalid date part {} in item", v);
+                        return None;
+                    },
+                };
+            }
+            r.resize(3, 0);
+            if r[1] > 12 || r[2] > 31 {
+                return None;
+            }
+            let date = (r[2] as u8, r[1] as u8, r[0]);
+            if !crate::meta::publish_date_valid(&date) {
+                debug!("invalid date {:?} in item", date);
+                return None;
+            }
+            Some(date)
+        },
+        None => {
+            let raw = issued.get("raw").and_then(|v| v.as_str())?;
+            crate::meta::publish_date_from_str(raw).ok()
+        },
+    }
+}
+
+/// Returns the identifier of the item, in order of preference from `DOI`, `ISBN`, `ISSN` and `URL`.
+fn parse_identifier(item: &Map<String, Value>) -> Option<String> {
+    if let Some(v) = field_value(item, "DOI") {
+        return Some(format!("doi:{}", v));
+    }
+    if let Some(v) = field_value(item, "ISBN") {
+        return Some(format!("urn:isbn:{}", v));
+    }
+    if let Some(v) = field_value(item, "ISSN") {
+        return Some(format!("urn:issn:{}", v));
+    }
+    field_value(item, "URL")
+}
+
+/// Parse digest URNs from the `note` field and the `digests` array of the `custom` field.
+///
+/// Words in the `note` field that are not valid digest URNs are ignored.
+fn parse_digests(item: &Map<String, Value>) -> Vec<RecordDigest> {
+    let mut r: Vec<RecordDigest> = vec!();
+    let mut candidates: Vec<String> = vec!();
+    if let Some(v) = item.get("custom").and_then(|v| v.get("digests")).and_then(|v| v.as_array()) {
+        for urn in v {
+            if let Some(s) = urn.as_str() {
+                candidates.push(String::from(s));
+            }
+        }
+    }
+    if let Some(v) = field_value(item, "note") {
+        for s in v.split_whitespace() {
+            candidates.push(String::from(s));
+        }
+    }
+    for v in candidates {
+        match digest_urn::parse(&v) {
+            Ok(RecordDigest::Empty) => {},
+            Ok(digest) => {
+                if !r.contains(&digest) {
+                    r.push(digest);
+                }
+            },
+            Err(e) => {
+                debug!("skipping non-digest note content {:?}: {:?}", v, e);
+            },
+        };
+    }
+    r
+}
+
+/// Read one or more metadata entries from the CSL-JSON source.
+///
+/// The source may be an array of items, or a single item.
+///
+/// Will return [Error::Parse](Error::Parse) if the source is not valid CSL-JSON, or if any of the
+/// items have no title or no digest.
+///
+/// # Arguments
+///
+/// * `r` - reader implementation providing the source.
+/// * `digests` - digests to add to the record, in which case the source must have a single item.
+pub fn read_all(r: impl Read, digests: &Vec<RecordDigest>) -> Result<Vec<MetaData>, Error> {
+    let src: Value = match serde_json::from_reader(r) {
+        Ok(v) => {
+            v
+        },
+        Err(e) => {
+            return Err(Error::parse(Format::Csl, &e.to_string()));
+        },
+    };
+    let items: Vec<Value> = match src {
+        Value::Array(v) => {
+            v
+        },
+        Value::Object(_) => {
+            vec!(src)
+        },
+        _ => {
+            return Err(Error::parse(Format::Csl, "not an item or array of items"));
+        },
+    };
+
+    if items.len() > 1 && !digests.is_empty() {
+        return Err(Error::parse(Format::Csl, "more than one csl item parsed while static digest provided"));
+    }
+
+    let mut rr: Vec<MetaData> = vec!();
+    for v in items.iter() {
+        let item = match v.as_object() {
+            Some(v) => {
+                v
+            },
+            None => {
+                return Err(Error::parse(Format::Csl, "item is not an object"));
+            },
+        };
+        let id = field_value(item, "id").unwrap_or_default();
+
+        let title = match field_value(item, "title") {
+            Some(v) => {
+                v
+            },
+            None => {
+                return Err(Error::parse(Format::Csl, &format!("missing title in item {}", id)));
+            },
+        };
+
+        let mut use_digests = parse_digests(item);
+        for v in digests {
+            use_digests.push(v.clone());
+        }
+        if use_digests.is_empty() {
+            return Err(Error::parse(Format::Csl, &format!("no digests found in item {}", id)));
+        }
+
+        let typ = entry_type_from_csl(field_value(item, "type").unwrap_or_default().as_str());
+        let mut m = MetaData::new(title.as_str(), "", typ, RecordDigest::Empty, None);
+        m.set_creators(parse_names(item, "author"));
+        for dd in use_digests.into_iter() {
+            m.add_digest(dd);
+        }
+
+        if let Some(v) = field_value(item, "keyword") {
+            for k in v.split([',', ';']) {
+                m.add_subject(k);
+            }
+        }
+
+        if let Some(v) = field_value(item, "language") {
+            if let Err(e) = m.set_language(v.as_str()) {
+                warn!("skipping invalid language in item {}: {}", id, e);
+            }
+        }
+
+        m.set_contributors(&parse_names(item, "editor"));
+
+        if let Some(v) = parse_publish_date(item) {
+            m.set_publish_date(v);
+        }
+
+        if let Some(v) = parse_identifier(item) {
+            m.set_identifier(v.as_str());
+        }
+
+        for (k, set) in [
+            ("publisher", MetaData::set_publisher as fn(&mut MetaData, &str)),
+            ("abstract", MetaData::set_description),
+            ("license", MetaData::set_rights),
+            ("container-title", MetaData::set_part_of),
+            ("source", MetaData::set_source),
+        ] {
+            if let Some(v) = field_value(item, k) {
+                set(&mut m, v.as_str());
+            }
+        }
+
+        match field_value(item, "number-of-pages") {
+            Some(v) => {
+                m.set_extent(format!("{} pages", v).as_str());
+            },
+            None => {
+                if let Some(v) = field_value(item, "page") {
+                    m.set_extent(format!("pp. {}", v).as_str());
+                }
+            },
+        };
+
+        debug!("read metadata {:?}", &m);
+        rr.push(m);
+    }
+    Ok(rr)
+}
+
+/// Generate a CSL-JSON item from a metadata record.
+///
+/// The item id is the hex digest of the record, and the URNs of all digests of the record are
+/// stored in the `note` field, so that the output can be imported again with
+/// [read_all](read_all).
+pub fn to_item(entry: &MetaData) -> Value {
+    let mut item = Map::new();
+    item.insert(String::from("id"), json!(entry.fingerprint()));
+    item.insert(String::from("type"), json!(entry_type_to_csl(&entry.typ())));
+    item.insert(String::from("title"), json!(entry.title()));
+    let creators = entry.creators();
+    if !creators.is_empty() {
+        item.insert(String::from("author"), names_value(&creators));
+    }
+
+    let editors = entry.contributors();
+    if !editors.is_empty() {
+        item.insert(String::from("editor"), names_value(&editors));
+    }
+
+    if let Some(v) = entry.subject() {
+        item.insert(String::from("keyword"), json!(v));
+    }
+
+    if let Some(v) = entry.language() {
+        item.insert(String::from("language"), json!(v.to_string()));
+    }
+
+    if let Some((d, m, y)) = entry.publish_date() {
+        let mut parts: Vec<u32> = vec!(y);
+        if m > 0 {
+            parts.push(m as u32);
+            if d > 0 {
+                parts.push(d as u32);
+            }
+        }
+        item.insert(String::from("issued"), json!({"date-parts": [parts]}));
+    }
+
+    if let Some(v) = entry.identifier() {
+        let (k, id) = if let Some(id) = v.strip_prefix("doi:") {
+            ("DOI", id)
+        } else if let Some(id) = v.strip_prefix("urn:isbn:") {
+            ("ISBN", id)
+        } else if let Some(id) = v.strip_prefix("urn:issn:") {
+            ("ISSN", id)
+        } else {
+            ("URL", v.as_str())
+        };
+        item.insert(String::from(k), json!(id));
+    }
+
+    for (k, get) in [
+        ("publisher", MetaData::publisher as fn(&MetaData) -> Option<String>),
+        ("abstract", MetaData::description),
+        ("license", MetaData::rights),
+        ("container-title", MetaData::part_of),
+        ("source", MetaData::source),
+    ] {
+        if let Some(v) = get(entry) {
+            item.insert(String::from(k), json!(v));
+        }
+    }
+
+    if let Some(v) = entry.extent() {
+        if let Some(n) = v.strip_suffix(" pages") {
+            item.insert(String::from("number-of-pages"), json!(n));
+        } else if let Some(n) = v.strip_prefix("pp. ") {
+            item.insert(String::from("page"), json!(n));
+        }
+    }
+
+    let urns: Vec<String> = entry.digests().iter()
+        .map(|v| {
+            v.urn()
+        })
+        .collect();
+    item.insert(String::from("note"), json!(urns.join(" ")));
+
+    Value::Object(item)
+}
+
+/// Write one or more metadata entries as a CSL-JSON array.
+///
+/// On success, returns the total number of bytes written.
+///
+/// # Arguments
+///
+/// * `entries` - metadata to write.
+/// * `w` - writer implementation providing the destination.
+pub fn write_all(entries: &[MetaData], mut w: impl Write) -> Result<usize, Error> {
+    let items: Vec<Value> = entries.iter()
+        .map(to_item)
+        .collect();
+    let s = match serde_json::to_string_pretty(&items) {
+        Ok(v) => {
+            v
+        },
+        Err(e) => {
+            return Err(Error::parse(Format::Csl, &e.to_string()));
+        },
+    };
+    w.write_all(s.as_bytes())?;
+    w.write_all(b"\n")?;
+    debug!("wrote {} csl items", entries.len());
+    Ok(s.len() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        read_all,
+        write_all,
+    };
+    use std::fs::File;
+    use biblatex::EntryType;
+    use crate::digest;
+    use crate::dc::Creator;
+    use crate::error::{
+        Error,
+        Format,
+    };
+
+    #[test]
+    fn test_csl_read() {
+        let f = File::open("testdata/meta.csl.json").unwrap();
+        let r = read_all(&f, &vec!()).unwrap();
+        assert_eq!(r.len(), 2);
+
+        let m = &r[0];
+        assert_eq!(m.title(), "Bitcoin: A Peer-to-Peer Electronic Cash System");
+        assert_eq!(m.creators(), vec!(Creator::person("Satoshi", "Nakamoto")));
+        assert_eq!(m.typ(), EntryType::Article);
+        assert_eq!(m.subjects(), vec!("bitcoin", "cryptocurrency"));
+        assert_eq!(m.language().unwrap(), "en");
+        assert_eq!(m.publish_date().unwrap(), (31, 10, 2008));
+        assert_eq!(m.urn(), "sha512:2ac531ee521cf93f8419c2018f770fbb42c65396178e079a416e7038d3f9ab9fc2c35c4d838bc8b5dd68f4c13759fe9cdf90a46528412fefe1294cb26beabf4e");
+
+        let m = &r[1];
+        assert_eq!(m.creators(), vec!(Creator::organisation("Government of Kenya")));
+        assert_eq!(m.typ(), EntryType::Report);
+        assert_eq!(m.digests().len(), 2);
+        assert_eq!(m.publish_date().unwrap(), (0, 11, 2019));
+        assert_eq!(m.identifier().unwrap(), "doi:10.1000/182");
+        assert_eq!(m.part_of().unwrap(), "Kenya Gazette Supplement, ACTS 2019");
+        assert_eq!(m.extent().unwrap(), "pp. 901-948");
+    }
+
+    #[test]
+    fn test_csl_roundtrip() {
+        let f = File::open("testdata/meta.csl.json").unwrap();
+        let r = read_all(&f, &vec!()).unwrap();
+
+        let mut v: Vec<u8> = vec!();
+        write_all(&r, &mut v).unwrap();
+        let r_check = read_all(v.as_slice(), &vec!()).unwrap();
+
+        assert_eq!(r.len(), r_check.len());
+        for (m, m_check) in r.iter().zip(r_check.iter()) {
+            assert_eq!(m.digests(), m_check.digests());
+            assert_eq!(m.title(), m_check.title());
+            assert_eq!(m.creators(), m_check.creators());
+            assert_eq!(m.typ(), m_check.typ());
+            assert_eq!(m.subject(), m_check.subject());
+            assert_eq!(m.language(), m_check.language());
+            assert_eq!(m.publisher(), m_check.publisher());
+            assert_eq!(m.publish_date(), m_check.publish_date());
+            assert_eq!(m.identifier(), m_check.identifier());
+            assert_eq!(m.part_of(), m_check.part_of());
+            assert_eq!(m.extent(), m_check.extent());
+        }
+    }
+
+    #[test]
+    fn test_csl_digests() {
+        let d = digest::RecordDigest::Sha256(vec!(0x2a; 32));
+        let src = "{\"id\": \"foo\", \"type\": \"book\", \"title\": \"bar\", \"custom\": {\"digests\": [\"urn:sha256:2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b2b\"]}}";
+        let r = read_all(src.as_bytes(), &vec!(d.clone())).unwrap();
+        assert_eq!(r.len(), 1);
+        assert_eq!(r[0].typ(), EntryType::Book);
+        assert_eq!(r[0].digests(), vec!(digest::RecordDigest::Sha256(vec!(0x2b; 32)), d));
+
+        let e = read_all("[{\"id\": \"foo\", \"title\": \"bar\"}]".as_bytes(), &vec!()).unwrap_err();
+        assert_eq!(e.to_string(), "invalid csl-json data: no digests found in item foo");
+        let e = read_all("@article{foo,}".as_bytes(), &vec!()).unwrap_err();
+        assert!(matches!(e, Error::Parse{format: Format::Csl, ..}));
+    }
+}
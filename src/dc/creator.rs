@@ -0,0 +1,547 @@
+use std::fmt;
+
+use biblatex::{
+    Chunk,
+    Person,
+    Spanned,
+};
+
+/// Represents a creator of a work, e.g. an author or an editor.
+///
+/// Creators are represented as text in [BibTeX name format](https://www.bibtex.org/Format/),
+/// where the name of an organisation is enclosed in curly braces:
+///
+/// * `Nakamoto, Satoshi`
+/// * `von Neumann, John`
+/// * `{Government of Kenya}`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Creator {
+    /// A person, by the parts of the name.
+    Person {
+        /// Given name / first name.
+        given: String,
+        /// Family name / surname / last name.
+        family: String,
+        /// Particle placed between given name and family name, e.g. "van".
+        prefix: String,
+        /// Suffix placed after the family name, e.g. "Jr.".
+        suffix: String,
+    },
+    /// An organisation, by its full name.
+    Organisation(String),
+}
+
+impl Creator {
+    /// Create a person from given name and family name.
+    pub fn person(given: &str, family: &str) -> Creator {
+        Creator::Person{
+            given: String::from(given),
+            family: String::from(family),
+            prefix: String::new(),
+            suffix: String::new(),
+        }
+    }
+
+    /// Create an organisation from its full name.
+    pub fn organisation(name: &str) -> Creator {
+        Creator::Organisation(String::from(name))
+    }
+
+    /// Parse a creator from a name in BibTeX name format.
+    ///
+    /// A name enclosed in curly braces is an organisation. Other names are persons, in which
+    /// words enclosed in curly braces are not split into name parts, e.g. `{Le Guin}, Ursula K.`.
+    /// A name followed only by a comma, as [Creator::to_bibtex](Creator::to_bibtex) writes a
+    /// family name of more than one word without given name, e.g. `{Van Gogh},`, is taken as the
+    /// family name.
+    pub fn parse(s: &str) -> Creator {
+        let s = s.trim();
+        if is_braced(s) {
+            return Creator::organisation(&s[1..s.len()-1]);
+        }
+        match s.strip_suffix(',') {
+            Some(last) if !last.contains(',') => {
+                let last = last.trim();
+                if is_braced(last) {
+                    return Creator::person("", &last[1..last.len()-1]);
+                }
+                if !last.contains('{') && !last.starts_with(|c: char| c.is_lowercase()) {
+                    return Creator::person("", last);
+                }
+                let p = Person::parse(&bibtex_chunks(last));
+                return Creator::from(&p);
+            },
+            _ => {},
+        };
+        let p = Person::parse(&bibtex_chunks(s));
+        Creator::from(&p)
+    }
+
+    /// Parse a creator from a name as written in running text, e.g. `Satoshi Nakamoto`.
+    ///
+    /// The name is taken to be an organisation if it contains words like "of" and "for",
+    /// parentheses, digits or `&`, e.g. `Government of Kenya`.
+    pub fn from_text(s: &str) -> Creator {
+        let s = s.trim();
+        if is_organisation_text(s) {
+            return Creator::organisation(s);
+        }
+        Creator::parse(s)
+    }
+
+    /// Returns the name in BibTeX name format.
+    ///
+    /// Family names of more than one word are enclosed in curly braces, so that the name can not
+    /// be mistaken for a list of names in running text, see [parse_stored](parse_stored).
+    pub fn to_bibtex(&self) -> String {
+        match self {
+            Creator::Person{given, family, prefix, suffix} => {
+                let mut s = match prefix.starts_with(|c: char| c.is_lowercase()) {
+                    true => {
+                        format!("{} {}", prefix, family_bibtex(family))
+                    },
+                    false => {
+                        family_bibtex(format!("{} {}", prefix, family).trim())
+                    },
+                };
+                if !suffix.is_empty() {
+                    s = format!("{}, {}, {}", s, suffix, given);
+                } else if !given.is_empty() || s.contains(' ') || s.contains('{') {
+                    s = format!("{}, {}", s, given);
+                }
+                s.trim_end().to_string()
+            },
+            Creator::Organisation(name) => {
+                format!("{{{}}}", name)
+            },
+        }
+    }
+
+    /// Returns the name as written in running text, e.g. `Satoshi Nakamoto`.
+    pub fn name(&self) -> String {
+        match self {
+            Creator::Person{given, family, prefix, suffix} => {
+                let parts: Vec<&str> = vec!(given.as_str(), prefix.as_str(), family.as_str(), suffix.as_str());
+                parts.into_iter()
+                    .filter(|v| {
+                        !v.is_empty()
+                    })
+                    .collect::<Vec<&str>>()
+                    .join(" ")
+            },
+            Creator::Organisation(name) => {
+                name.clone()
+            },
+        }
+    }
+}
+
+/// Lowercase words which join the parts of organisation names, e.g. "Government of Kenya".
+///
+/// Unlike name particles such as "von" or "de", these do not occur in the names of persons.
+const ORGANISATION_PARTICLES: [&str; 8] = ["of", "for", "the", "and", "on", "in", "at", "to"];
+
+/// Returns true if the name is enclosed in curly braces as a whole, e.g. `{Government of Kenya}`.
+fn is_braced(s: &str) -> bool {
+    if s.len() < 2 || !s.starts_with('{') || !s.ends_with('}') {
+        return false;
+    }
+    let mut depth: usize = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => {
+                depth += 1;
+            },
+            '}' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    return i == s.len() - 1;
+                }
+            },
+            _ => {},
+        };
+    }
+    false
+}
+
+/// Returns the family name in BibTeX name format, enclosed in curly braces if it has more than
+/// one word or looks like the name of an organisation.
+fn family_bibtex(family: &str) -> String {
+    match family.contains(' ') || is_organisation_text(family) {
+        true => {
+            format!("{{{}}}", family)
+        },
+        false => {
+            String::from(family)
+        },
+    }
+}
+
+/// Split a name in BibTeX name format into chunks, where words enclosed in curly braces are
+/// verbatim.
+pub(crate) fn bibtex_chunks(s: &str) -> Vec<Spanned<Chunk>> {
+    let mut r: Vec<Spanned<Chunk>> = vec!();
+    let mut depth: usize = 0;
+    let mut v = String::new();
+    for c in s.chars() {
+        match c {
+            '{' => {
+                if depth == 0 && !v.is_empty() {
+                    r.push(Spanned::detached(Chunk::Normal(std::mem::take(&mut v))));
+                } else if depth > 0 {
+                    v.push(c);
+                }
+                depth += 1;
+            },
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    r.push(Spanned::detached(Chunk::Verbatim(std::mem::take(&mut v))));
+                } else {
+                    v.push(c);
+                }
+            },
+            _ => {
+                v.push(c);
+            },
+        };
+    }
+    if !v.is_empty() {
+        r.push(Spanned::detached(Chunk::Normal(v)));
+    }
+    r
+}
+
+impl From<&Person> for Creator {
+    fn from(p: &Person) -> Creator {
+        Creator::Person{
+            given: p.given_name.clone(),
+            family: p.name.clone(),
+            prefix: p.prefix.clone(),
+            suffix: p.suffix.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Creator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Parse a list of creators in BibTeX name format, separated by `and`, as written by
+/// [format_list](format_list).
+///
+/// Separators within curly braces are ignored, so organisation names may contain `and`. Names
+/// not enclosed in curly braces are persons.
+pub fn parse_list(s: &str) -> Vec<Creator> {
+    split_list(s).into_iter()
+        .map(|v| {
+            Creator::parse(v)
+        })
+        .collect()
+}
+
+/// Parse a list of creators in BibTeX name format, separated by `and`, from a source written by
+/// hand, e.g. a BibTeX file.
+///
+/// Unlike with [parse_list](parse_list), a name written as in running text, i.e. without a comma
+/// and not enclosed in curly braces, is taken to be an organisation if it looks like one, see
+/// [Creator::from_text](Creator::from_text).
+pub fn parse_source_list(s: &str) -> Vec<Creator> {
+    split_list(s).into_iter()
+        .map(|v| {
+            let v = v.trim();
+            match is_braced(v) || v.contains(',') {
+                true => {
+                    Creator::parse(v)
+                },
+                false => {
+                    Creator::from_text(v)
+                },
+            }
+        })
+        .collect()
+}
+
+/// Split a list of names separated by `and`, ignoring separators within curly braces.
+fn split_list(s: &str) -> Vec<&str> {
+    let mut r: Vec<&str> = vec!();
+    let mut depth: usize = 0;
+    let mut start: usize = 0;
+    let b = s.as_bytes();
+    let mut i: usize = 0;
+    while i < b.len() {
+        match b[i] {
+            b'{' => {
+                depth += 1;
+            },
+            b'}' => {
+                depth = depth.saturating_sub(1);
+            },
+            _ => {
+                if depth == 0 && b[i..].starts_with(b" and ") {
+                    r.push(&s[start..i]);
+                    i += 5;
+                    start = i;
+                    continue;
+                }
+            },
+        };
+        i += 1;
+    }
+    if !s[start..].trim().is_empty() {
+        r.push(&s[start..]);
+    }
+    r
+}
+
+/// Returns true if the name, as written in running text, looks like the name of an organisation.
+fn is_organisation_text(s: &str) -> bool {
+    if s.contains(|c: char| c == '(' || c == ')' || c == '&' || c.is_ascii_digit()) {
+        return true;
+    }
+    s.split_whitespace().any(|v| {
+        ORGANISATION_PARTICLES.contains(&v)
+    })
+}
+
+/// Returns true if the name is written as in running text with more than one word, e.g.
+/// `Satoshi Nakamoto`, rather than as a family name in BibTeX name format, e.g. `von Neumann`.
+fn is_running_text_name(s: &str) -> bool {
+    let s = s.trim();
+    s.contains(' ') && s.starts_with(|c: char| c.is_uppercase())
+}
+
+/// Returns true if the value is a list of names in running text, as written by earlier versions,
+/// rather than a list in BibTeX name format.
+///
+/// In BibTeX name format, organisations and family names of more than one word are enclosed in
+/// curly braces, and every name of more than one word is written with the family name first,
+/// followed by a comma. Earlier versions joined the names with commas only, e.g.
+/// `Satoshi Nakamoto, Hal Finney`, which is told apart from a single name in BibTeX name format
+/// by every part being a name in running text.
+fn is_legacy_list(s: &str) -> bool {
+    if s.contains('{') {
+        return false;
+    }
+    if !s.contains(" and ") && s.contains(',') && s.split(',').all(is_running_text_name) {
+        return true;
+    }
+    s.split(" and ").any(|v| {
+        let v = v.trim();
+        !v.contains(',') && (v.contains(' ') || is_organisation_text(v))
+    })
+}
+
+/// Parse a list of creators from names in running text, separated by commas and `and`, e.g.
+/// `Gowri Sankar Ramachandran, Rahul Radhakrishnan and Bhaskar Krishnamachari`.
+///
+/// Earlier versions stored creators in this form. Since the names of organisations may contain
+/// commas, a part of the list between `and` separators which looks like the name of an
+/// organisation is not split at commas, e.g.
+/// `European Parliamentary Research Service, Scientific Foresight Unit (STOA)`.
+pub fn parse_legacy_list(s: &str) -> Vec<Creator> {
+    let mut r: Vec<Creator> = vec!();
+    for part in s.split(" and ") {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+        if is_organisation_text(part) {
+            r.push(Creator::organisation(part));
+            continue;
+        }
+        for v in part.split(',') {
+            if !v.trim().is_empty() {
+                r.push(Creator::from_text(v));
+            }
+        }
+    }
+    r
+}
+
+/// Parse a list of creators as stored in a record.
+///
+/// Values in BibTeX name format are parsed with [parse_list](parse_list), and the lists of names
+/// in running text written by earlier versions with [parse_legacy_list](parse_legacy_list).
+pub fn parse_stored(s: &str) -> Vec<Creator> {
+    match is_legacy_list(s) {
+        true => {
+            parse_legacy_list(s)
+        },
+        false => {
+            parse_list(s)
+        },
+    }
+}
+
+/// Format a list of creators in BibTeX name format, separated by `and`.
+pub fn format_list(creators: &[Creator]) -> String {
+    creators.iter()
+        .map(|v| {
+            v.to_bibtex()
+        })
+        .collect::<Vec<String>>()
+        .join(" and ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Creator,
+        parse_list,
+        parse_legacy_list,
+        parse_stored,
+        parse_source_list,
+        format_list,
+    };
+
+    #[test]
+    fn test_creator_parse() {
+        let c = Creator::parse("Nakamoto, Satoshi");
+        assert_eq!(c, Creator::person("Satoshi", "Nakamoto"));
+        assert_eq!(c.name(), "Satoshi Nakamoto");
+        assert_eq!(c.to_bibtex(), "Nakamoto, Satoshi");
+
+        let c = Creator::parse("John von Neumann");
+        assert_eq!(c.name(), "John von Neumann");
+        assert_eq!(c.to_bibtex(), "von Neumann, John");
+        assert_eq!(Creator::parse(c.to_bibtex().as_str()), c);
+
+        let c = Creator::parse("{Government of Kenya}");
+        assert_eq!(c, Creator::organisation("Government of Kenya"));
+        assert_eq!(c.name(), "Government of Kenya");
+        assert_eq!(c.to_bibtex(), "{Government of Kenya}");
+
+        let c = Creator::parse("bar");
+        assert_eq!(c.name(), "bar");
+        assert_eq!(c.to_bibtex(), "bar");
+    }
+
+    #[test]
+    fn test_creator_list() {
+        let s = "Ramachandran, Gowri Sankar and Radhakrishnan, Rahul and {Bell and Howell}";
+        let r = parse_list(s);
+        assert_eq!(r.len(), 3);
+        assert_eq!(r[0], Creator::person("Gowri Sankar", "Ramachandran"));
+        assert_eq!(r[2], Creator::organisation("Bell and Howell"));
+        assert_eq!(format_list(&r), s);
+        assert_eq!(parse_list("").len(), 0);
+
+        let r = parse_list("Bär, Jürgen and Åberg, Öyvind");
+        assert_eq!(r, vec!(Creator::person("Jürgen", "Bär"), Creator::person("Öyvind", "Åberg")));
+    }
+
+    #[test]
+    fn test_creator_organisation() {
+        assert_eq!(Creator::parse("Government of Kenya"), Creator::Person{
+            given: String::from("Government"),
+            family: String::from("Kenya"),
+            prefix: String::from("of"),
+            suffix: String::new(),
+        });
+        assert_eq!(Creator::parse("{Ministry for the Environment}"), Creator::organisation("Ministry for the Environment"));
+        assert_eq!(Creator::parse("John von Neumann"), Creator::Person{
+            given: String::from("John"),
+            family: String::from("Neumann"),
+            prefix: String::from("von"),
+            suffix: String::new(),
+        });
+        assert_eq!(Creator::parse("Satoshi Nakamoto"), Creator::person("Satoshi", "Nakamoto"));
+
+        assert_eq!(Creator::from_text("Satoshi Nakamoto"), Creator::person("Satoshi", "Nakamoto"));
+        assert_eq!(Creator::from_text("Government of Kenya"), Creator::organisation("Government of Kenya"));
+        assert_eq!(Creator::from_text("Ernst & Young"), Creator::organisation("Ernst & Young"));
+        assert_eq!(Creator::from_text("Bell Labs"), Creator::person("Bell", "Labs"));
+
+        let c = Creator::person("", "Van Gogh");
+        assert_eq!(c.to_bibtex(), "{Van Gogh},");
+        assert_eq!(Creator::parse(&c.to_bibtex()), c);
+        assert_eq!(Creator::parse("Van Gogh,"), c);
+        assert_eq!(parse_stored(&c.to_bibtex()), vec!(c.clone()));
+        let r = vec!(c, Creator::organisation("Van Gogh Museum"), Creator::Person{
+            given: String::from("Vincent"),
+            family: String::from("Gogh"),
+            prefix: String::from("van"),
+            suffix: String::new(),
+        });
+        assert_eq!(parse_list(&format_list(&r)), r);
+        assert_eq!(parse_source_list(&format_list(&r)), r);
+
+        assert_eq!(parse_source_list("Government of Kenya and Nakamoto, Satoshi and Satoshi Nakamoto"), vec!(
+            Creator::organisation("Government of Kenya"),
+            Creator::person("Satoshi", "Nakamoto"),
+            Creator::person("Satoshi", "Nakamoto"),
+        ));
+    }
+
+    #[test]
+    fn test_creator_multi_word_family() {
+        let c = Creator::person("Ursula K.", "Le Guin");
+        assert_eq!(c.to_bibtex(), "{Le Guin}, Ursula K.");
+        assert_eq!(Creator::parse(&c.to_bibtex()), c);
+        assert_eq!(parse_stored(&c.to_bibtex()), vec!(c.clone()));
+
+        let r = vec!(c, Creator::Person{
+            given: String::from("Robert"),
+            family: String::from("De Niro"),
+            prefix: String::new(),
+            suffix: String::from("Jr."),
+        }, Creator::Person{
+            given: String::from("Maria"),
+            family: String::from("la Cruz"),
+            prefix: String::from("de"),
+            suffix: String::new(),
+        });
+        assert_eq!(format_list(&r), "{Le Guin}, Ursula K. and {De Niro}, Jr., Robert and de {la Cruz}, Maria");
+        assert_eq!(parse_stored(&format_list(&r)), r);
+    }
+
+    #[test]
+    fn test_creator_legacy_list() {
+        let s = "Gowri Sankar Ramachandran, Rahul Radhakrishnan and Bhaskar Krishnamachari";
+        let r = parse_legacy_list(s);
+        assert_eq!(r, vec!(
+            Creator::person("Gowri Sankar", "Ramachandran"),
+            Creator::person("Rahul", "Radhakrishnan"),
+            Creator::person("Bhaskar", "Krishnamachari"),
+        ));
+        assert_eq!(parse_stored(s), r);
+
+        let s = "European Parliamentary Research Service, Scientific Foresight Unit (STOA)";
+        assert_eq!(parse_stored(s), vec!(Creator::organisation(s)));
+        assert_eq!(parse_stored("Government of Kenya"), vec!(Creator::organisation("Government of Kenya")));
+        assert_eq!(parse_stored("Satoshi Nakamoto"), vec!(Creator::person("Satoshi", "Nakamoto")));
+
+        let s = "Ramachandran, Gowri Sankar and Radhakrishnan, Rahul and {Government of Kenya}";
+        assert_eq!(parse_stored(s), parse_list(s));
+        assert_eq!(parse_stored("Nakamoto, Satoshi"), vec!(Creator::person("Satoshi", "Nakamoto")));
+        assert_eq!(parse_stored("bar"), vec!(Creator::person("", "bar")));
+    }
+
+    #[test]
+    fn test_creator_legacy_comma_list() {
+        // authors as joined by biblatex::read_all of earlier versions
+        let s = "Satoshi Nakamoto, Hal Finney";
+        assert_eq!(parse_stored(s), vec!(
+            Creator::person("Satoshi", "Nakamoto"),
+            Creator::person("Hal", "Finney"),
+        ));
+        let s = "Gowri Sankar Ramachandran, Rahul Radhakrishnan, Bhaskar Krishnamachari";
+        assert_eq!(parse_stored(s), vec!(
+            Creator::person("Gowri Sankar", "Ramachandran"),
+            Creator::person("Rahul", "Radhakrishnan"),
+            Creator::person("Bhaskar", "Krishnamachari"),
+        ));
+
+        assert_eq!(parse_stored("von Neumann, John"), vec!(Creator::Person{
+            given: String::from("John"),
+            family: String::from("Neumann"),
+            prefix: String::from("von"),
+            suffix: String::new(),
+        }));
+        assert_eq!(parse_stored("Nakamoto, Satoshi"), vec!(Creator::person("Satoshi", "Nakamoto")));
+        assert_eq!(parse_stored("Ramachandran, Gowri Sankar"), vec!(Creator::person("Gowri Sankar", "Ramachandran")));
+    }
+}
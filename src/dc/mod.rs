@@ -3,22 +3,45 @@ use unic_langid_impl::LanguageIdentifier;
 use biblatex::EntryType;
 use std::str::FromStr;
 
+pub mod creator;
+
+pub use creator::Creator;
 
 /// Represents the parts of the metadata schema covered by the Dublin Core vocabulary.
+#[derive(Clone)]
 pub struct DCMetaData {
     /// Title of work represented by media.
     pub title: String,
-    /// Author(s) of work represented by media. Multiple authors may be specified by separating
-    /// them with comma.
-    pub author: String,
+    /// Author(s) of work represented by media, in order.
+    pub creators: Vec<Creator>,
     /// Type of work represented by media. Maps to bibtex entry types.
     pub typ: EntryType,
-    /// Comma-separated keyword list describing the content.
-    pub subject: Option<String>,
+    /// Keywords describing the content, without duplicates.
+    pub subjects: Vec<String>,
     /// MIME type of the media.
     pub mime: Option<Mime>,
     /// What language the work represented by this media file is in.
     pub language: Option<LanguageIdentifier>,
+    /// Entity responsible for making the work available.
+    pub publisher: Option<String>,
+    /// Point or period of time associated with the work, in free form.
+    pub date: Option<String>,
+    /// Unambiguous reference to the work, e.g. `doi:<doi>` or `urn:isbn:<isbn>`.
+    pub identifier: Option<String>,
+    /// Account of the work, e.g. an abstract.
+    pub description: Option<String>,
+    /// Entity responsible for contributions to the work, other than the author(s), e.g. editors.
+    /// Multiple contributors are given in BibTeX name format separated by `and`, as for
+    /// [creators](crate::dc::creator::format_list).
+    pub contributor: Option<String>,
+    /// Related work from which the work is derived.
+    pub source: Option<String>,
+    /// Information about rights held in and over the work, e.g. a license.
+    pub rights: Option<String>,
+    /// Related work in which the work is included, e.g. a journal or book.
+    pub part_of: Option<String>,
+    /// Size or duration of the work, e.g. number of pages.
+    pub extent: Option<String>,
 }
 
 pub const DC_IRI_TITLE: &str = "https://purl.org/dc/terms/title";
@@ -27,26 +50,56 @@ pub const DC_IRI_SUBJECT: &str = "https://purl.org/dc/terms/subject";
 pub const DC_IRI_LANGUAGE: &str = "https://purl.org/dc/terms/language";
 pub const DC_IRI_TYPE: &str = "https://purl.org/dc/terms/type";
 pub const DC_IRI_MEDIATYPE: &str = "https://purl.org/dc/terms/MediaType";
+pub const DC_IRI_PUBLISHER: &str = "https://purl.org/dc/terms/publisher";
+pub const DC_IRI_DATE: &str = "https://purl.org/dc/terms/date";
+pub const DC_IRI_IDENTIFIER: &str = "https://purl.org/dc/terms/identifier";
+pub const DC_IRI_DESCRIPTION: &str = "https://purl.org/dc/terms/description";
+pub const DC_IRI_CONTRIBUTOR: &str = "https://purl.org/dc/terms/contributor";
+pub const DC_IRI_SOURCE: &str = "https://purl.org/dc/terms/source";
+pub const DC_IRI_RIGHTS: &str = "https://purl.org/dc/terms/rights";
+pub const DC_IRI_ISPARTOF: &str = "https://purl.org/dc/terms/isPartOf";
+pub const DC_IRI_EXTENT: &str = "https://purl.org/dc/terms/extent";
+pub const DC_IRI_ISSUED: &str = "https://purl.org/dc/terms/issued";
 pub const DC_XATTR_TITLE: &str = "user.dcterms:title";
 pub const DC_XATTR_CREATOR: &str = "user.dcterms:creator";
 pub const DC_XATTR_SUBJECT: &str = "user.dcterms:subject";
 pub const DC_XATTR_LANGUAGE: &str = "user.dcterms:language";
 pub const DC_XATTR_TYPE: &str = "user.dcterms:type";
 pub const DC_XATTR_MEDIATYPE: &str = "user.dcterms:MediaType";
+pub const DC_XATTR_PUBLISHER: &str = "user.dcterms:publisher";
+pub const DC_XATTR_DATE: &str = "user.dcterms:date";
+pub const DC_XATTR_IDENTIFIER: &str = "user.dcterms:identifier";
+pub const DC_XATTR_DESCRIPTION: &str = "user.dcterms:description";
+pub const DC_XATTR_CONTRIBUTOR: &str = "user.dcterms:contributor";
+pub const DC_XATTR_SOURCE: &str = "user.dcterms:source";
+pub const DC_XATTR_RIGHTS: &str = "user.dcterms:rights";
+pub const DC_XATTR_ISPARTOF: &str = "user.dcterms:isPartOf";
+pub const DC_XATTR_EXTENT: &str = "user.dcterms:extent";
+pub const DC_XATTR_ISSUED: &str = "user.dcterms:issued";
 
 impl DCMetaData {
     /// Creates a new Dublin Core metadata part with minimal data.
     ///
     /// `title`, `author` and `entry_type` map to corresponding [DCMetaData](DCMetaData)
-    /// properties.
+    /// properties. Multiple authors may be specified in `author` by separating them with `and`,
+    /// see [creator::parse_list](creator::parse_list).
     pub fn new(title: &str, author: &str, entry_type: EntryType) -> DCMetaData {
         DCMetaData{
             title: String::from(title),
-            author: String::from(author),
+            creators: creator::parse_list(author),
             typ: entry_type,
-            subject: None,
+            subjects: vec!(),
             mime: None,
             language: None,
+            publisher: None,
+            date: None,
+            identifier: None,
+            description: None,
+            contributor: None,
+            source: None,
+            rights: None,
+            part_of: None,
+            extent: None,
         }
     }
 }
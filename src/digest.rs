@@ -3,7 +3,9 @@ use std::io::Read;
 use std::fmt;
 use std::str::FromStr;
 
-use crate::error::ParseError;
+use crate::error::Error;
+
+pub mod urn;
 
 use sha2::{
     Sha512,
@@ -13,18 +15,40 @@ use sha2::{
 
 use log::error;
 
-#[derive(Copy, Clone)]
+#[cfg(feature="digest_bzz")]
+use crate::swarm::SwarmHasher;
+
+#[cfg(feature="digest_sha1")]
+use sha1::Sha1;
+
+#[cfg(feature="digest_ipfs")]
+use crate::ipfs::{
+    IpfsHasher,
+    cid_to_string,
+    cid_from_str,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum DigestType {
     Sha512,
     Sha256,
     #[cfg(feature="digest_md5")]
     MD5,
+    #[cfg(feature="digest_bzz")]
+    SwarmHash,
+    #[cfg(feature="digest_sha1")]
+    Sha1,
+    #[cfg(feature="digest_blake3")]
+    Blake3,
+    #[cfg(feature="digest_ipfs")]
+    Ipfs,
 }
 
 impl FromStr for DigestType {
-    type Err = ParseError;
+    type Err = Error;
     fn from_str(s: &str) -> Result<DigestType, Self::Err> {
         match s {
+            #[cfg(feature="digest_md5")]
             "md5" => {
                 return Ok(DigestType::MD5);
             },
@@ -34,25 +58,237 @@ impl FromStr for DigestType {
             "sha256" => {
                 return Ok(DigestType::Sha256);
             },
+            #[cfg(feature="digest_bzz")]
+            "bzz" => {
+                return Ok(DigestType::SwarmHash);
+            },
+            #[cfg(feature="digest_sha1")]
+            "sha1" => {
+                return Ok(DigestType::Sha1);
+            },
+            #[cfg(feature="digest_blake3")]
+            "blake3" => {
+                return Ok(DigestType::Blake3);
+            },
+            #[cfg(feature="digest_ipfs")]
+            "ipfs" => {
+                return Ok(DigestType::Ipfs);
+            },
             _ => {
-                return Err(ParseError::new("unknown digest string"));
+                return Err(Error::digest("unknown digest string"));
             },
         };
     }
 }
 
+/// Names of the digest types available with the enabled features, as parsed by
+/// [DigestType::from_str](DigestType::from_str).
+pub const DIGEST_TYPE_NAMES: &[&str] = &[
+    "sha512",
+    "sha256",
+    #[cfg(feature="digest_md5")]
+    "md5",
+    #[cfg(feature="digest_bzz")]
+    "bzz",
+    #[cfg(feature="digest_sha1")]
+    "sha1",
+    #[cfg(feature="digest_blake3")]
+    "blake3",
+    #[cfg(feature="digest_ipfs")]
+    "ipfs",
+];
+
+/// Size of the buffer that data is read into when calculating digests.
+pub const DIGEST_BUFFER_SIZE: usize = 1 << 16;
+
 impl DigestType {
-    pub fn digest_for(&self, f: impl Read) -> RecordDigest {
-        RecordDigest::Empty 
+    /// Calculate the digest of this type for all data in the reader.
+    pub fn digest_for(&self, f: impl Read) -> Result<RecordDigest, std::io::Error> {
+        let mut r = digests_for(f, &vec!(*self))?;
+        Ok(r.remove(0))
     }
 }
 
+enum Hasher {
+    Sha512(Sha512),
+    Sha256(Sha256),
+    #[cfg(feature="digest_md5")]
+    MD5(md5::Context),
+    #[cfg(feature="digest_bzz")]
+    SwarmHash(SwarmHasher),
+    #[cfg(feature="digest_sha1")]
+    Sha1(Sha1),
+    #[cfg(feature="digest_blake3")]
+    Blake3(Box<blake3::Hasher>),
+    #[cfg(feature="digest_ipfs")]
+    Ipfs(IpfsHasher),
+}
+
+/// Calculates digests of several types for the same data in a single pass.
+///
+/// Each digest type is only calculated once, even if given more than once.
+pub struct MultiHasher {
+    hashers: Vec<(DigestType, Hasher)>,
+}
+
+impl MultiHasher {
+    /// Create a new hasher for the given digest types.
+    pub fn new(digest_types: &Vec<DigestType>) -> MultiHasher {
+        let mut hashers: Vec<(DigestType, Hasher)> = vec!();
+        for v in digest_types {
+            if hashers.iter().any(|(t, _)| t == v) {
+                continue;
+            }
+            let h = match v {
+                DigestType::Sha512 => {
+                    Hasher::Sha512(Sha512::new())
+                },
+                DigestType::Sha256 => {
+                    Hasher::Sha256(Sha256::new())
+                },
+                #[cfg(feature="digest_md5")]
+                DigestType::MD5 => {
+                    Hasher::MD5(md5::Context::new())
+                },
+                #[cfg(feature="digest_bzz")]
+                DigestType::SwarmHash => {
+                    Hasher::SwarmHash(SwarmHasher::new())
+                },
+                #[cfg(feature="digest_sha1")]
+                DigestType::Sha1 => {
+                    Hasher::Sha1(Sha1::new())
+                },
+                #[cfg(feature="digest_blake3")]
+                DigestType::Blake3 => {
+                    Hasher::Blake3(Box::new(blake3::Hasher::new()))
+                },
+                #[cfg(feature="digest_ipfs")]
+                DigestType::Ipfs => {
+                    Hasher::Ipfs(IpfsHasher::new())
+                },
+            };
+            hashers.push((*v, h));
+        }
+        MultiHasher{
+            hashers,
+        }
+    }
+
+    /// Add data to all digests.
+    pub fn update(&mut self, data: &[u8]) {
+        for (_, h) in self.hashers.iter_mut() {
+            match h {
+                Hasher::Sha512(h) => {
+                    h.update(data);
+                },
+                Hasher::Sha256(h) => {
+                    h.update(data);
+                },
+                #[cfg(feature="digest_md5")]
+                Hasher::MD5(h) => {
+                    h.consume(data);
+                },
+                #[cfg(feature="digest_bzz")]
+                Hasher::SwarmHash(h) => {
+                    h.update(data);
+                },
+                #[cfg(feature="digest_sha1")]
+                Hasher::Sha1(h) => {
+                    h.update(data);
+                },
+                #[cfg(feature="digest_blake3")]
+                Hasher::Blake3(h) => {
+                    h.update(data);
+                },
+                #[cfg(feature="digest_ipfs")]
+                Hasher::Ipfs(h) => {
+                    h.update(data);
+                },
+            };
+        }
+    }
+
+    /// Returns the digests of all data added, in the order of the digest types given to
+    /// [MultiHasher::new](MultiHasher::new).
+    pub fn finalize(self) -> Vec<RecordDigest> {
+        self.hashers.into_iter()
+            .map(|(_, h)| {
+                match h {
+                    Hasher::Sha512(h) => {
+                        RecordDigest::Sha512(h.finalize().to_vec())
+                    },
+                    Hasher::Sha256(h) => {
+                        RecordDigest::Sha256(h.finalize().to_vec())
+                    },
+                    #[cfg(feature="digest_md5")]
+                    Hasher::MD5(h) => {
+                        RecordDigest::MD5(h.compute().to_vec())
+                    },
+                    #[cfg(feature="digest_bzz")]
+                    Hasher::SwarmHash(h) => {
+                        RecordDigest::SwarmHash(h.finalize())
+                    },
+                    #[cfg(feature="digest_sha1")]
+                    Hasher::Sha1(h) => {
+                        RecordDigest::Sha1(h.finalize().to_vec())
+                    },
+                    #[cfg(feature="digest_blake3")]
+                    Hasher::Blake3(h) => {
+                        RecordDigest::Blake3(h.finalize().as_bytes().to_vec())
+                    },
+                    #[cfg(feature="digest_ipfs")]
+                    Hasher::Ipfs(h) => {
+                        RecordDigest::Ipfs(h.finalize())
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+/// Calculate digests of all given types for all data in the reader, reading the data only once.
+///
+/// The digests are returned in the order of the given digest types, without duplicates.
+pub fn digests_for(mut f: impl Read, digest_types: &Vec<DigestType>) -> Result<Vec<RecordDigest>, std::io::Error> {
+    if digest_types.is_empty() {
+        return Ok(vec!());
+    }
+    let mut h = MultiHasher::new(digest_types);
+    let mut b: Vec<u8> = vec!(0; DIGEST_BUFFER_SIZE);
+    loop {
+        let c = match f.read(&mut b) {
+            Ok(0) => {
+                break;
+            },
+            Ok(c) => {
+                c
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {
+                continue;
+            },
+            Err(e) => {
+                return Err(e);
+            },
+        };
+        h.update(&b[..c]);
+    }
+    Ok(h.finalize())
+}
+
 /// Encapsulations of supported digests for digest data.
+#[derive(PartialEq)]
 pub enum RecordDigest {
     Sha512(Vec<u8>),
     Sha256(Vec<u8>),
     MD5(Vec<u8>),
     SwarmHash(Vec<u8>),
+    #[cfg(feature="digest_sha1")]
+    Sha1(Vec<u8>),
+    #[cfg(feature="digest_blake3")]
+    Blake3(Vec<u8>),
+    /// Binary CIDv1.
+    #[cfg(feature="digest_ipfs")]
+    Ipfs(Vec<u8>),
     EmptyWithType(DigestType),
     Empty,
 }
@@ -72,6 +308,18 @@ impl Clone for RecordDigest {
             RecordDigest::SwarmHash(v) => {
                 RecordDigest::SwarmHash(v.to_vec())
             },
+            #[cfg(feature="digest_sha1")]
+            RecordDigest::Sha1(v) => {
+                RecordDigest::Sha1(v.to_vec())
+            },
+            #[cfg(feature="digest_blake3")]
+            RecordDigest::Blake3(v) => {
+                RecordDigest::Blake3(v.to_vec())
+            },
+            #[cfg(feature="digest_ipfs")]
+            RecordDigest::Ipfs(v) => {
+                RecordDigest::Ipfs(v.to_vec())
+            },
             _ => {
                 RecordDigest::Empty
             },
@@ -80,6 +328,41 @@ impl Clone for RecordDigest {
 }
 
 impl RecordDigest {
+    /// Returns the type of the digest, if it is one that can be calculated.
+    pub fn digest_type(&self) -> Option<DigestType> {
+        match self {
+            RecordDigest::Sha512(_) => {
+                Some(DigestType::Sha512)
+            },
+            RecordDigest::Sha256(_) => {
+                Some(DigestType::Sha256)
+            },
+            #[cfg(feature="digest_md5")]
+            RecordDigest::MD5(_) => {
+                Some(DigestType::MD5)
+            },
+            #[cfg(feature="digest_bzz")]
+            RecordDigest::SwarmHash(_) => {
+                Some(DigestType::SwarmHash)
+            },
+            #[cfg(feature="digest_sha1")]
+            RecordDigest::Sha1(_) => {
+                Some(DigestType::Sha1)
+            },
+            #[cfg(feature="digest_blake3")]
+            RecordDigest::Blake3(_) => {
+                Some(DigestType::Blake3)
+            },
+            #[cfg(feature="digest_ipfs")]
+            RecordDigest::Ipfs(_) => {
+                Some(DigestType::Ipfs)
+            },
+            _ => {
+                None
+            },
+        }
+    }
+
     pub fn fingerprint(&self) -> Vec<u8> {
         match self {
             RecordDigest::Sha512(v) => {
@@ -94,6 +377,18 @@ impl RecordDigest {
             RecordDigest::SwarmHash(v) => {
                 return v.to_vec();
             },
+            #[cfg(feature="digest_sha1")]
+            RecordDigest::Sha1(v) => {
+                return v.to_vec();
+            },
+            #[cfg(feature="digest_blake3")]
+            RecordDigest::Blake3(v) => {
+                return v.to_vec();
+            },
+            #[cfg(feature="digest_ipfs")]
+            RecordDigest::Ipfs(v) => {
+                return v.to_vec();
+            },
             _ => {
                 return vec!()
             },
@@ -115,7 +410,19 @@ impl RecordDigest {
                 return String::from("md5:") + hex::encode(&v).as_str();
             },
             RecordDigest::SwarmHash(v) => {
-                return hex::encode(&v);
+                return String::from("bzz:") + hex::encode(v).as_str();
+            },
+            #[cfg(feature="digest_sha1")]
+            RecordDigest::Sha1(v) => {
+                return String::from("sha1:") + hex::encode(v).as_str();
+            },
+            #[cfg(feature="digest_blake3")]
+            RecordDigest::Blake3(v) => {
+                return String::from("blake3:") + hex::encode(v).as_str();
+            },
+            #[cfg(feature="digest_ipfs")]
+            RecordDigest::Ipfs(v) => {
+                return String::from("ipfs:") + cid_to_string(v).as_str();
             },
             _ => {
                 return String::new();
@@ -133,10 +440,10 @@ impl fmt::Debug for RecordDigest {
 /// Create a [RecordDigest::Sha512](RecordDigest::Sha512) instance from the raw digest data.
 ///
 /// Will fail if digest has incorrect length.
-pub fn from_vec(v: Vec<u8>) -> Result<RecordDigest, ParseError> {
+pub fn from_vec(v: Vec<u8>) -> Result<RecordDigest, Error> {
     let sz = Sha512::output_size();
     if v.len() != sz {
-        return Err(ParseError::new("invalid digest size"));
+        return Err(Error::digest("invalid digest size"));
     }
     Ok(RecordDigest::Sha512(v))
 }
@@ -148,35 +455,61 @@ pub fn from_vec(v: Vec<u8>) -> Result<RecordDigest, ParseError> {
 /// * `sha512` -> [RecordDigest::Sha512](RecordDigest::Sha512])
 /// * `sha256` -> [RecordDigest::Sha256](RecordDigest::Sha256])
 /// * `bzz` -> [RecordDigest::SwarmHash](RecordDigest::SwarmHash])
-pub fn from_urn(urn: &str) -> Result<RecordDigest, ParseError> {
+/// * `sha1` -> `RecordDigest::Sha1`, with the `digest_sha1` feature
+/// * `blake3` -> `RecordDigest::Blake3`, with the `digest_blake3` feature
+/// * `ipfs` -> `RecordDigest::Ipfs`, with the `digest_ipfs` feature
+///
+/// The scheme is not case sensitive. See [urn::parse](urn::parse) for other notations of digests.
+pub fn from_urn(urn: &str) -> Result<RecordDigest, Error> {
     let mut v = urn.split(":");
-    let r = match v.next() {
+    let r = match v.next().map(|s| s.to_lowercase()).as_deref() {
         Some("sha512") => {
             let digest_hex = match v.next() {
                 Some(r) => {
                     r
                 },
                 None => {
-                    return Err(ParseError::new("not a valid digest urn"));
+                    return Err(Error::digest("not a valid digest urn"));
+                },
+            };
+            let digest = match hex::decode(digest_hex) {
+                Ok(r) => {
+                    r
+                },
+                Err(_) => {
+                    return Err(Error::digest("invalid digest hex"));
                 },
             };
-            let digest = hex::decode(digest_hex).unwrap();
             match from_vec(digest) {
                 Ok(vv) => {
                     vv
                 },
                 Err(e) => {
-                    return Err(ParseError::new("invalid sha512 digest"));
+                    return Err(Error::digest("invalid sha512 digest"));
                 },
             }
         },
         Some("sha256") => {
-            let digest_hex = v.next().unwrap();
-            let digest = hex::decode(digest_hex).unwrap();
+            let digest_hex = match v.next() {
+                Some(r) => {
+                    r
+                },
+                None => {
+                    return Err(Error::digest("not a valid digest urn"));
+                },
+            };
+            let digest = match hex::decode(digest_hex) {
+                Ok(r) => {
+                    r
+                },
+                Err(_) => {
+                    return Err(Error::digest("invalid digest hex"));
+                },
+            };
 
             let sz = Sha256::output_size();
             if digest.len() != sz {
-                return Err(ParseError::new("invalid sha256 digest"));
+                return Err(Error::digest("invalid sha256 digest"));
             }
 
             RecordDigest::Sha256(digest)
@@ -187,32 +520,114 @@ pub fn from_urn(urn: &str) -> Result<RecordDigest, ParseError> {
                     r
                 },
                 None => {
-                    return Err(ParseError::new("not a valid digest urn"));
+                    return Err(Error::digest("not a valid digest urn"));
+                },
+            };
+            let digest = match hex::decode(digest_hex) {
+                Ok(r) => {
+                    r
+                },
+                Err(_) => {
+                    return Err(Error::digest("invalid digest hex"));
                 },
             };
-            let digest = hex::decode(digest_hex).unwrap();
 
             if digest.len() != 16 {
-                return Err(ParseError::new("invalid md5 digest"));
+                return Err(Error::digest("invalid md5 digest"));
             }
 
             RecordDigest::MD5(digest)
         },
         Some("bzz") => {
-            let digest_hex = v.next().unwrap();
-            let digest = hex::decode(digest_hex).unwrap();
+            let digest_hex = match v.next() {
+                Some(r) => {
+                    r
+                },
+                None => {
+                    return Err(Error::digest("not a valid digest urn"));
+                },
+            };
+            let digest = match hex::decode(digest_hex) {
+                Ok(r) => {
+                    r
+                },
+                Err(_) => {
+                    return Err(Error::digest("invalid digest hex"));
+                },
+            };
 
             if digest.len() != 32 {
-                return Err(ParseError::new("invalid bzz digest"));
+                return Err(Error::digest("invalid bzz digest"));
             }
             
             RecordDigest::SwarmHash(digest)
         },
+        #[cfg(feature="digest_sha1")]
+        Some("sha1") => {
+            let digest_hex = match v.next() {
+                Some(r) => {
+                    r
+                },
+                None => {
+                    return Err(Error::digest("not a valid digest urn"));
+                },
+            };
+            let digest = match hex::decode(digest_hex) {
+                Ok(r) => {
+                    r
+                },
+                Err(_) => {
+                    return Err(Error::digest("invalid sha1 digest"));
+                },
+            };
+
+            if digest.len() != 20 {
+                return Err(Error::digest("invalid sha1 digest"));
+            }
+
+            RecordDigest::Sha1(digest)
+        },
+        #[cfg(feature="digest_blake3")]
+        Some("blake3") => {
+            let digest_hex = match v.next() {
+                Some(r) => {
+                    r
+                },
+                None => {
+                    return Err(Error::digest("not a valid digest urn"));
+                },
+            };
+            let digest = match hex::decode(digest_hex) {
+                Ok(r) => {
+                    r
+                },
+                Err(_) => {
+                    return Err(Error::digest("invalid blake3 digest"));
+                },
+            };
+
+            if digest.len() != 32 {
+                return Err(Error::digest("invalid blake3 digest"));
+            }
+
+            RecordDigest::Blake3(digest)
+        },
+        #[cfg(feature="digest_ipfs")]
+        Some("ipfs") => {
+            match v.next() {
+                Some(r) => {
+                    RecordDigest::Ipfs(cid_from_str(r)?)
+                },
+                None => {
+                    return Err(Error::digest("not a valid digest urn"));
+                },
+            }
+        },
         Some("") => {
             RecordDigest::Empty
         },
         Some(_) => {
-            return Err(ParseError::new("unknown digest type"));
+            return Err(Error::digest("unknown digest type"));
         },
         None => {
             RecordDigest::Empty
@@ -224,7 +639,83 @@ pub fn from_urn(urn: &str) -> Result<RecordDigest, ParseError> {
 #[cfg(test)]
 mod tests {
     use super::from_urn;
-    use super::ParseError;
+    use super::{
+        DigestType,
+        RecordDigest,
+        DIGEST_BUFFER_SIZE,
+        DIGEST_TYPE_NAMES,
+        digests_for,
+    };
+    use std::str::FromStr;
+    use sha2::{
+        Sha256,
+        Digest,
+    };
+
+    #[test]
+    fn test_digest_type_names() {
+        for v in DIGEST_TYPE_NAMES {
+            let digest_type = DigestType::from_str(v).unwrap();
+            let d = digest_type.digest_for("foo".as_bytes()).unwrap();
+            assert!(d.urn().starts_with(format!("{}:", v).as_str()));
+            assert_eq!(d.digest_type(), Some(digest_type));
+        }
+        assert!(DigestType::from_str("foo").is_err());
+    }
+
+    #[test]
+    fn test_digests_for() {
+        let r = digests_for("foo".as_bytes(), &vec!(DigestType::Sha256, DigestType::Sha512, DigestType::Sha256)).unwrap();
+        assert_eq!(r.len(), 2);
+        assert_eq!(r[0].urn(), "sha256:2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae");
+        assert_eq!(r[1].urn(), "sha512:f7fbba6e0636f890e56fbbf3283e524c6fa3204ae298382d624741d0dc6638326e282c41be5e4254d8820772c5518a2c5a8c0c7f7eda19594a7eb539453e1ed7");
+
+        let data: Vec<u8> = (0..DIGEST_BUFFER_SIZE * 3 + 42).map(|v| v as u8).collect();
+        let d = DigestType::Sha256.digest_for(data.as_slice()).unwrap();
+        assert_eq!(d, RecordDigest::Sha256(Sha256::digest(&data).to_vec()));
+    }
+
+    #[cfg(feature="digest_md5")]
+    #[test]
+    fn test_digests_for_md5() {
+        let d = DigestType::MD5.digest_for("foo".as_bytes()).unwrap();
+        assert_eq!(d.urn(), "md5:acbd18db4cc2f85cedef654fccc4a4d8");
+    }
+
+    #[cfg(feature="digest_bzz")]
+    #[test]
+    fn test_digests_for_bzz() {
+        let data: Vec<u8> = (0..4096 + 31).map(|v| (v % 255) as u8).collect();
+        let r = digests_for(data.as_slice(), &vec!(DigestType::SwarmHash, DigestType::Sha256)).unwrap();
+        assert_eq!(r[0].urn(), "bzz:91699c83ed93a1f87e326a29ccd8cc775323f9e7260035a5f014c975c5f3cd28");
+        assert_eq!(from_urn(&r[0].urn()).unwrap(), r[0]);
+        assert_eq!(r[0].digest_type(), Some(DigestType::SwarmHash));
+    }
+
+    #[cfg(feature="digest_sha1")]
+    #[test]
+    fn test_digests_for_sha1() {
+        let d = DigestType::Sha1.digest_for("foo".as_bytes()).unwrap();
+        assert_eq!(d.urn(), "sha1:0beec7b5ea3f0fdbc95d0dd47f3c5bc275da8a33");
+        assert_eq!(from_urn(&d.urn()).unwrap(), d);
+    }
+
+    #[cfg(feature="digest_blake3")]
+    #[test]
+    fn test_digests_for_blake3() {
+        let d = DigestType::Blake3.digest_for("".as_bytes()).unwrap();
+        assert_eq!(d.urn(), "blake3:af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262");
+        assert_eq!(from_urn(&d.urn()).unwrap(), d);
+    }
+
+    #[cfg(feature="digest_ipfs")]
+    #[test]
+    fn test_digests_for_ipfs() {
+        let d = DigestType::Ipfs.digest_for("".as_bytes()).unwrap();
+        assert_eq!(d.urn(), "ipfs:bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku");
+        assert_eq!(from_urn(&d.urn()).unwrap(), d);
+        assert_eq!(d.digest_type(), Some(DigestType::Ipfs));
+    }
 
     #[test]
     fn test_digest_urn_parse() {
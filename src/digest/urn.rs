@@ -0,0 +1,317 @@
+//! Digest identifiers as URNs and related notations.
+//!
+//! Records are keyed by URNs of the form `urn:<scheme>:<hex>`, following
+//! [RFC 8141](https://www.rfc-editor.org/rfc/rfc8141). The URN is always written in its
+//! canonical form, lowercase and without components, e.g. `urn:sha256:<hex>`.
+//!
+//! When parsing, the following notations of the same digest are accepted as well:
+//!
+//! * Any case for the `urn` scheme, the digest scheme and hexadecimal values, and `?+`, `?=`
+//!   and `#` components after the URN, which are ignored.
+//! * `urn:hash:<params>:<algorithm>:<hex>`, e.g. `urn:hash::sha256:<hex>`.
+//! * Named information URIs after [RFC 6920](https://www.rfc-editor.org/rfc/rfc6920), e.g.
+//!   `ni:///sha-256;<base64url>`.
+//! * [Multihash](https://multiformats.io/multihash/) in hex, as `urn:multihash:<hex>` or with
+//!   the `f` multibase prefix.
+//! * `<scheme>:<hex>` without the `urn` scheme, as understood by
+//!   [from_urn](crate::digest::from_urn).
+use std::str::FromStr;
+
+use data_encoding::BASE64URL_NOPAD;
+
+use crate::error::Error;
+use super::{
+    RecordDigest,
+    from_urn,
+};
+
+/// Multihash code of `sha1`.
+pub const MULTIHASH_SHA1: u64 = 0x11;
+/// Multihash code of `sha2-256`.
+pub const MULTIHASH_SHA256: u64 = 0x12;
+/// Multihash code of `sha2-512`.
+pub const MULTIHASH_SHA512: u64 = 0x13;
+/// Multihash code of `blake3`.
+pub const MULTIHASH_BLAKE3: u64 = 0x1e;
+/// Multihash code of `md5`.
+pub const MULTIHASH_MD5: u64 = 0xd5;
+
+/// Returns true if `s` starts with `prefix`, ignoring case.
+fn has_prefix(s: &str, prefix: &str) -> bool {
+    match s.get(..prefix.len()) {
+        Some(v) => {
+            v.eq_ignore_ascii_case(prefix)
+        },
+        None => {
+            false
+        },
+    }
+}
+
+/// Returns the digest scheme for a hash algorithm name, e.g. `sha256` for `SHA-256`.
+fn scheme_for_algorithm(s: &str) -> String {
+    s.to_lowercase().replace("-", "")
+}
+
+fn read_varint(b: &[u8]) -> Option<(u64, usize)> {
+    let mut r: u64 = 0;
+    for (i, v) in b.iter().enumerate().take(9) {
+        r |= ((v & 0x7f) as u64) << (i * 7);
+        if v & 0x80 == 0 {
+            return Some((r, i + 1));
+        }
+    }
+    None
+}
+
+fn put_varint(w: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        w.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    w.push(v as u8);
+}
+
+/// Create a [RecordDigest](RecordDigest) from a binary multihash.
+pub fn from_multihash(b: &[u8]) -> Result<RecordDigest, Error> {
+    let (code, c) = match read_varint(b) {
+        Some(v) => {
+            v
+        },
+        None => {
+            return Err(Error::digest("invalid multihash code"));
+        },
+    };
+    let (l, cc) = match read_varint(&b[c..]) {
+        Some(v) => {
+            v
+        },
+        None => {
+            return Err(Error::digest("invalid multihash length"));
+        },
+    };
+    let digest = b[c+cc..].to_vec();
+    if digest.len() as u64 != l {
+        return Err(Error::digest("multihash length mismatch"));
+    }
+    let scheme = match code {
+        MULTIHASH_SHA1 => {
+            "sha1"
+        },
+        MULTIHASH_SHA256 => {
+            "sha256"
+        },
+        MULTIHASH_SHA512 => {
+            "sha512"
+        },
+        MULTIHASH_BLAKE3 => {
+            "blake3"
+        },
+        MULTIHASH_MD5 => {
+            "md5"
+        },
+        _ => {
+            return Err(Error::digest("unsupported multihash code"));
+        },
+    };
+    from_urn(&format!("{}:{}", scheme, hex::encode(digest)))
+}
+
+/// Returns the binary multihash of the digest, if the digest has a multihash code.
+pub fn to_multihash(digest: &RecordDigest) -> Option<Vec<u8>> {
+    let code = match digest {
+        #[cfg(feature="digest_sha1")]
+        RecordDigest::Sha1(_) => {
+            MULTIHASH_SHA1
+        },
+        RecordDigest::Sha256(_) => {
+            MULTIHASH_SHA256
+        },
+        RecordDigest::Sha512(_) => {
+            MULTIHASH_SHA512
+        },
+        #[cfg(feature="digest_blake3")]
+        RecordDigest::Blake3(_) => {
+            MULTIHASH_BLAKE3
+        },
+        RecordDigest::MD5(_) => {
+            MULTIHASH_MD5
+        },
+        _ => {
+            return None;
+        },
+    };
+    let v = digest.fingerprint();
+    let mut r: Vec<u8> = vec!();
+    put_varint(&mut r, code);
+    put_varint(&mut r, v.len() as u64);
+    r.extend(v);
+    Some(r)
+}
+
+fn from_multihash_hex(s: &str) -> Result<RecordDigest, Error> {
+    let s = s.strip_prefix("f").unwrap_or(s);
+    match hex::decode(s) {
+        Ok(v) => {
+            from_multihash(&v)
+        },
+        Err(_) => {
+            Err(Error::digest("invalid multihash hex"))
+        },
+    }
+}
+
+/// Parse a named information URI, e.g. `ni:///sha-256;<base64url>`.
+///
+/// Truncated hash algorithms, e.g. `sha-256-128`, are not supported.
+pub fn from_ni(s: &str) -> Result<RecordDigest, Error> {
+    if !has_prefix(s, "ni://") {
+        return Err(Error::digest("not a named information uri"));
+    }
+    let path = match s[5..].split_once('/') {
+        Some((_, v)) => {
+            v
+        },
+        None => {
+            return Err(Error::digest("named information uri missing path"));
+        },
+    };
+    let path = path.split('?').next().unwrap();
+    let (alg, value) = match path.split_once(';') {
+        Some(v) => {
+            v
+        },
+        None => {
+            return Err(Error::digest("named information uri missing digest"));
+        },
+    };
+    let digest = match BASE64URL_NOPAD.decode(value.trim_end_matches('=').as_bytes()) {
+        Ok(v) => {
+            v
+        },
+        Err(_) => {
+            return Err(Error::digest("invalid named information digest encoding"));
+        },
+    };
+    match alg.to_lowercase().as_str() {
+        "sha-256" | "sha-512" => {
+            from_urn(&format!("{}:{}", scheme_for_algorithm(alg), hex::encode(digest)))
+        },
+        _ => {
+            Err(Error::digest("unsupported named information hash algorithm"))
+        },
+    }
+}
+
+/// Returns the named information URI of the digest, if it has a named information algorithm.
+pub fn to_ni(digest: &RecordDigest) -> Option<String> {
+    let alg = match digest {
+        RecordDigest::Sha256(_) => {
+            "sha-256"
+        },
+        RecordDigest::Sha512(_) => {
+            "sha-512"
+        },
+        _ => {
+            return None;
+        },
+    };
+    Some(format!("ni:///{};{}", alg, BASE64URL_NOPAD.encode(&digest.fingerprint())))
+}
+
+/// Parse a digest from any of the supported notations.
+pub fn parse(s: &str) -> Result<RecordDigest, Error> {
+    let s = s.trim();
+    if has_prefix(s, "ni:") {
+        return from_ni(s);
+    }
+    if !has_prefix(s, "urn:") {
+        return from_urn(s);
+    }
+
+    let urn = match ::urn::Urn::from_str(s) {
+        Ok(v) => {
+            v
+        },
+        Err(e) => {
+            return Err(Error::digest(&format!("invalid urn: {}", e)));
+        },
+    };
+    let nid = urn.nid().to_lowercase();
+    match nid.as_str() {
+        "hash" => {
+            let mut v = urn.nss().rsplitn(3, ':');
+            let value = v.next().unwrap();
+            match v.next() {
+                Some(alg) => {
+                    from_urn(&format!("{}:{}", scheme_for_algorithm(alg), value))
+                },
+                None => {
+                    Err(Error::digest("hash urn missing algorithm"))
+                },
+            }
+        },
+        "multihash" => {
+            from_multihash_hex(urn.nss())
+        },
+        _ => {
+            from_urn(&format!("{}:{}", nid, urn.nss()))
+        },
+    }
+}
+
+/// Returns the canonical URN of the digest, e.g. `urn:sha256:<hex>`.
+pub fn to_urn(digest: &RecordDigest) -> String {
+    format!("urn:{}", digest.urn())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse,
+        to_urn,
+        to_ni,
+        to_multihash,
+        from_multihash,
+    };
+    use crate::digest::RecordDigest;
+
+    const SHA256_HEX: &str = "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae";
+
+    #[test]
+    fn test_urn_parse() {
+        let d = RecordDigest::Sha256(hex::decode(SHA256_HEX).unwrap());
+        let canonical = format!("urn:sha256:{}", SHA256_HEX);
+        assert_eq!(to_urn(&d), canonical);
+
+        for v in [
+            canonical.clone(),
+            format!("URN:SHA256:{}", SHA256_HEX.to_uppercase()),
+            format!("urn:sha256:{}?=foo#bar", SHA256_HEX),
+            format!("sha256:{}", SHA256_HEX),
+            format!("urn:hash::sha256:{}", SHA256_HEX),
+            format!("urn:hash:foo=bar:SHA-256:{}", SHA256_HEX),
+            String::from("ni:///sha-256;LCa0a2j_xo_5m0U8HTBBNBNCLXBkg7-g-YpeiGJm564"),
+            String::from("ni://example.com/sha-256;LCa0a2j_xo_5m0U8HTBBNBNCLXBkg7-g-YpeiGJm564?ct=text/plain"),
+            format!("urn:multihash:1220{}", SHA256_HEX),
+            format!("urn:multihash:f1220{}", SHA256_HEX),
+        ] {
+            assert_eq!(parse(&v).unwrap(), d, "{}", v);
+        }
+
+        assert!(parse("urn:sha256:foo").is_err());
+        assert!(parse("urn:foo:deadbeef").is_err());
+        assert!(parse("urn:").is_err());
+        assert!(parse("ni:///sha-256-128;LCa0a2j_xo_5m0U8HTBBNA").is_err());
+        assert!(parse("urn:multihash:1221").is_err());
+    }
+
+    #[test]
+    fn test_urn_format() {
+        let d = RecordDigest::Sha256(hex::decode(SHA256_HEX).unwrap());
+        assert_eq!(to_ni(&d).unwrap(), "ni:///sha-256;LCa0a2j_xo_5m0U8HTBBNBNCLXBkg7-g-YpeiGJm564");
+        let mh = to_multihash(&d).unwrap();
+        assert_eq!(hex::encode(&mh), format!("1220{}", SHA256_HEX));
+        assert_eq!(from_multihash(&mh).unwrap(), d);
+    }
+}
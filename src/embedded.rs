@@ -0,0 +1,1372 @@
+//! Read metadata embedded in the contents of media files.
+//!
+//! Supported containers are PDF (Info dictionary and XMP packet), EPUB (Dublin Core in the OPF
+//! package document), MP3 (ID3v2 tags), and FLAC, Ogg Vorbis and Opus (Vorbis comments).
+//!
+//! Only available with the `embedded` feature.
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{
+    BufReader,
+    BufWriter,
+    Read,
+    Seek,
+    SeekFrom,
+    Write,
+};
+use std::path;
+
+use biblatex::EntryType;
+use id3::TagLike;
+use lopdf::{
+    dictionary,
+    Dictionary,
+    Document,
+    Object,
+    Stream,
+    StringFormat,
+};
+use log::{
+    debug,
+    warn,
+};
+
+use crate::cache::DigestCache;
+use crate::dc::Creator;
+use crate::dc::creator::parse_source_list;
+use crate::digest;
+use crate::error::{
+    Error,
+    Format,
+};
+use crate::meta::{
+    MetaData,
+    digests_from_path,
+    publish_date_from_str,
+    publish_date_to_string,
+};
+
+const NS_DC: &str = "http://purl.org/dc/elements/1.1/";
+const NS_RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const NS_OPF: &str = "http://www.idpf.org/2007/opf";
+
+/// XMP packet to add metadata to, for PDF documents that have none.
+const XMP_EMPTY: &str = "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>
+<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">
+ <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">
+  <rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end=\"w\"?>";
+
+/// Media containers that metadata can be read from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Container {
+    Pdf,
+    Epub,
+    /// MP3, or any other content prefixed with an ID3v2 tag.
+    Id3,
+    Flac,
+    /// Ogg Vorbis or Opus.
+    Ogg,
+}
+
+impl Container {
+    /// Identify the container from the first bytes of the file.
+    ///
+    /// EPUB files are identified by the uncompressed `mimetype` entry that must be the first in
+    /// the archive.
+    pub fn from_magic(b: &[u8]) -> Option<Container> {
+        if b.starts_with(b"%PDF-") {
+            return Some(Container::Pdf);
+        }
+        if b.starts_with(b"PK\x03\x04") && b.get(30..58) == Some(&b"mimetypeapplication/epub+zip"[..]) {
+            return Some(Container::Epub);
+        }
+        if b.starts_with(b"ID3") {
+            return Some(Container::Id3);
+        }
+        if b.starts_with(b"fLaC") {
+            return Some(Container::Flac);
+        }
+        if b.starts_with(b"OggS") {
+            return Some(Container::Ogg);
+        }
+        None
+    }
+
+    /// Returns the MIME type of the container.
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Container::Pdf => {
+                "application/pdf"
+            },
+            Container::Epub => {
+                "application/epub+zip"
+            },
+            Container::Id3 => {
+                "audio/mpeg"
+            },
+            Container::Flac => {
+                "audio/flac"
+            },
+            Container::Ogg => {
+                "audio/ogg"
+            },
+        }
+    }
+}
+
+fn embedded_error(container: &str, e: impl std::fmt::Display) -> Error {
+    Error::parse(Format::Embedded, &format!("{}: {}", container, e))
+}
+
+/// Identify the container of the file from its first bytes.
+///
+/// Returns `None` if the file is not in any of the supported containers.
+pub fn container_from_path(filepath: &path::Path) -> Result<Option<Container>, Error> {
+    let mut b: Vec<u8> = vec!();
+    File::open(filepath)?.take(58).read_to_end(&mut b)?;
+    Ok(Container::from_magic(&b))
+}
+
+fn set_creators(m: &mut MetaData, values: &[String]) {
+    let mut creators: Vec<Creator> = vec!();
+    for v in values {
+        for s in v.split(';') {
+            creators.append(&mut parse_source_list(s));
+        }
+    }
+    if !creators.is_empty() {
+        m.set_creators(creators);
+    }
+}
+
+fn set_language(m: &mut MetaData, v: &str) {
+    if let Err(e) = m.set_language(v) {
+        warn!("skipping invalid embedded language: {}", e);
+    }
+}
+
+/// Set the publication date from an ISO 8601 date or date and time.
+///
+/// Falls back to the year only, if the rest of the date is invalid.
+fn set_date(m: &mut MetaData, v: &str) {
+    let s = v.trim().split('T').next().unwrap_or("");
+    let r = publish_date_from_str(s)
+        .or_else(|_| {
+            publish_date_from_str(s.get(..4).unwrap_or(""))
+        });
+    match r {
+        Ok(date) => {
+            m.set_publish_date(date);
+        },
+        Err(e) => {
+            warn!("skipping invalid embedded date: {}", e);
+        },
+    };
+}
+
+/// Returns the text values of the Dublin Core element with the given name.
+///
+/// Values in RDF containers, as used by XMP, are returned one per list item.
+fn dc_values(doc: &roxmltree::Document, name: &str) -> Vec<String> {
+    let mut r: Vec<String> = vec!();
+    for n in doc.descendants().filter(|v| v.has_tag_name((NS_DC, name))) {
+        let mut items: Vec<roxmltree::Node> = n.descendants()
+            .filter(|v| {
+                v.has_tag_name((NS_RDF, "li"))
+            })
+            .collect();
+        if items.is_empty() {
+            items.push(n);
+        }
+        for v in items {
+            let s: String = v.descendants()
+                .filter(|v| {
+                    v.is_text()
+                })
+                .filter_map(|v| {
+                    v.text()
+                })
+                .collect();
+            if !s.trim().is_empty() {
+                r.push(String::from(s.trim()));
+            }
+        }
+    }
+    r
+}
+
+/// Apply the Dublin Core elements of an XMP packet or OPF package document to the metadata.
+///
+/// Values already in the metadata are only replaced by values that are present in the document.
+fn apply_dc(m: &mut MetaData, src: &str) -> Result<(), Error> {
+    let doc = roxmltree::Document::parse(src).map_err(|e| embedded_error("xml", e))?;
+    if let Some(v) = dc_values(&doc, "title").first() {
+        m.set_title(v);
+    }
+    set_creators(m, &dc_values(&doc, "creator"));
+    for v in dc_values(&doc, "subject") {
+        m.add_subject(&v);
+    }
+    if let Some(v) = dc_values(&doc, "language").first() {
+        set_language(m, v);
+    }
+    if let Some(v) = dc_values(&doc, "date").first() {
+        set_date(m, v);
+    }
+    if let Some(v) = dc_values(&doc, "publisher").first() {
+        m.set_publisher(v);
+    }
+    Ok(())
+}
+
+/// Decode a PDF text string, either UTF-16BE with byte order mark or PDFDocEncoding.
+///
+/// PDFDocEncoding is read as Latin-1, which it matches for all printable characters but a few.
+fn pdf_string(o: &Object) -> Option<String> {
+    let b = o.as_str().ok()?;
+    let s: String = match b.strip_prefix(&[0xfe, 0xff]) {
+        Some(v) => {
+            let u: Vec<u16> = v.chunks(2)
+                .map(|c| {
+                    ((c[0] as u16) << 8) | *c.get(1).unwrap_or(&0) as u16
+                })
+                .collect();
+            String::from_utf16_lossy(&u)
+        },
+        None => {
+            b.iter()
+                .map(|c| {
+                    *c as char
+                })
+                .collect()
+        },
+    };
+    match s.trim() {
+        "" => {
+            None
+        },
+        v => {
+            Some(String::from(v))
+        },
+    }
+}
+
+/// Convert a PDF date, e.g. `D:20081031120000Z`, to an ISO 8601 date.
+fn pdf_date(s: &str) -> String {
+    let digits: String = s.trim_start_matches("D:").chars()
+        .take_while(|c| {
+            c.is_ascii_digit()
+        })
+        .take(8)
+        .collect();
+    let mut r = String::from(digits.get(..4).unwrap_or(&digits));
+    for v in [digits.get(4..6), digits.get(6..8)].into_iter().flatten() {
+        r.push('-');
+        r.push_str(v);
+    }
+    r
+}
+
+fn apply_pdf_info(m: &mut MetaData, info: &Dictionary, doc: &Document) {
+    let value = |k: &[u8]| {
+        info.get_deref(k, doc).ok().and_then(pdf_string)
+    };
+    if let Some(v) = value(b"Title") {
+        m.set_title(&v);
+    }
+    if let Some(v) = value(b"Author") {
+        set_creators(m, &[v]);
+    }
+    if let Some(v) = value(b"Keywords") {
+        for k in v.split([',', ';']) {
+            m.add_subject(k);
+        }
+    }
+    if let Some(v) = value(b"CreationDate") {
+        set_date(m, &pdf_date(&v));
+    }
+}
+
+/// Returns the XMP packet referenced by the document catalog, if any.
+fn pdf_xmp(doc: &Document) -> Option<String> {
+    let catalog = doc.trailer.get_deref(b"Root", doc).and_then(Object::as_dict).ok()?;
+    let stream = catalog.get_deref(b"Metadata", doc).and_then(Object::as_stream).ok()?;
+    let content = match stream.dict.has(b"Filter") {
+        true => {
+            stream.decompressed_content().ok()?
+        },
+        false => {
+            stream.content.clone()
+        },
+    };
+    String::from_utf8(content).ok()
+}
+
+/// Read metadata from the Info dictionary and XMP packet of a PDF document.
+///
+/// Values in the XMP packet take precedence over those in the Info dictionary.
+pub fn read_pdf(r: impl Read) -> Result<MetaData, Error> {
+    let doc = Document::load_from(r).map_err(|e| embedded_error("pdf", e))?;
+    let mut m = MetaData::new("", "", EntryType::Misc, digest::RecordDigest::Empty, None);
+    match doc.trailer.get_deref(b"Info", &doc).and_then(Object::as_dict) {
+        Ok(info) => {
+            apply_pdf_info(&mut m, info, &doc);
+        },
+        Err(_) => {
+            debug!("no info dictionary in pdf");
+        },
+    };
+    match pdf_xmp(&doc) {
+        Some(v) => {
+            apply_dc(&mut m, &v)?;
+        },
+        None => {
+            debug!("no xmp metadata in pdf");
+        },
+    };
+    Ok(m)
+}
+
+fn zip_string<R: Read + Seek>(z: &mut zip::ZipArchive<R>, name: &str) -> Result<String, Error> {
+    let mut f = z.by_name(name).map_err(|e| embedded_error("epub", e))?;
+    let mut s = String::new();
+    f.read_to_string(&mut s)?;
+    Ok(s)
+}
+
+/// Read the Dublin Core metadata from the package document of an EPUB publication.
+pub fn read_epub(r: impl Read + Seek) -> Result<MetaData, Error> {
+    let mut z = zip::ZipArchive::new(r).map_err(|e| embedded_error("epub", e))?;
+    let container = zip_string(&mut z, "META-INF/container.xml")?;
+    let doc = roxmltree::Document::parse(&container).map_err(|e| embedded_error("epub", e))?;
+    let opf_path = doc.descendants()
+        .find(|v| {
+            v.has_tag_name("rootfile")
+        })
+        .and_then(|v| {
+            v.attribute("full-path")
+        })
+        .ok_or_else(|| {
+            Error::parse(Format::Embedded, "epub: no package document")
+        })?;
+    let opf = zip_string(&mut z, opf_path)?;
+
+    let mut m = MetaData::new("", "", EntryType::Book, digest::RecordDigest::Empty, None);
+    apply_dc(&mut m, &opf)?;
+    Ok(m)
+}
+
+/// Read metadata from the ID3v2 tag at the start of an MP3 file.
+pub fn read_id3(r: impl Read + Seek) -> Result<MetaData, Error> {
+    let tag = id3::Tag::read_from2(r).map_err(|e| embedded_error("id3", e))?;
+    let mut m = MetaData::new("", "", EntryType::Misc, digest::RecordDigest::Empty, None);
+    if let Some(v) = tag.title() {
+        m.set_title(v);
+    }
+    if let Some(v) = tag.artists() {
+        let artists: Vec<String> = v.iter()
+            .map(|s| {
+                String::from(*s)
+            })
+            .collect();
+        set_creators(&mut m, &artists);
+    }
+    for v in tag.genres().unwrap_or(vec!()) {
+        m.add_subject(v);
+    }
+    if let Some(v) = tag.get("TLAN").and_then(|v| v.content().text()) {
+        set_language(&mut m, v);
+    }
+    if let Some(v) = tag.get("TPUB").and_then(|v| v.content().text()) {
+        m.set_publisher(v);
+    }
+    match tag.date_released().or(tag.date_recorded()) {
+        Some(v) => {
+            m.set_publish_date((v.day.unwrap_or(0), v.month.unwrap_or(0), v.year as u32));
+        },
+        None => {
+            if let Some(v) = tag.year() {
+                m.set_publish_date((0, 0, v as u32));
+            }
+        },
+    };
+    Ok(m)
+}
+
+fn read_u32_le(b: &[u8], pos: &mut usize) -> Result<u32, Error> {
+    let v = b.get(*pos..*pos+4).ok_or_else(|| {
+        Error::parse(Format::Embedded, "vorbis: truncated comment header")
+    })?;
+    *pos += 4;
+    Ok(u32::from_le_bytes([v[0], v[1], v[2], v[3]]))
+}
+
+/// Parse a Vorbis comment header, without framing, into the vendor string, and uppercase keys
+/// and their values.
+fn parse_vorbis_comments(b: &[u8]) -> Result<(String, Vec<(String, String)>), Error> {
+    let mut pos: usize = 0;
+    let vendor_len = read_u32_le(b, &mut pos)? as usize;
+    let vendor = b.get(pos..pos+vendor_len).ok_or_else(|| {
+        Error::parse(Format::Embedded, "vorbis: truncated vendor")
+    })?;
+    let vendor = String::from_utf8_lossy(vendor).into_owned();
+    pos += vendor_len;
+    let count = read_u32_le(b, &mut pos)?;
+    let mut r: Vec<(String, String)> = vec!();
+    for _ in 0..count {
+        let len = read_u32_le(b, &mut pos)? as usize;
+        let v = b.get(pos..pos+len).ok_or_else(|| {
+            Error::parse(Format::Embedded, "vorbis: truncated comment")
+        })?;
+        pos += len;
+        let s = String::from_utf8_lossy(v);
+        match s.split_once('=') {
+            Some((k, v)) => {
+                r.push((k.to_uppercase(), String::from(v.trim())));
+            },
+            None => {
+                debug!("skipping invalid vorbis comment {:?}", s);
+            },
+        };
+    }
+    Ok((vendor, r))
+}
+
+fn apply_vorbis_comments(m: &mut MetaData, comments: &[(String, String)]) {
+    let mut artists: Vec<String> = vec!();
+    for (k, v) in comments {
+        match k.as_str() {
+            "TITLE" => {
+                m.set_title(v);
+            },
+            "ARTIST" => {
+                artists.push(v.clone());
+            },
+            "GENRE" => {
+                m.add_subject(v);
+            },
+            "LANGUAGE" => {
+                set_language(m, v);
+            },
+            "DATE" => {
+                set_date(m, v);
+            },
+            "ORGANIZATION" | "LABEL" => {
+                m.set_publisher(v);
+            },
+            _ => {},
+        };
+    }
+    set_creators(m, &artists);
+}
+
+/// Read metadata from the Vorbis comment block of a FLAC file.
+pub fn read_flac(mut r: impl Read + Seek) -> Result<MetaData, Error> {
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    if &b != b"fLaC" {
+        return Err(Error::parse(Format::Embedded, "flac: invalid stream marker"));
+    }
+    loop {
+        r.read_exact(&mut b)?;
+        let last = b[0] & 0x80 > 0;
+        let len = u32::from_be_bytes([0, b[1], b[2], b[3]]);
+        if b[0] & 0x7f == 4 {
+            let mut block = vec![0u8; len as usize];
+            r.read_exact(&mut block)?;
+            let mut m = MetaData::new("", "", EntryType::Misc, digest::RecordDigest::Empty, None);
+            apply_vorbis_comments(&mut m, &parse_vorbis_comments(&block)?.1);
+            return Ok(m);
+        }
+        if last {
+            break;
+        }
+        r.seek(SeekFrom::Current(len as i64))?;
+    }
+    Err(Error::parse(Format::Embedded, "flac: no vorbis comment block"))
+}
+
+/// Read metadata from the comment header of an Ogg Vorbis or Opus stream.
+pub fn read_ogg(r: impl Read + Seek) -> Result<MetaData, Error> {
+    let mut rr = ogg::PacketReader::new(r);
+    let mut packets: Vec<Vec<u8>> = vec!();
+    while packets.len() < 2 {
+        match rr.read_packet().map_err(|e| embedded_error("ogg", e))? {
+            Some(v) => {
+                packets.push(v.data);
+            },
+            None => {
+                return Err(Error::parse(Format::Embedded, "ogg: missing comment header"));
+            },
+        };
+    }
+    let comments = match (packets[0].get(..7), packets[0].get(..8)) {
+        (Some(b"\x01vorbis"), _) => {
+            packets[1].strip_prefix(b"\x03vorbis")
+        },
+        (_, Some(b"OpusHead")) => {
+            packets[1].strip_prefix(b"OpusTags")
+        },
+        _ => {
+            return Err(Error::parse(Format::Embedded, "ogg: unsupported codec"));
+        },
+    };
+    let comments = comments.ok_or_else(|| {
+        Error::parse(Format::Embedded, "ogg: invalid comment header")
+    })?;
+    let mut m = MetaData::new("", "", EntryType::Misc, digest::RecordDigest::Empty, None);
+    apply_vorbis_comments(&mut m, &parse_vorbis_comments(comments)?.1);
+    Ok(m)
+}
+
+/// Read metadata embedded in content of the given container.
+pub fn read(r: impl Read + Seek, container: Container) -> Result<MetaData, Error> {
+    let mut m = match container {
+        Container::Pdf => {
+            read_pdf(r)?
+        },
+        Container::Epub => {
+            read_epub(r)?
+        },
+        Container::Id3 => {
+            read_id3(r)?
+        },
+        Container::Flac => {
+            read_flac(r)?
+        },
+        Container::Ogg => {
+            read_ogg(r)?
+        },
+    };
+    m.set_mime_str(container.mime())?;
+    Ok(m)
+}
+
+/// Read metadata embedded in the file, keyed by the digests of the file.
+///
+/// Will return [Error::Parse](Error::Parse) if the file is not in a supported container, or if
+/// no title is embedded.
+///
+/// # Arguments
+///
+/// * `filepath` - file to read metadata from.
+/// * `digest_types` - digests to calculate for the file.
+/// * `cache` - optional digest cache to use.
+pub fn from_path(filepath: &path::Path, digest_types: &Vec<digest::DigestType>, cache: Option<&DigestCache>) -> Result<MetaData, Error> {
+    let container = match container_from_path(filepath)? {
+        Some(v) => {
+            v
+        },
+        None => {
+            return Err(Error::parse(Format::Embedded, "unsupported container"));
+        },
+    };
+    let f = File::open(filepath)?;
+    let mut m = read(BufReader::new(f), container)?;
+    if !m.validate() {
+        return Err(Error::parse(Format::Embedded, "title missing"));
+    }
+
+    for v in digests_from_path(filepath, digest_types, cache)? {
+        m.add_digest(v);
+    }
+    debug!("read embedded {:?} metadata {:?} for file {:?}", container, &m, filepath);
+    Ok(m)
+}
+
+/// Dublin Core elements written by [to_path](to_path), and their values in the record.
+///
+/// Elements the record has no value for are left out, so that the embedded values are kept.
+fn dc_record_values(m: &MetaData) -> Vec<(&'static str, Vec<String>)> {
+    let mut r: Vec<(&'static str, Vec<String>)> = vec!();
+    r.push(("title", vec!(m.title())));
+    r.push(("creator", m.creators().iter().map(|v| v.to_bibtex()).collect()));
+    r.push(("subject", m.subjects()));
+    r.push(("language", m.language().iter().map(|v| v.to_string()).collect()));
+    r.push(("date", m.publish_date().iter().map(publish_date_to_string).collect()));
+    r.push(("publisher", m.publisher().into_iter().collect()));
+    r.into_iter()
+        .filter(|(_, v)| {
+            !v.is_empty() && !v[0].is_empty()
+        })
+        .collect()
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Replace the Dublin Core elements the record has values for in the XML document, and insert
+/// the new elements at the end of the first element matching `parent`.
+///
+/// The rest of the document is left as it is.
+fn splice_dc(src: &str, m: &MetaData, parent: (&str, &str), format: fn(&str, Option<&str>, &[String]) -> String) -> Result<String, Error> {
+    let doc = roxmltree::Document::parse(src).map_err(|e| embedded_error("xml", e))?;
+    let values = dc_record_values(m);
+    let parent = doc.descendants()
+        .find(|v| {
+            v.has_tag_name(parent)
+        })
+        .ok_or_else(|| {
+            Error::parse(Format::Embedded, &format!("xml: no {} element", parent.1))
+        })?;
+    let prefix = parent.lookup_prefix(NS_DC);
+
+    let mut remove: Vec<std::ops::Range<usize>> = doc.descendants()
+        .filter(|v| {
+            v.tag_name().namespace() == Some(NS_DC) && values.iter().any(|(k, _)| v.tag_name().name() == *k)
+        })
+        .map(|v| {
+            v.range()
+        })
+        .collect();
+    remove.sort_by_key(|v| v.start);
+
+    let mut insert = String::new();
+    for (k, v) in values.iter() {
+        insert.push_str(&format(k, prefix, v));
+    }
+
+    let range = parent.range();
+    let tag = &src[range.clone()];
+    let (at, close) = match tag.ends_with("/>") {
+        true => {
+            let name: String = tag[1..].chars()
+                .take_while(|c| {
+                    !c.is_whitespace() && *c != '/' && *c != '>'
+                })
+                .collect();
+            insert.insert(0, '>');
+            (range.end - 2, format!("</{}>", name))
+        },
+        false => {
+            (range.start + tag.rfind("</").unwrap_or(tag.len()), String::new())
+        },
+    };
+
+    let mut r = String::new();
+    let mut pos: usize = 0;
+    for v in remove.iter() {
+        if v.start < pos || v.end > at {
+            continue;
+        }
+        r.push_str(&src[pos..v.start]);
+        pos = v.end;
+    }
+    r.push_str(&src[pos..at]);
+    r.push_str(&insert);
+    r.push_str(&close);
+    pos = at;
+    if !close.is_empty() {
+        pos = range.end;
+    }
+    for v in remove.iter() {
+        if v.start < pos {
+            continue;
+        }
+        r.push_str(&src[pos..v.start]);
+        pos = v.end;
+    }
+    r.push_str(&src[pos..]);
+    Ok(r)
+}
+
+fn dc_tag(name: &str, prefix: Option<&str>) -> (String, String) {
+    match prefix {
+        Some(p) => {
+            (format!("<{}:{}>", p, name), format!("</{}:{}>", p, name))
+        },
+        None => {
+            (format!("<dc:{} xmlns:dc=\"{}\">", name, NS_DC), format!("</dc:{}>", name))
+        },
+    }
+}
+
+/// Format a Dublin Core element of an OPF package document, one element per value.
+fn format_opf(name: &str, prefix: Option<&str>, values: &[String]) -> String {
+    let (open, close) = dc_tag(name, prefix);
+    values.iter()
+        .map(|v| {
+            format!("\n    {}{}{}", open, xml_escape(v), close)
+        })
+        .collect()
+}
+
+/// Format a Dublin Core property of an XMP packet, with its values in an RDF container.
+fn format_xmp(name: &str, prefix: Option<&str>, values: &[String]) -> String {
+    let (open, close) = dc_tag(name, prefix);
+    let (container, lang) = match name {
+        "title" => {
+            ("Alt", " xml:lang=\"x-default\"")
+        },
+        "creator" | "date" => {
+            ("Seq", "")
+        },
+        _ => {
+            ("Bag", "")
+        },
+    };
+    let items: String = values.iter()
+        .map(|v| {
+            format!("<rdf:li{}>{}</rdf:li>", lang, xml_escape(v))
+        })
+        .collect();
+    format!("\n   {}<rdf:{}>{}</rdf:{}>{}", open, container, items, container, close)
+}
+
+/// Write a new version of the file next to it, and replace the file with it.
+///
+/// The new file gets the permissions of the original. Extended attributes are not kept.
+fn replace_file(filepath: &path::Path, write: impl FnOnce(&mut BufWriter<File>) -> Result<(), Error>) -> Result<(), Error> {
+    let filename = match filepath.file_name() {
+        Some(v) => {
+            v.to_string_lossy().into_owned()
+        },
+        None => {
+            return Err(Error::parse(Format::Embedded, "path has no file name"));
+        },
+    };
+    let tmp = filepath.with_file_name(format!(".{}.kitab", filename));
+    let mut w = BufWriter::new(File::create(&tmp)?);
+    let r = write(&mut w)
+        .and_then(|_| {
+            w.flush()?;
+            w.get_ref().sync_all()?;
+            fs::set_permissions(&tmp, fs::metadata(filepath)?.permissions())?;
+            fs::rename(&tmp, filepath)?;
+            Ok(())
+        });
+    if r.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    r
+}
+
+/// Encode a PDF text string, as UTF-16BE with byte order mark if not all ASCII.
+fn pdf_text(s: &str) -> Object {
+    if s.is_ascii() {
+        return Object::string_literal(s);
+    }
+    let mut b: Vec<u8> = vec!(0xfe, 0xff);
+    for c in s.encode_utf16() {
+        b.extend(c.to_be_bytes());
+    }
+    Object::String(b, StringFormat::Hexadecimal)
+}
+
+/// Write the record to the Info dictionary and XMP packet of a PDF document.
+pub fn write_pdf(m: &MetaData, filepath: &path::Path) -> Result<(), Error> {
+    let mut doc = Document::load(filepath).map_err(|e| embedded_error("pdf", e))?;
+
+    let mut info = Dictionary::new();
+    info.set("Title", pdf_text(&m.title()));
+    let creators: Vec<String> = m.creators().iter()
+        .map(|v| {
+            v.to_bibtex()
+        })
+        .collect();
+    if !creators.is_empty() {
+        info.set("Author", pdf_text(&creators.join("; ")));
+    }
+    if !m.subjects().is_empty() {
+        info.set("Keywords", pdf_text(&m.subjects().join(", ")));
+    }
+    match doc.trailer.get(b"Info").and_then(Object::as_reference) {
+        Ok(id) => {
+            doc.get_object_mut(id)
+                .and_then(Object::as_dict_mut)
+                .map_err(|e| embedded_error("pdf", e))?
+                .extend(&info);
+        },
+        Err(_) => {
+            let id = doc.add_object(info);
+            doc.trailer.set("Info", id);
+        },
+    };
+
+    let catalog_id = doc.trailer.get(b"Root")
+        .and_then(Object::as_reference)
+        .map_err(|e| embedded_error("pdf", e))?;
+    let metadata_id = doc.get_dictionary(catalog_id)
+        .and_then(|v| v.get(b"Metadata"))
+        .and_then(Object::as_reference)
+        .ok();
+    let xmp = match pdf_xmp(&doc) {
+        Some(v) => {
+            v
+        },
+        None => {
+            String::from(XMP_EMPTY)
+        },
+    };
+    let xmp = splice_dc(&xmp, m, (NS_RDF, "Description"), format_xmp)?;
+    match metadata_id {
+        Some(id) => {
+            doc.get_object_mut(id)
+                .and_then(Object::as_stream_mut)
+                .map_err(|e| embedded_error("pdf", e))?
+                .set_plain_content(xmp.into_bytes());
+        },
+        None => {
+            let stream = Stream::new(dictionary! {
+                "Type" => "Metadata",
+                "Subtype" => "XML",
+            }, xmp.into_bytes());
+            let id = doc.add_object(stream);
+            doc.get_object_mut(catalog_id)
+                .and_then(Object::as_dict_mut)
+                .map_err(|e| embedded_error("pdf", e))?
+                .set("Metadata", id);
+        },
+    };
+
+    replace_file(filepath, |w| {
+        doc.save_to(w).map_err(|e| embedded_error("pdf", e))
+    })
+}
+
+/// Write the record to the Dublin Core elements of the package document of an EPUB publication.
+///
+/// All other entries of the archive are copied as they are.
+pub fn write_epub(m: &MetaData, filepath: &path::Path) -> Result<(), Error> {
+    let mut z = zip::ZipArchive::new(BufReader::new(File::open(filepath)?)).map_err(|e| embedded_error("epub", e))?;
+    let container = zip_string(&mut z, "META-INF/container.xml")?;
+    let doc = roxmltree::Document::parse(&container).map_err(|e| embedded_error("epub", e))?;
+    let opf_path = doc.descendants()
+        .find(|v| {
+            v.has_tag_name("rootfile")
+        })
+        .and_then(|v| {
+            v.attribute("full-path")
+        })
+        .ok_or_else(|| {
+            Error::parse(Format::Embedded, "epub: no package document")
+        })?;
+    let opf = zip_string(&mut z, opf_path)?;
+    let opf = splice_dc(&opf, m, (NS_OPF, "metadata"), format_opf)?;
+
+    replace_file(filepath, |w| {
+        let mut zw = zip::ZipWriter::new(w);
+        for i in 0..z.len() {
+            let f = z.by_index_raw(i).map_err(|e| embedded_error("epub", e))?;
+            if f.name() == opf_path {
+                let options = zip::write::FileOptions::default()
+                    .compression_method(f.compression());
+                zw.start_file(f.name(), options).map_err(|e| embedded_error("epub", e))?;
+                zw.write_all(opf.as_bytes())?;
+                continue;
+            }
+            zw.raw_copy_file(f).map_err(|e| embedded_error("epub", e))?;
+        }
+        zw.finish().map_err(|e| embedded_error("epub", e))?;
+        Ok(())
+    })
+}
+
+/// Write the record to the ID3v2 tag of an MP3 file.
+pub fn write_id3(m: &MetaData, filepath: &path::Path) -> Result<(), Error> {
+    let mut tag = id3::Tag::read_from_path(filepath).map_err(|e| embedded_error("id3", e))?;
+    for (k, v) in dc_record_values(m) {
+        let v = v.join("\0");
+        match k {
+            "title" => {
+                tag.set_title(v);
+            },
+            "creator" => {
+                tag.set_artist(v);
+            },
+            "subject" => {
+                tag.set_genre(v);
+            },
+            "language" => {
+                tag.set_text("TLAN", v);
+            },
+            "publisher" => {
+                tag.set_text("TPUB", v);
+            },
+            _ => {},
+        };
+    }
+    if let Some((d, mo, y)) = m.publish_date() {
+        tag.set_date_released(id3::Timestamp{
+            year: y as i32,
+            month: Some(mo).filter(|v| *v > 0),
+            day: Some(d).filter(|v| *v > 0),
+            hour: None,
+            minute: None,
+            second: None,
+        });
+    }
+    tag.write_to_path(filepath, id3::Version::Id3v24).map_err(|e| embedded_error("id3", e))
+}
+
+/// Returns the Vorbis comment header with the values of the record replacing those of the
+/// existing comments.
+fn vorbis_comments_with_record(m: &MetaData, vendor: &str, comments: Vec<(String, String)>) -> Vec<u8> {
+    let mut values: Vec<(&str, Vec<String>)> = vec!();
+    for (k, v) in dc_record_values(m) {
+        match k {
+            "title" => {
+                values.push(("TITLE", v));
+            },
+            "creator" => {
+                values.push(("ARTIST", v));
+            },
+            "subject" => {
+                values.push(("GENRE", v));
+            },
+            "language" => {
+                values.push(("LANGUAGE", v));
+            },
+            "date" => {
+                values.push(("DATE", v));
+            },
+            "publisher" => {
+                values.push(("ORGANIZATION", v));
+            },
+            _ => {},
+        };
+    }
+
+    let mut r: Vec<String> = comments.into_iter()
+        .filter(|(k, _)| {
+            !values.iter().any(|(vk, _)| vk == k)
+        })
+        .map(|(k, v)| {
+            format!("{}={}", k, v)
+        })
+        .collect();
+    for (k, vv) in values {
+        for v in vv {
+            r.push(format!("{}={}", k, v));
+        }
+    }
+
+    let mut b: Vec<u8> = vec!();
+    b.extend((vendor.len() as u32).to_le_bytes());
+    b.extend(vendor.as_bytes());
+    b.extend((r.len() as u32).to_le_bytes());
+    for v in r {
+        b.extend((v.len() as u32).to_le_bytes());
+        b.extend(v.as_bytes());
+    }
+    b
+}
+
+/// Write the record to the Vorbis comment block of a FLAC file.
+///
+/// A comment block is added after the stream info if the file has none.
+pub fn write_flac(m: &MetaData, filepath: &path::Path) -> Result<(), Error> {
+    let mut r = BufReader::new(File::open(filepath)?);
+    let mut b = [0u8; 4];
+    r.read_exact(&mut b)?;
+    if &b != b"fLaC" {
+        return Err(Error::parse(Format::Embedded, "flac: invalid stream marker"));
+    }
+    let mut blocks: Vec<(u8, Vec<u8>)> = vec!();
+    let mut comments: Option<(String, Vec<(String, String)>)> = None;
+    loop {
+        r.read_exact(&mut b)?;
+        let last = b[0] & 0x80 > 0;
+        let mut block = vec![0u8; u32::from_be_bytes([0, b[1], b[2], b[3]]) as usize];
+        r.read_exact(&mut block)?;
+        match b[0] & 0x7f {
+            4 => {
+                comments = Some(parse_vorbis_comments(&block)?);
+            },
+            typ => {
+                blocks.push((typ, block));
+            },
+        };
+        if last {
+            break;
+        }
+    }
+    let (vendor, comments) = comments.unwrap_or((String::from("kitab"), vec!()));
+    let block = vorbis_comments_with_record(m, &vendor, comments);
+    if block.len() >= 1 << 24 {
+        return Err(Error::parse(Format::Embedded, "flac: vorbis comment block too large"));
+    }
+    blocks.insert(1.min(blocks.len()), (4, block));
+
+    replace_file(filepath, |w| {
+        w.write_all(b"fLaC")?;
+        let n = blocks.len();
+        for (i, (typ, block)) in blocks.iter().enumerate() {
+            let mut header = (block.len() as u32).to_be_bytes();
+            header[0] = *typ;
+            if i == n - 1 {
+                header[0] |= 0x80;
+            }
+            w.write_all(&header)?;
+            w.write_all(block)?;
+        }
+        io::copy(&mut r, w)?;
+        Ok(())
+    })
+}
+
+/// Write the record to the comment header of the Vorbis and Opus streams of an Ogg file.
+///
+/// Pages are rewritten, with the granule positions of the original.
+pub fn write_ogg(m: &MetaData, filepath: &path::Path) -> Result<(), Error> {
+    let mut rr = ogg::PacketReader::new(BufReader::new(File::open(filepath)?));
+    replace_file(filepath, |w| {
+        let mut pw = ogg::PacketWriter::new(w);
+        let mut streams: HashMap<u32, (usize, &'static [u8])> = HashMap::new();
+        while let Some(p) = rr.read_packet().map_err(|e| embedded_error("ogg", e))? {
+            let serial = p.stream_serial();
+            let inf = match (p.last_in_stream(), p.last_in_page()) {
+                (true, _) => {
+                    ogg::PacketWriteEndInfo::EndStream
+                },
+                (false, true) => {
+                    ogg::PacketWriteEndInfo::EndPage
+                },
+                _ => {
+                    ogg::PacketWriteEndInfo::NormalPacket
+                },
+            };
+            let absgp = p.absgp_page();
+            let mut data = p.data;
+            let stream = streams.entry(serial).or_insert((0, b""));
+            match stream.0 {
+                0 => {
+                    if data.starts_with(b"\x01vorbis") {
+                        stream.1 = b"\x03vorbis";
+                    } else if data.starts_with(b"OpusHead") {
+                        stream.1 = b"OpusTags";
+                    }
+                },
+                1 if !stream.1.is_empty() => {
+                    let src = data.strip_prefix(stream.1).ok_or_else(|| {
+                        Error::parse(Format::Embedded, "ogg: invalid comment header")
+                    })?;
+                    let (vendor, comments) = parse_vorbis_comments(src)?;
+                    let mut b = stream.1.to_vec();
+                    b.extend(vorbis_comments_with_record(m, &vendor, comments));
+                    if stream.1 == b"\x03vorbis" {
+                        b.push(1);
+                    }
+                    data = b;
+                },
+                _ => {},
+            };
+            stream.0 += 1;
+            pw.write_packet(data.into_boxed_slice(), serial, inf, absgp)?;
+        }
+        Ok(())
+    })
+}
+
+/// Write the record into the metadata embedded in the file.
+///
+/// Title, creators, subjects, language, date and publisher of the record replace the embedded
+/// values; embedded values the record has no counterpart for are kept. The file is rewritten, so
+/// its digests change.
+///
+/// Will return [Error::Parse](Error::Parse) if the file is not in a supported container.
+pub fn to_path(m: &MetaData, filepath: &path::Path) -> Result<(), Error> {
+    let container = match container_from_path(filepath)? {
+        Some(v) => {
+            v
+        },
+        None => {
+            return Err(Error::parse(Format::Embedded, "unsupported container"));
+        },
+    };
+    debug!("write embedded {:?} metadata {:?} to file {:?}", container, m, filepath);
+    match container {
+        Container::Pdf => {
+            write_pdf(m, filepath)
+        },
+        Container::Epub => {
+            write_epub(m, filepath)
+        },
+        Container::Id3 => {
+            write_id3(m, filepath)
+        },
+        Container::Flac => {
+            write_flac(m, filepath)
+        },
+        Container::Ogg => {
+            write_ogg(m, filepath)
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        read,
+        from_path,
+        to_path,
+        Container,
+    };
+    use std::io::{
+        Cursor,
+        Write,
+    };
+    use std::path;
+    use biblatex::EntryType;
+    use id3::TagLike;
+    use lopdf::{
+        dictionary,
+        Document,
+        Object,
+        Stream,
+    };
+    use crate::dc::Creator;
+    use crate::digest::{
+        DigestType,
+        RecordDigest,
+    };
+    use crate::meta::MetaData;
+
+    const XMP: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"><rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/">
+<dc:title><rdf:Alt><rdf:li xml:lang="x-default">Bitcoin: A Peer-to-Peer Electronic Cash System</rdf:li></rdf:Alt></dc:title>
+<dc:creator><rdf:Seq><rdf:li>Satoshi Nakamoto</rdf:li></rdf:Seq></dc:creator>
+<dc:subject><rdf:Bag><rdf:li>bitcoin</rdf:li><rdf:li>cryptocurrency</rdf:li></rdf:Bag></dc:subject>
+<dc:language><rdf:Bag><rdf:li>en</rdf:li></rdf:Bag></dc:language>
+<dc:date><rdf:Seq><rdf:li>2008-10-31</rdf:li></rdf:Seq></dc:date>
+</rdf:Description></rdf:RDF></x:xmpmeta>"#;
+
+    const OPF: &str = r#"<?xml version="1.0"?><package xmlns="http://www.idpf.org/2007/opf" version="3.0"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+<dc:title>The Data Protection Act, 2019</dc:title>
+<dc:creator>Government of Kenya</dc:creator>
+<dc:subject>data protection</dc:subject>
+<dc:language>en</dc:language>
+<dc:date>2019-11</dc:date>
+<dc:publisher>The Government Printer, Nairobi</dc:publisher>
+</metadata></package>"#;
+
+    fn pdf(xmp: bool) -> Vec<u8> {
+        let mut doc = Document::with_version("1.5");
+        let info = doc.add_object(dictionary! {
+            "Title" => Object::string_literal("Untitled"),
+            "Author" => Object::string_literal("Nakamoto, Satoshi"),
+            "Keywords" => Object::string_literal("p2p, payments"),
+            "CreationDate" => Object::string_literal("D:20081031120000Z"),
+        });
+        let mut catalog = dictionary! {
+            "Type" => "Catalog",
+        };
+        if xmp {
+            let metadata = doc.add_object(Stream::new(dictionary! {
+                "Type" => "Metadata",
+                "Subtype" => "XML",
+            }, XMP.as_bytes().to_vec()));
+            catalog.set("Metadata", metadata);
+        }
+        let catalog = doc.add_object(catalog);
+        doc.trailer.set("Root", catalog);
+        doc.trailer.set("Info", info);
+        let mut b: Vec<u8> = vec!();
+        doc.save_to(&mut b).unwrap();
+        b
+    }
+
+    fn epub() -> Vec<u8> {
+        let mut b: Vec<u8> = vec!();
+        {
+            let mut z = zip::ZipWriter::new(Cursor::new(&mut b));
+            let stored = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+            z.start_file("mimetype", stored).unwrap();
+            z.write_all(b"application/epub+zip").unwrap();
+            z.start_file("META-INF/container.xml", zip::write::FileOptions::default()).unwrap();
+            z.write_all(br#"<?xml version="1.0"?><container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#).unwrap();
+            z.start_file("OEBPS/content.opf", zip::write::FileOptions::default()).unwrap();
+            z.write_all(OPF.as_bytes()).unwrap();
+            z.start_file("OEBPS/text.xhtml", zip::write::FileOptions::default()).unwrap();
+            z.write_all(b"<html/>").unwrap();
+            z.finish().unwrap();
+        }
+        b
+    }
+
+    fn vorbis_comment(comments: &[&str]) -> Vec<u8> {
+        let mut b: Vec<u8> = vec!();
+        b.extend(2u32.to_le_bytes());
+        b.extend(b"kb");
+        b.extend((comments.len() as u32).to_le_bytes());
+        for v in comments {
+            b.extend((v.len() as u32).to_le_bytes());
+            b.extend(v.as_bytes());
+        }
+        b
+    }
+
+    fn flac() -> Vec<u8> {
+        let comment = vorbis_comment(&["TITLE=Foo", "artist=Bar Baz", "DATE=2022-06-01", "LANGUAGE=sw"]);
+        let mut b: Vec<u8> = b"fLaC".to_vec();
+        b.extend([0x00, 0x00, 0x00, 0x22]);
+        b.extend([0u8; 0x22]);
+        b.push(0x84);
+        b.extend(&(comment.len() as u32).to_be_bytes()[1..]);
+        b.extend(&comment);
+        b.extend(b"audio");
+        b
+    }
+
+    fn ogg() -> Vec<u8> {
+        let mut b: Vec<u8> = vec!();
+        {
+            let mut w = ogg::PacketWriter::new(Cursor::new(&mut b));
+            let mut comment = b"\x03vorbis".to_vec();
+            comment.extend(vorbis_comment(&["TITLE=Foo", "ENCODER=xyzzy"]));
+            comment.push(1);
+            w.write_packet(b"\x01vorbis".to_vec().into_boxed_slice(), 42, ogg::PacketWriteEndInfo::EndPage, 0).unwrap();
+            w.write_packet(comment.into_boxed_slice(), 42, ogg::PacketWriteEndInfo::NormalPacket, 0).unwrap();
+            w.write_packet(b"\x05vorbis".to_vec().into_boxed_slice(), 42, ogg::PacketWriteEndInfo::EndPage, 0).unwrap();
+            w.write_packet(b"audio".to_vec().into_boxed_slice(), 42, ogg::PacketWriteEndInfo::EndStream, 1024).unwrap();
+        }
+        b
+    }
+
+    #[test]
+    fn test_embedded_pdf() {
+        let b = pdf(false);
+        assert_eq!(Container::from_magic(&b), Some(Container::Pdf));
+        let m = read(Cursor::new(b), Container::Pdf).unwrap();
+        assert_eq!(m.title(), "Untitled");
+        assert_eq!(m.creators(), vec!(Creator::person("Satoshi", "Nakamoto")));
+        assert_eq!(m.subjects(), vec!("p2p", "payments"));
+        assert_eq!(m.publish_date().unwrap(), (31, 10, 2008));
+        assert_eq!(m.mime().unwrap(), "application/pdf");
+
+        let m = read(Cursor::new(pdf(true)), Container::Pdf).unwrap();
+        assert_eq!(m.title(), "Bitcoin: A Peer-to-Peer Electronic Cash System");
+        assert_eq!(m.creators(), vec!(Creator::person("Satoshi", "Nakamoto")));
+        assert_eq!(m.subjects(), vec!("p2p", "payments", "bitcoin", "cryptocurrency"));
+        assert_eq!(m.language().unwrap(), "en");
+    }
+
+    #[test]
+    fn test_embedded_epub() {
+        let b = epub();
+        assert_eq!(Container::from_magic(&b), Some(Container::Epub));
+        let m = read(Cursor::new(b), Container::Epub).unwrap();
+        assert_eq!(m.title(), "The Data Protection Act, 2019");
+        assert_eq!(m.creators(), vec!(Creator::organisation("Government of Kenya")));
+        assert_eq!(m.typ(), EntryType::Book);
+        assert_eq!(m.subjects(), vec!("data protection"));
+        assert_eq!(m.publish_date().unwrap(), (0, 11, 2019));
+        assert_eq!(m.publisher().unwrap(), "The Government Printer, Nairobi");
+    }
+
+    #[test]
+    fn test_embedded_audio() {
+        let mut tag = id3::Tag::new();
+        tag.set_title("Foo");
+        tag.set_artist("Bar Baz");
+        tag.set_genre("Podcast");
+        tag.set_year(2022);
+        let mut b: Vec<u8> = vec!();
+        tag.write_to(&mut b, id3::Version::Id3v24).unwrap();
+        assert_eq!(Container::from_magic(&b), Some(Container::Id3));
+        let m = read(Cursor::new(b), Container::Id3).unwrap();
+        assert_eq!(m.title(), "Foo");
+        assert_eq!(m.creators(), vec!(Creator::person("Bar", "Baz")));
+        assert_eq!(m.subjects(), vec!("Podcast"));
+        assert_eq!(m.publish_date().unwrap(), (0, 0, 2022));
+
+        let b = flac();
+        assert_eq!(Container::from_magic(&b), Some(Container::Flac));
+        let m = read(Cursor::new(b), Container::Flac).unwrap();
+        assert_eq!(m.title(), "Foo");
+        assert_eq!(m.creators(), vec!(Creator::person("Bar", "Baz")));
+        assert_eq!(m.language().unwrap(), "sw");
+        assert_eq!(m.publish_date().unwrap(), (1, 6, 2022));
+        assert_eq!(m.mime().unwrap(), "audio/flac");
+    }
+
+    #[test]
+    fn test_embedded_from_path() {
+        let d = tempfile::tempdir().unwrap();
+        let fp = d.path().join("bitcoin.pdf");
+        std::fs::write(&fp, pdf(true)).unwrap();
+        let m = from_path(&fp, &vec!(DigestType::Sha512), None).unwrap();
+        assert_eq!(m.digests().len(), 1);
+        assert_eq!(m.title(), "Bitcoin: A Peer-to-Peer Electronic Cash System");
+
+        let fp = path::Path::new("testdata/meta.ttl");
+        assert!(from_path(fp, &vec!(DigestType::Sha512), None).is_err());
+    }
+
+    fn record() -> MetaData {
+        let mut m = MetaData::new("Bitcoin: A Peer-to-Peer Electronic Cash System", "", EntryType::Article, RecordDigest::Empty, None);
+        m.set_creators(vec!(
+            Creator::person("Satoshi", "Nakamoto"),
+            Creator::person("Ursula K.", "Le Guin"),
+            Creator::organisation("Government of Kenya"),
+        ));
+        m.add_subject("bitcoin");
+        m.set_language("en").unwrap();
+        m.set_publish_date((31, 10, 2008));
+        m
+    }
+
+    #[test]
+    fn test_embedded_write() {
+        let d = tempfile::tempdir().unwrap();
+        let m = record();
+        for (name, b) in [("a.pdf", pdf(false)), ("b.pdf", pdf(true)), ("c.epub", epub()), ("d.flac", flac()), ("e.ogg", ogg())] {
+            let fp = d.path().join(name);
+            std::fs::write(&fp, &b).unwrap();
+            to_path(&m, &fp).unwrap();
+            let r = from_path(&fp, &vec!(DigestType::Sha512), None).unwrap();
+            assert_eq!(r.title(), m.title());
+            assert_eq!(r.creators(), m.creators());
+            assert!(r.has_subject("bitcoin"));
+            assert_eq!(r.language().unwrap(), "en");
+            assert_ne!(std::fs::read(&fp).unwrap(), b);
+        }
+
+        let fp = d.path().join("c.epub");
+        let mut z = zip::ZipArchive::new(std::fs::File::open(&fp).unwrap()).unwrap();
+        assert_eq!(z.by_index(0).unwrap().name(), "mimetype");
+        assert_eq!(z.by_index(0).unwrap().compression(), zip::CompressionMethod::Stored);
+        assert_eq!(z.len(), 4);
+        let r = read(std::fs::File::open(&fp).unwrap(), Container::Epub).unwrap();
+        assert_eq!(r.publisher().unwrap(), "The Government Printer, Nairobi");
+        assert_eq!(r.publish_date().unwrap(), (31, 10, 2008));
+
+        let fp = d.path().join("d.flac");
+        assert!(std::fs::read(&fp).unwrap().ends_with(b"audio"));
+
+        let fp = d.path().join("e.ogg");
+        let mut rr = ogg::PacketReader::new(std::fs::File::open(&fp).unwrap());
+        let comment = rr.read_packet().unwrap().and_then(|_| rr.read_packet().unwrap()).unwrap();
+        assert!(comment.data.ends_with(b"\x01"));
+        assert!(String::from_utf8_lossy(&comment.data).contains("ENCODER=xyzzy"));
+        let last = std::iter::from_fn(|| rr.read_packet().unwrap()).last().unwrap();
+        assert_eq!(last.data, b"audio");
+        assert_eq!(last.absgp_page(), 1024);
+
+        let mut tag = id3::Tag::new();
+        tag.set_title("Foo");
+        tag.set_text("TPUB", "Baz");
+        let fp = d.path().join("f.mp3");
+        std::fs::write(&fp, b"audio").unwrap();
+        tag.write_to_path(&fp, id3::Version::Id3v24).unwrap();
+        to_path(&m, &fp).unwrap();
+        let r = read(std::fs::File::open(&fp).unwrap(), Container::Id3).unwrap();
+        assert_eq!(r.title(), m.title());
+        assert_eq!(r.creators(), m.creators());
+        assert_eq!(r.publisher().unwrap(), "Baz");
+        assert_eq!(r.publish_date().unwrap(), (31, 10, 2008));
+        assert!(std::fs::read(&fp).unwrap().ends_with(b"audio"));
+
+        let fp = path::Path::new("testdata/meta.ttl");
+        assert!(to_path(&m, fp).is_err());
+    }
+}
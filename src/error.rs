@@ -1,13 +1,203 @@
-/// Used for any parsing error for any supported format.
+//! Errors returned by the library.
+use std::fmt;
+use std::io;
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+/// `ENOTSUP` / `EOPNOTSUPP` on linux, returned for extended attribute operations on filesystems
+/// without support for them.
+const ERRNO_NOTSUP: i32 = 95;
+
+/// `ENODATA` on linux, returned when removing an extended attribute that is not set.
+pub(crate) const ERRNO_NODATA: i32 = 61;
+
+/// Source format of data that could not be parsed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// rdf-turtle, the native store format.
+    Rdf,
+    /// BibTeX / BibLaTeX.
+    Biblatex,
+    /// CSL-JSON.
+    Csl,
+    /// RIS.
+    Ris,
+    /// Extended attributes of a file.
+    Xattr,
+    /// Metadata embedded in the contents of a media file.
+    Embedded,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Format::Rdf => {
+                "rdf"
+            },
+            Format::Biblatex => {
+                "biblatex"
+            },
+            Format::Csl => {
+                "csl-json"
+            },
+            Format::Ris => {
+                "ris"
+            },
+            Format::Xattr => {
+                "xattr"
+            },
+            Format::Embedded => {
+                "embedded"
+            },
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Any error returned by the library.
 #[derive(Debug)]
-pub struct ParseError {
-    pub detail: String,
+pub enum Error {
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// Source data in the given format is malformed.
+    Parse {
+        format: Format,
+        detail: String,
+    },
+    /// Digest or digest identifier is malformed, or of an unsupported type.
+    InvalidDigest(String),
+    /// Value is not valid for the metadata field or option.
+    InvalidField {
+        field: &'static str,
+        value: String,
+    },
+    /// The filesystem of the file does not support extended attributes.
+    XattrUnsupported(PathBuf),
+    /// A record in the sidecar does not match the digest of the file it is next to.
+    DigestMismatch(PathBuf),
+    /// The store has no record for the digest.
+    NotFound(String),
+    /// The store already has a record for the digest, and its conflict policy refuses to change it.
+    AlreadyExists(String),
+    /// The digests of the entry belong to more than one record in the store, so the records can
+    /// not be replaced by the entry.
+    Conflict(String),
+    /// The store backend failed.
+    Store(String),
+}
+
+impl Error {
+    /// Create an [Error::Parse](Error::Parse) for the given format.
+    pub fn parse(format: Format, detail: &str) -> Error {
+        Error::Parse{
+            format,
+            detail: String::from(detail),
+        }
+    }
+
+    /// Create an [Error::InvalidDigest](Error::InvalidDigest).
+    pub fn digest(detail: &str) -> Error {
+        Error::InvalidDigest(String::from(detail))
+    }
+
+    /// Create an [Error::InvalidField](Error::InvalidField) for the given field and value.
+    pub fn field(field: &'static str, value: &str) -> Error {
+        Error::InvalidField{
+            field,
+            value: String::from(value),
+        }
+    }
+
+    /// Convert an error from an extended attribute operation on the file at `p`.
+    pub fn from_xattr(e: io::Error, p: &Path) -> Error {
+        match e.raw_os_error() {
+            Some(ERRNO_NOTSUP) => {
+                Error::XattrUnsupported(p.to_path_buf())
+            },
+            _ => {
+                Error::Io(e)
+            },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => {
+                write!(f, "i/o error: {}", e)
+            },
+            Error::Parse{format, detail} => {
+                write!(f, "invalid {} data: {}", format, detail)
+            },
+            Error::InvalidDigest(v) => {
+                write!(f, "invalid digest: {}", v)
+            },
+            Error::InvalidField{field, value} => {
+                write!(f, "invalid value for {}: \"{}\"", field, value)
+            },
+            Error::XattrUnsupported(p) => {
+                write!(f, "extended attributes not supported for {}", p.display())
+            },
+            Error::DigestMismatch(p) => {
+                write!(f, "sidecar {} does not match the digest of its file", p.display())
+            },
+            Error::NotFound(v) => {
+                write!(f, "no record for {}", v)
+            },
+            Error::AlreadyExists(v) => {
+                write!(f, "record for {} already exists", v)
+            },
+            Error::Conflict(v) => {
+                write!(f, "digests of {} belong to more than one record", v)
+            },
+            Error::Store(v) => {
+                write!(f, "store error: {}", v)
+            },
+        }
+    }
 }
 
-impl ParseError {
-    pub fn new(s: &str) -> ParseError {
-        ParseError{
-            detail: String::from(s),
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => {
+                Some(e)
+            },
+            _ => {
+                None
+            },
         }
     }
 }
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::Path;
+    use super::{
+        Error,
+        Format,
+    };
+
+    #[test]
+    fn test_error_display() {
+        let e = Error::parse(Format::Biblatex, "missing title");
+        assert_eq!(e.to_string(), "invalid biblatex data: missing title");
+        let e = Error::field("language", "xx-");
+        assert_eq!(e.to_string(), "invalid value for language: \"xx-\"");
+
+        let e = Error::from_xattr(io::Error::from_raw_os_error(95), Path::new("/foo"));
+        assert_eq!(e.to_string(), "extended attributes not supported for /foo");
+        let e = Error::from_xattr(io::Error::from_raw_os_error(2), Path::new("/foo"));
+        assert!(matches!(e, Error::Io(_)));
+    }
+}
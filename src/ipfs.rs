@@ -0,0 +1,278 @@
+//! IPFS content identifier (CID) calculation.
+//!
+//! The CID is the one a file gets when added to IPFS as CIDv1 with default settings: the file is
+//! split into raw leaf blocks of 262144 bytes, which are linked by a balanced tree of UnixFS
+//! `dag-pb` nodes with at most 174 links each. All blocks are hashed with `sha2-256`.
+//!
+//! A file that fits in a single block is identified by the CID of the raw block itself.
+use sha2::{
+    Sha256,
+    Digest,
+};
+use data_encoding::BASE32_NOPAD;
+
+use crate::error::Error;
+
+/// Size of a leaf block in bytes.
+pub const BLOCK_SIZE: usize = 262144;
+
+/// Maximum number of links in a node.
+pub const MAX_LINKS: usize = 174;
+
+/// CID version.
+const CID_V1: u8 = 0x01;
+
+/// Multicodec of raw blocks.
+const CODEC_RAW: u8 = 0x55;
+
+/// Multicodec of `dag-pb` blocks.
+const CODEC_DAG_PB: u8 = 0x70;
+
+/// Multihash code of `sha2-256`.
+const MULTIHASH_SHA256: u8 = 0x12;
+
+/// UnixFS data type of a file node.
+const UNIXFS_FILE: u64 = 2;
+
+/// Returns the binary CIDv1 of a block with the given codec.
+fn block_cid(codec: u8, data: &[u8]) -> Vec<u8> {
+    let mut r: Vec<u8> = vec!(CID_V1, codec, MULTIHASH_SHA256, 32);
+    r.extend(Sha256::digest(data));
+    r
+}
+
+/// Returns the multibase base32 string of a binary CID, as `ipfs` shows it.
+pub fn cid_to_string(cid: &[u8]) -> String {
+    String::from("b") + BASE32_NOPAD.encode(cid).to_lowercase().as_str()
+}
+
+/// Parse a binary CID from its multibase base32 string.
+///
+/// Only `sha2-256` CIDv1 of raw and `dag-pb` blocks are accepted.
+pub fn cid_from_str(s: &str) -> Result<Vec<u8>, Error> {
+    let v = match s.strip_prefix("b") {
+        Some(v) => {
+            v
+        },
+        None => {
+            return Err(Error::digest("unsupported cid multibase"));
+        },
+    };
+    let cid = match BASE32_NOPAD.decode(v.to_uppercase().as_bytes()) {
+        Ok(v) => {
+            v
+        },
+        Err(_) => {
+            return Err(Error::digest("invalid cid encoding"));
+        },
+    };
+    if cid.len() != 36 || cid[0] != CID_V1 || (cid[1] != CODEC_RAW && cid[1] != CODEC_DAG_PB) || cid[2] != MULTIHASH_SHA256 || cid[3] != 32 {
+        return Err(Error::digest("unsupported cid"));
+    }
+    Ok(cid)
+}
+
+fn put_varint(w: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        w.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    w.push(v as u8);
+}
+
+fn put_bytes(w: &mut Vec<u8>, field: u64, v: &[u8]) {
+    put_varint(w, (field << 3) | 2);
+    put_varint(w, v.len() as u64);
+    w.extend_from_slice(v);
+}
+
+fn put_uint(w: &mut Vec<u8>, field: u64, v: u64) {
+    put_varint(w, field << 3);
+    put_varint(w, v);
+}
+
+#[derive(Clone)]
+struct Link {
+    cid: Vec<u8>,
+    /// Size of the linked block and all blocks below it.
+    tsize: u64,
+    /// Size of the file data below the link.
+    filesize: u64,
+}
+
+/// Encode a UnixFS file node linking to the given children, and return the link to it.
+fn node(links: &[Link]) -> Link {
+    let mut data: Vec<u8> = vec!();
+    let mut filesize: u64 = 0;
+    put_uint(&mut data, 1, UNIXFS_FILE);
+    for v in links {
+        filesize += v.filesize;
+    }
+    put_uint(&mut data, 3, filesize);
+    for v in links {
+        put_uint(&mut data, 4, v.filesize);
+    }
+
+    let mut block: Vec<u8> = vec!();
+    let mut tsize: u64 = 0;
+    for v in links {
+        let mut link: Vec<u8> = vec!();
+        put_bytes(&mut link, 1, &v.cid);
+        put_bytes(&mut link, 2, &[]);
+        put_uint(&mut link, 3, v.tsize);
+        put_bytes(&mut block, 2, &link);
+        tsize += v.tsize;
+    }
+    put_bytes(&mut block, 1, &data);
+
+    Link{
+        cid: block_cid(CODEC_DAG_PB, &block),
+        tsize: tsize + block.len() as u64,
+        filesize,
+    }
+}
+
+/// Calculates the IPFS CID of data in a single pass, keeping one block and one node per tree
+/// level in memory.
+pub struct IpfsHasher {
+    buf: Vec<u8>,
+    levels: Vec<Vec<Link>>,
+    length: u64,
+}
+
+impl Default for IpfsHasher {
+    fn default() -> Self {
+        IpfsHasher::new()
+    }
+}
+
+impl IpfsHasher {
+    pub fn new() -> IpfsHasher {
+        IpfsHasher{
+            buf: Vec::with_capacity(BLOCK_SIZE),
+            levels: vec!(),
+            length: 0,
+        }
+    }
+
+    fn add_link(&mut self, level: usize, link: Link) {
+        if self.levels.len() == level {
+            self.levels.push(vec!());
+        }
+        self.levels[level].push(link);
+        if self.levels[level].len() == MAX_LINKS {
+            let links = std::mem::take(&mut self.levels[level]);
+            self.add_link(level + 1, node(&links));
+        }
+    }
+
+    fn flush_block(&mut self) {
+        let link = Link{
+            cid: block_cid(CODEC_RAW, &self.buf),
+            tsize: self.buf.len() as u64,
+            filesize: self.buf.len() as u64,
+        };
+        self.buf.clear();
+        self.add_link(0, link);
+    }
+
+    /// Add data to the digest.
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        while !data.is_empty() {
+            let c = (BLOCK_SIZE - self.buf.len()).min(data.len());
+            self.buf.extend_from_slice(&data[..c]);
+            data = &data[c..];
+            if self.buf.len() == BLOCK_SIZE {
+                self.flush_block();
+            }
+        }
+    }
+
+    /// Returns the binary CID of all data added.
+    pub fn finalize(mut self) -> Vec<u8> {
+        if !self.buf.is_empty() || self.length == 0 {
+            self.flush_block();
+        }
+        let mut i: usize = 0;
+        loop {
+            let top = i + 1 == self.levels.len();
+            if top && self.levels[i].len() == 1 {
+                return self.levels[i].remove(0).cid;
+            }
+            let links = std::mem::take(&mut self.levels[i]);
+            if !links.is_empty() {
+                self.add_link(i + 1, node(&links));
+            }
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        IpfsHasher,
+        BLOCK_SIZE,
+        MAX_LINKS,
+        cid_to_string,
+        cid_from_str,
+    };
+
+    fn cid(data: &[u8]) -> String {
+        let mut h = IpfsHasher::new();
+        h.update(data);
+        cid_to_string(&h.finalize())
+    }
+
+    #[test]
+    fn test_cid_raw() {
+        assert_eq!(cid(b""), "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku");
+        let s = cid(b"foo");
+        assert_eq!(cid_to_string(&cid_from_str(&s).unwrap()), s);
+        assert!(cid_from_str("Qmfoo").is_err());
+    }
+
+    #[test]
+    fn test_cid_tree() {
+        // expected values are those of kubo's default size-262144 chunker and balanced layout:
+        //
+        //   ipfs add -Q --only-hash --cid-version=1 --raw-leaves
+        //
+        // with the data given on stdin by, respectively:
+        //
+        //   head -c 524289 /dev/zero
+        //   python3 -c 'import sys; sys.stdout.buffer.write(bytes((v * 7 + 3) % 256 for v in range(798777)))'
+        //   head -c 45613057 /dev/zero | tr '\0' '\1'
+        let data: Vec<u8> = vec!(0; BLOCK_SIZE * 2 + 1);
+        let s = cid(&data);
+        assert_eq!(s, "bafybeiccdyx3btkzo6gcp5rzteka62rjst3vvgrnd7kosz6rlpg2esghpe");
+        let data_pattern: Vec<u8> = (0..BLOCK_SIZE * 3 + 12345)
+            .map(|v| {
+                (v * 7 + 3) as u8
+            })
+            .collect();
+        assert_eq!(cid(&data_pattern), "bafybeibkbtzakmiglza7qxy2wyukkagn5rtrmkkzlk2q3hgyzxiswevbom");
+
+        let mut h = IpfsHasher::new();
+        for v in data.chunks(1000) {
+            h.update(v);
+        }
+        assert_eq!(cid_to_string(&h.finalize()), s);
+
+        // a full first level is the root itself, one more block adds a level
+        let mut h = IpfsHasher::new();
+        let mut h_more = IpfsHasher::new();
+        let block: Vec<u8> = vec!(1; BLOCK_SIZE);
+        for _ in 0..MAX_LINKS {
+            h.update(&block);
+            h_more.update(&block);
+        }
+        h_more.update(&[1]);
+        assert_eq!(h.levels.len(), 2);
+        let root = h.finalize();
+        let root_more = h_more.finalize();
+        assert_ne!(root, root_more);
+        assert_eq!(cid_to_string(&root_more), "bafybeifvgcdflxv23wulrucihj2hagkdgggjhywkttn56y2f5jqrf7zvgu");
+    }
+}
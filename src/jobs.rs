@@ -0,0 +1,281 @@
+//! Process files concurrently with a bounded number of worker threads.
+//!
+//! Work on each file is done by the workers in any order, while the results are handed back on
+//! the calling thread in the order of the files. Writes to a store and output can thus stay on a
+//! single thread, and appear the same as when files are processed one by one.
+use std::collections::BTreeMap;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::sync::mpsc::sync_channel;
+use std::sync::{
+    Condvar,
+    Mutex,
+};
+
+use crossbeam_utils::thread;
+use walkdir::WalkDir;
+use log::{
+    debug,
+};
+
+/// Returns all files under the path `p`, recursively, in directory walk order.
+///
+/// If `p` is a file, only `p` itself is returned.
+pub fn files(p: &Path) -> Vec<PathBuf> {
+    WalkDir::new(p)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| !e.file_type().is_dir())
+        .map(|e| {
+            e.into_path()
+        })
+        .collect()
+}
+
+/// Number of items per worker thread that may be worked on ahead of the next item to be passed to
+/// the sink.
+///
+/// This bounds the number of results held back while waiting for an earlier item to finish.
+const WINDOW_PER_JOB: usize = 4;
+
+/// Progress of the sink shared with the workers: the index of the next item to be passed to the
+/// sink, or None when processing has stopped.
+type Progress = (Mutex<Option<usize>>, Condvar);
+
+/// Stops processing if a worker thread panics, so that other workers do not wait for progress
+/// forever.
+struct StopOnPanic<'a>(&'a Progress);
+
+impl Drop for StopOnPanic<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            *self.0.0.lock().unwrap_or_else(|e| e.into_inner()) = None;
+            self.0.1.notify_all();
+        }
+    }
+}
+
+/// Resolve the number of worker threads to use.
+///
+/// `0` means the number of available CPUs.
+pub fn resolve_jobs(jobs: usize) -> usize {
+    match jobs {
+        0 => {
+            std::thread::available_parallelism()
+                .map(|v| {
+                    v.get()
+                })
+                .unwrap_or(1)
+        },
+        _ => {
+            jobs
+        },
+    }
+}
+
+/// Call `work` on every item using up to `jobs` worker threads, and `sink` with each result on
+/// the calling thread, in the order of `items`.
+///
+/// With one job, no threads are spawned and each item is passed to `sink` before the next is
+/// worked on. With more jobs, workers do not start on an item more than `jobs * 4` items ahead of
+/// the next one to be passed to `sink`, so that the results held back for ordering stay bounded
+/// when some items take much longer than others.
+///
+/// # Arguments
+///
+/// * `items` - Items to work on.
+/// * `jobs` - Maximum number of worker threads.
+/// * `work` - Work to perform on each item, e.g. calculating digests.
+/// * `sink` - Receives each item together with the result of `work`.
+pub fn process_ordered<T, R, F, S>(items: &[T], jobs: usize, work: F, mut sink: S)
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+    S: FnMut(&T, R) {
+    process_ordered_while(items, jobs, work, |v, r| {
+        sink(v, r);
+        true
+    });
+}
+
+/// As [process_ordered](process_ordered), but stops when `sink` returns false.
+///
+/// Items after the one `sink` returned false for are not passed to `sink`, and no more items are
+/// worked on.
+pub fn process_ordered_while<T, R, F, S>(items: &[T], jobs: usize, work: F, mut sink: S)
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+    S: FnMut(&T, R) -> bool {
+    let jobs = jobs.max(1).min(items.len().max(1));
+    if jobs == 1 {
+        for v in items {
+            if !sink(v, work(v)) {
+                break;
+            }
+        }
+        return;
+    }
+
+    debug!("processing {} items with {} jobs", items.len(), jobs);
+    let window = jobs * WINDOW_PER_JOB;
+    let next = AtomicUsize::new(0);
+    let done: Progress = (Mutex::new(Some(0)), Condvar::new());
+    let (tx, rx) = sync_channel::<(usize, R)>(jobs);
+    thread::scope(|s| {
+        for _ in 0..jobs {
+            let tx = tx.clone();
+            let next = &next;
+            let done = &done;
+            let work = &work;
+            s.spawn(move |_| {
+                let _guard = StopOnPanic(done);
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    if i >= items.len() {
+                        break;
+                    }
+                    let mut expect = done.0.lock().unwrap();
+                    loop {
+                        match *expect {
+                            Some(v) if i >= v + window => {
+                                expect = done.1.wait(expect).unwrap();
+                            },
+                            _ => {
+                                break;
+                            },
+                        };
+                    }
+                    if expect.is_none() {
+                        break;
+                    }
+                    drop(expect);
+                    if tx.send((i, work(&items[i]))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        let mut pending: BTreeMap<usize, R> = BTreeMap::new();
+        let mut expect: usize = 0;
+        'recv: for (i, r) in rx.iter() {
+            pending.insert(i, r);
+            while let Some(r) = pending.remove(&expect) {
+                if !sink(&items[expect], r) {
+                    debug!("stopped processing after {} of {} items", expect + 1, items.len());
+                    next.store(items.len(), Ordering::SeqCst);
+                    *done.0.lock().unwrap() = None;
+                    done.1.notify_all();
+                    break 'recv;
+                }
+                expect += 1;
+                *done.0.lock().unwrap() = Some(expect);
+                done.1.notify_all();
+            }
+        }
+        drop(rx);
+    }).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{
+        write,
+        create_dir,
+    };
+    use tempfile::tempdir;
+    use std::sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    };
+    use super::{
+        files,
+        process_ordered,
+        process_ordered_while,
+        WINDOW_PER_JOB,
+    };
+
+    #[test]
+    fn test_process_ordered() {
+        let items: Vec<u64> = (0..100).collect();
+        for jobs in [1, 4, 200] {
+            let mut r: Vec<u64> = vec!();
+            process_ordered(&items, jobs, |v| {
+                std::thread::sleep(std::time::Duration::from_micros((100 - v) * 10));
+                v * 2
+            }, |v, x| {
+                assert_eq!(*v * 2, x);
+                r.push(*v);
+            });
+            assert_eq!(r, items);
+        }
+    }
+
+    #[test]
+    fn test_process_ordered_bounded() {
+        let items: Vec<u64> = (0..200).collect();
+        let started = AtomicUsize::new(0);
+        let mut r: Vec<u64> = vec!();
+        process_ordered(&items, 4, |v| {
+            let c = started.fetch_add(1, Ordering::SeqCst);
+            if *v == 0 {
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                // no more than the window ahead of the first item may have been started.
+                assert!(started.load(Ordering::SeqCst) <= 4 * WINDOW_PER_JOB);
+            }
+            c
+        }, |v, _| {
+            r.push(*v);
+        });
+        assert_eq!(r, items);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_process_ordered_panic() {
+        let items: Vec<u64> = (0..100).collect();
+        process_ordered(&items, 4, |v| {
+            if *v == 0 {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                panic!("foo");
+            }
+            *v
+        }, |_, _| {});
+    }
+
+    #[test]
+    fn test_process_ordered_while() {
+        let items: Vec<u64> = (0..100).collect();
+        for jobs in [1, 4] {
+            let mut r: Vec<u64> = vec!();
+            process_ordered_while(&items, jobs, |v| {
+                v * 2
+            }, |v, _| {
+                r.push(*v);
+                *v < 41
+            });
+            assert_eq!(r, (0..42).collect::<Vec<u64>>());
+        }
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = tempdir().unwrap();
+        create_dir(dir.path().join("sub")).unwrap();
+        write(dir.path().join("foo"), "foo").unwrap();
+        write(dir.path().join("sub").join("bar"), "bar").unwrap();
+        let r = files(dir.path());
+        assert_eq!(r.len(), 2);
+        assert_eq!(files(&dir.path().join("foo")).len(), 1);
+    }
+}
@@ -5,8 +5,10 @@
 //! location whose digests match the respective keys of the metadata.
 //!
 //! Also, metadata can be imported from the same extended file attributes, as well as
-//! files containing [bibtex](http://www.bibtex.org/Format/) entries and
-//! entries in kitab's native store format.
+//! files containing [bibtex](http://www.bibtex.org/Format/) entries,
+//! [CSL-JSON](https://citeproc-js.readthedocs.io/en/latest/csl-json/markup.html) items,
+//! [RIS](https://en.wikipedia.org/wiki/RIS_(file_format)) records and entries in kitab's native
+//! store format.
 //!
 //! ## Usage examples
 //!
@@ -22,20 +24,95 @@
 //!
 //! ## apply metadata on files matching digests in store
 //! $ kitab apply /path/to/media_files
+//!
+//! ## also write metadata into pdf, epub and audio file contents (with the embedded feature)
+//! $ kitab apply --embed /path/to/media_files
+//!
+//! ## write metadata to <file>.ttl sidecar files instead of extended attributes
+//! $ kitab apply --sidecar /path/to/media_files
+//!
+//! ## write one .kitab.ttl sidecar per directory instead
+//! $ kitab apply --sidecar=dir /path/to/media_files
+//!
+//! ## also remove metadata extended attributes that the record does not define
+//! $ kitab apply --prune /path/to/media_files
+//!
+//! ## show which extended attributes apply would add, change or, with --prune, remove
+//! $ kitab apply --dry-run /path/to/media_files
+//!
+//! ## hash and apply up to 8 files at a time (0 for one per CPU)
+//! $ kitab apply --jobs 8 /path/to/media_files
+//!
+//! ## add a new entry for a file, prompting for metadata
+//! $ kitab new /path/to/media_file
+//!
+//! ## add a new entry without prompting, and apply it to the file's extended attributes
+//! $ kitab new --batch --xattr --title "Foo" --author "Bar Baz" --type article /path/to/media_file
+//!
+//! ## list entries in store with the keyword "bitcoin" in english
+//! $ kitab search --subject bitcoin --language en
+//!
+//! ## export all entries in store as bibtex
+//! $ kitab export --format bibtex > library.bib
+//!
+//! ## export all entries in store as CSL-JSON, e.g. for Zotero or pandoc
+//! $ kitab export --format csl > library.json
 //! ```
 //!
+//! In bibtex and CSL-JSON sources, the digests of an entry are given as whitespace-separated URNs
+//! in the `note` field. CSL-JSON items may also list them in a `digests` array in the `custom`
+//! field. In RIS sources, they are read from `N1` notes and the custom tags `C1` to `C8`.
+//!
 //! ## Native store format
 //!
 //! The native data format is [rdf-turtle](https://www.w3.org/TR/turtle/), currently limited to a
-//! subset of the [DublinCore](https://www.dublincore.org/specifications/dublin-core/dcmi-terms/) vocabulary.
+//! subset of the [DublinCore](https://www.dublincore.org/specifications/dublin-core/dcmi-terms/) vocabulary:
+//! `title`, `creator`, `type`, `subject`, `MediaType`, `language`, `publisher`, `date`,
+//! `identifier`, `description`, `contributor`, `source`, `rights`, `isPartOf`, `extent` and `issued`.
+//!
+//! Each creator is a separate `dcterms:creator` literal in [BibTeX name
+//! format](https://www.bibtex.org/Format/), with the names of organisations in curly braces. The
+//! `user.dcterms:creator` extended attribute holds all creators, separated by `and`:
+//!
+//! ``` ignore;
+//! user.dcterms:creator="Nakamoto, Satoshi and {Government of Kenya}"
+//! ```
+//!
+//! Creators written by earlier versions as names in running text, separated by commas and `and`,
+//! are still read, e.g. `Gowri Sankar Ramachandran, Rahul Radhakrishnan and Bhaskar
+//! Krishnamachari`. They are rewritten in BibTeX name format the next time the record is stored.
+//!
+//! The publication date in `dcterms:issued` is typed by its precision, as `xsd:gYear`,
+//! `xsd:gYearMonth` or `xsd:date`:
+//!
+//! ``` ignore;
+//! <urn:sha512:2c26b4[...]> dcterms:issued "2019-11"^^xsd:gYearMonth
+//! ```
 //!
 //! The subject of all entries is a URN specifying the digest of the matching file, in the format
 //! (digest hex for illustration purpose only):
 //!
 //! ``` ignore;
-//! <URN:sha256:2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae> predicate object
+//! <urn:sha256:2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae> predicate object
 //! [...]
 //! ```
+//!
+//! Subjects are written as lowercase [RFC 8141](https://www.rfc-editor.org/rfc/rfc8141) URNs.
+//! Records from other tools may also use other notations of the same digest, such as
+//! `urn:hash::sha256:<hex>`, `ni:///sha-256;<base64url>` ([RFC
+//! 6920](https://www.rfc-editor.org/rfc/rfc6920)) or `urn:multihash:<hex>`, in any case. These are
+//! accepted on import, as are digests given with `--digest`. See
+//! [digest::urn](crate::digest::urn).
+//!
+//! If the same file is known by more than one digest, the additional digests are linked to the
+//! record subject with `owl:sameAs`:
+//!
+//! ``` ignore;
+//! <urn:sha512:2c26b4[...]> owl:sameAs <urn:sha256:b5bb9d[...]>
+//! ```
+//!
+//! The record can then be looked up in the store by any of its digests.
+//!
 //! Please forgive the lack of a schema describing the data. It will follow.
 //!
 //! ## Store location
@@ -43,14 +120,58 @@
 //! Metadata files are stored under `~/.local/share/kitab/idx/<hex>` where `<hex>` is the
 //! (lowercase) digest hex matching the URN in the record.
 //!
+//! Another location can be given with the `--store` option, either as a directory path or as
+//! `file://<path>`.
+//!
+//! ### Import conflicts
+//!
+//! When an imported entry shares a digest with a record already in the store, the `--on-conflict`
+//! option of `import` decides what happens:
+//!
+//! * `overwrite` (default) - the entry replaces the record.
+//! * `keep` - the record is left unchanged.
+//! * `merge` - fields missing in the record are set from the entry. Fields where the two disagree
+//!   keep the record's value, and are reported.
+//! * `fail` - the import is aborted at the first such entry, and the tool exits with status `2`.
+//!   Entries imported before it are kept.
+//!
+//! ### Optional: SQLite store
+//!
+//! If built with the `store_sqlite` feature, records can instead be kept in an SQLite database
+//! by passing `--store sqlite://<path>`. The fields that `search` selects on are kept in indexed
+//! columns, so searching does not read every record.
+//!
 //! ## Supported digests
 //!
 //! * `SHA512` (native)
 //! * `SHA256`
+//! * `MD5` (with the `digest_md5` feature)
+//! * Swarm hash, as `bzz` (with the `digest_bzz` feature)
+//! * `SHA1`, as `sha1` (with the `digest_sha1` feature)
+//! * `BLAKE3`, as `blake3` (with the `digest_blake3` feature)
+//! * IPFS CIDv1, as `ipfs` (with the `digest_ipfs` feature)
+//!
+//! The IPFS digest is the CID the file gets when added with `ipfs add --cid-version=1`, and is
+//! written in URNs in its base32 form, e.g. `ipfs:bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku`.
 //!
 //! Metadata imported from extended attributes will use the `SHA512` digest of the file as the
 //! storage key.
 //!
+//! ### Digest cache
+//!
+//! Digests calculated for files are cached next to the store, in `<store>.digests`, keyed by
+//! device, inode, size and modification time of the file. Unchanged files are not read again on
+//! later `import`, `apply` or `new` runs.
+//!
+//! Use `--no-cache` to bypass the cache, and `kitab cache prune` to drop entries for files that
+//! have since been changed or removed.
+//!
+//! ### Concurrency
+//!
+//! `import` and `apply` hash files on a single thread by default. The `--jobs` option sets the
+//! number of files read and hashed concurrently. Records are still written to the store, and
+//! results reported, one file at a time and in directory walk order.
+//!
 //! ## Example
 //!
 //! The rust crate author's [PDF
@@ -63,9 +184,9 @@
 //! @prefix dcterms: <https://purl.org/dc/terms/> .
 //! @prefix dcmi: <https://purl.org/dc/dcmi/> .
 //!
-//! <URN:sha256:b1674191a88ec5cdd733e4240a81803105dc412d6c6708d53ab94fc248f4f553>
+//! <urn:sha256:b1674191a88ec5cdd733e4240a81803105dc412d6c6708d53ab94fc248f4f553>
 //!     dcterms:title "Bitcoin: A Peer-to-Peer Electronic Cash System" ;
-//!     dcterms:subject "bitcoin,cryptocurrency,cryptography" ;
+//!     dcterms:subject "bitcoin", "cryptocurrency", "cryptography" ;
 //!	    dcterms:creator "Satoshi Nakamoto" ;
 //!	    dcterms:type "article" ;
 //!	    dcterms:MediaType "application/pdf" ;
@@ -84,6 +205,22 @@
 //! user.dcterms:type="article"
 //! ```
 //!
+//! ### Sidecar files
+//!
+//! Some filesystems, such as FAT, exFAT and many network mounts, do not support extended
+//! attributes. `apply` checks this once per filesystem, and reports files on such filesystems as
+//! failed.
+//!
+//! With `apply --sidecar`, the record is instead written in rdf-turtle to a `<file>.ttl` sidecar
+//! next to each file, or with `--sidecar=dir` to a single `.kitab.ttl` sidecar per directory.
+//! Records already in a `.kitab.ttl` sidecar are kept, unless they are for the same file as a
+//! record written. With `--dry-run`, the sidecar records that would be added or changed are
+//! listed.
+//!
+//! `import` reads sidecars back, and checks each record against the digests of the file next to
+//! it. A sidecar that no longer matches its file, e.g. because the file was changed, is reported
+//! as failed. See [sidecar](crate::sidecar).
+//!
 //! ### Optional: File magic
 //!
 //! If built with the `magic` feature, an attempt will be made to determine the media type for each
@@ -92,6 +229,52 @@
 //! Without the `magic` feature, the `dcterms.MediaType` will not be included in the metadata
 //! record.
 //!
+//! ### Optional: Embedded metadata
+//!
+//! If built with the `embedded` feature, `import` falls back to metadata embedded in the file
+//! itself when the file has no extended attributes: the Info dictionary and XMP packet of PDF
+//! documents, the Dublin Core elements of the EPUB package document, and ID3v2 tags and Vorbis
+//! comments of MP3, FLAC and Ogg audio. Title, creators, subjects, language, date and publisher
+//! are read, and the record is keyed by the digests of the file.
+//!
+//! With the same feature, `apply --embed` also writes these values into the file itself, for
+//! metadata that survives e-mail, HTTP and sync tools that drop extended attributes. Embedded
+//! values that the record has no counterpart for are kept. As this changes the contents of the
+//! file, the new digests of the file are added to the record in the store, so that the file
+//! still matches it afterwards.
+//!
+//! ## Errors
+//!
+//! Library functions return [Error](Error) rather than panicking on invalid input, e.g. a
+//! malformed source file, an unknown language tag or MIME type, or a filesystem without extended
+//! attribute support.
+//!
+//! The tool prints errors to standard error. Files under a path that cannot be read, parsed,
+//! stored or applied to are reported, and the remaining files are still processed.
+//!
+//! ### Run summary and exit status
+//!
+//! After `import` and `apply`, a summary of the files scanned, matched, imported per source
+//! format, applied, skipped and failed is printed. With `--report json` the summary is printed as
+//! a JSON object instead:
+//!
+//! ``` ignore;
+//! $ kitab --report json import testdata
+//! {"applied":0,"failed":0,"imported":{"biblatex":2,"rdf":3,"xattr":1},"matched":5,"scanned":5,"skipped":0}
+//! ```
+//!
+//! The JSON object is then the only output on standard output. Merge conflicts, and the changes
+//! and summary of `apply --dry-run`, are printed to standard error instead.
+//!
+//! Files larger than 1MB are not probed for rdf-turtle or bibtex content, and are counted as
+//! skipped unless they have metadata extended attributes.
+//!
+//! The exit status is:
+//!
+//! * `0` - success.
+//! * `1` - the command could not be run, e.g. for invalid arguments or an unavailable store.
+//! * `2` - the command ran, but one or more files failed.
+//!
 //! ## Debugging
 //!
 //! `kitab` uses [env_logger](env_logger). Loglevel can be set using the
@@ -111,14 +294,38 @@ pub mod dc;
 
 pub mod store;
 
+pub mod search;
+
 pub mod rdf;
 
 pub mod biblatex;
 
+pub mod csl;
+
+pub mod ris;
+
+#[cfg(feature = "embedded")]
+pub mod embedded;
+
 pub mod error;
+pub use error::Error;
 
 pub mod digest;
 
+#[cfg(feature = "digest_bzz")]
+pub mod swarm;
+
+#[cfg(feature = "digest_ipfs")]
+pub mod ipfs;
+
+pub mod cache;
+
+pub mod jobs;
+
+pub mod report;
+
+pub mod sidecar;
+
 #[cfg(test)]
 mod tests {
     use env_logger;
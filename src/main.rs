@@ -1,15 +1,21 @@
+use std::collections::BTreeMap;
 use std::default;
-use std::fs::{
-    File,
-    create_dir_all,
-    metadata,
+use std::fs::File;
+use std::io;
+use std::io::{
+    Write,
+    stdin,
+    stdout,
 };
-use std::io::Write;
 use std::path::{
     Path,
     PathBuf,
 };
 use std::str::FromStr;
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
 use env_logger;
 use clap::{
     App, 
@@ -20,8 +26,8 @@ use clap::{
 use directories::{
     BaseDirs,
 };
-use walkdir::WalkDir;
-use hex;
+use mime::Mime;
+use unic_langid_impl::LanguageIdentifier;
 use log::{
     debug,
     info,
@@ -30,21 +36,74 @@ use log::{
 
 use biblatex::EntryType;
 use kitab::rdf::{
-    read as rdf_read,
     read_all as rdf_read_all,
-    write as rdf_write,
 };
 use kitab::biblatex::{
     read_all as biblatex_read_all,
+    write_all as biblatex_write_all,
+};
+use kitab::csl::{
+    read_all as csl_read_all,
+    write_all as csl_write_all,
 };
+use kitab::ris::read_all as ris_read_all;
+use kitab::sidecar;
+use kitab::sidecar::{
+    SidecarChange,
+    SidecarMode,
+    XattrSupport,
+};
+#[cfg(feature = "embedded")]
+use kitab::embedded;
 use kitab::meta::{
     MetaData,
+    MergeConflict,
+    XattrChange,
     digests_from_path,
+    publish_date_from_str,
+    text_fields,
+    xattr_values_from_path,
+};
+use kitab::store::{
+    Store,
+    ConflictPolicy,
+    open as store_open,
 };
-use kitab::digest::from_urn;
+use kitab::search::Query;
+use kitab::digest::urn::parse as parse_digest;
 use kitab::digest::RecordDigest;
 use kitab::digest::DigestType;
+use kitab::digest::DIGEST_TYPE_NAMES;
+use kitab::cache::DigestCache;
+use kitab::jobs::{
+    files,
+    process_ordered,
+    process_ordered_while,
+    resolve_jobs,
+};
+use kitab::Error;
+use kitab::report::{
+    Report,
+    EXIT_ERROR,
+};
 
+/// Set when standard output is reserved for the machine readable report of `--report json`.
+static REPORT_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Print a line of output for the user, to standard error if standard output is reserved for
+/// the report.
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        match REPORT_JSON.load(Ordering::Relaxed) {
+            true => {
+                eprintln!($($arg)*);
+            },
+            false => {
+                println!($($arg)*);
+            },
+        }
+    };
+}
 
 fn args_setup() -> ArgMatches<'static> {
     let mut o = App::new("kitab");
@@ -55,7 +114,19 @@ fn args_setup() -> ArgMatches<'static> {
         .short("s")
         .long("store")
         .value_name("Store location")
+        .help("Store location, as directory path, file://<path> or sqlite://<path>")
+        .takes_value(true)
+        );
+    o = o.arg(clap::Arg::with_name("nocache")
+        .long("no-cache")
+        .help("Always calculate file digests, without using or updating the digest cache")
+        );
+    o = o.arg(clap::Arg::with_name("report")
+        .long("report")
+        .help("Format of the summary printed after import and apply")
         .takes_value(true)
+        .possible_values(&["text", "json"])
+        .default_value("text")
         );
         
     let mut o_import = (
@@ -67,16 +138,32 @@ fn args_setup() -> ArgMatches<'static> {
         Arg::with_name("setdigest")
         .short("d")
         .long("digest")
-        .help("Explicitly set digest")
+        .help("Explicitly set digest, as URN or digest type to calculate for files")
         .multiple(true)
         .takes_value(true)
         .number_of_values(1)
         );
+    o_import = o_import.arg(
+        Arg::with_name("onconflict")
+        .long("on-conflict")
+        .help("How to handle records already in the store")
+        .takes_value(true)
+        .possible_values(&["overwrite", "keep", "merge", "fail"])
+        .default_value("overwrite")
+        );
     o_import = o_import.arg(
         Arg::with_name("PATH")
         .help("Path to operate on")
         .required(true)
         );
+    o_import = o_import.arg(
+        Arg::with_name("jobs")
+        .short("j")
+        .long("jobs")
+        .help("Number of files to process concurrently, 0 for one per CPU")
+        .takes_value(true)
+        .default_value("1")
+        );
     o = o.subcommand(o_import);
 
     let mut o_apply = (
@@ -90,6 +177,34 @@ fn args_setup() -> ArgMatches<'static> {
         .required(true)
         .index(1)
         );
+    o_apply = o_apply.arg(
+        Arg::with_name("dryrun")
+        .short("n")
+        .long("dry-run")
+        .help("Only report changes to extended attributes, without applying them")
+        );
+    o_apply = o_apply.arg(
+        Arg::with_name("prune")
+        .long("prune")
+        .help("Remove metadata extended attributes that the record does not define")
+        );
+    #[cfg(feature = "embedded")]
+    {
+        o_apply = o_apply.arg(
+            Arg::with_name("embed")
+            .long("embed")
+            .help("Also write metadata into the contents of PDF, EPUB and audio files, adding their new digests to the record")
+            );
+    }
+    o_apply = o_apply.arg(
+        Arg::with_name("sidecar")
+        .long("sidecar")
+        .help("Write metadata to <file>.ttl sidecar files, or one .kitab.ttl per directory, instead of extended attributes")
+        .takes_value(true)
+        .min_values(0)
+        .require_equals(true)
+        .possible_values(&["file", "dir"])
+        );
     o_apply = o_apply.arg(
         Arg::with_name("adddigest")
         .short("d")
@@ -98,29 +213,164 @@ fn args_setup() -> ArgMatches<'static> {
         .multiple(true)
         .takes_value(true)
         .number_of_values(1)
+        .possible_values(DIGEST_TYPE_NAMES)
+        );
+    o_apply = o_apply.arg(
+        Arg::with_name("jobs")
+        .short("j")
+        .long("jobs")
+        .help("Number of files to process concurrently, 0 for one per CPU")
+        .takes_value(true)
+        .default_value("1")
         );
     o = o.subcommand(o_apply);
 
-//    let mut o_entry = (
-//       SubCommand::with_name("new")
-//        .about("add metadata for file")
-//        .version("0.0.1")
-//        );
-//
-//    o_entry = o_entry.arg(clap::Arg::with_name("validators")
-//         .long("validator")
-//         .value_name("Add given validator engine")
-//         .multiple(true)
-//         .takes_value(true)
-//         );
-//
-//    o_entry = o_entry.arg(
-//        Arg::with_name("PATH")
-//        .help("Path to operate on")
-//        .required(true)
-//        .index(1)
-//        );
-//    o = o.subcommand(o_entry);
+    let mut o_search = SubCommand::with_name("search")
+        .about("Search metadata in store")
+        .version("0.0.1");
+    o_search = o_search.arg(
+        Arg::with_name("title")
+        .long("title")
+        .help("Match substring of title")
+        .takes_value(true)
+        );
+    o_search = o_search.arg(
+        Arg::with_name("author")
+        .long("author")
+        .help("Match substring of author(s)")
+        .takes_value(true)
+        );
+    o_search = o_search.arg(
+        Arg::with_name("subject")
+        .long("subject")
+        .help("Match keyword in subject")
+        .takes_value(true)
+        );
+    o_search = o_search.arg(
+        Arg::with_name("language")
+        .long("language")
+        .help("Match language identifier")
+        .takes_value(true)
+        );
+    o_search = o_search.arg(
+        Arg::with_name("type")
+        .long("type")
+        .help("Match entry type")
+        .takes_value(true)
+        );
+    o_search = o_search.arg(
+        Arg::with_name("mime")
+        .long("mime")
+        .help("Match MIME type")
+        .takes_value(true)
+        );
+    o = o.subcommand(o_search);
+
+    let mut o_export = SubCommand::with_name("export")
+        .about("Export all metadata in store")
+        .version("0.0.1");
+    o_export = o_export.arg(
+        Arg::with_name("format")
+        .short("f")
+        .long("format")
+        .help("Output format")
+        .takes_value(true)
+        .possible_values(&["bibtex", "csl"])
+        .default_value("bibtex")
+        );
+    o = o.subcommand(o_export);
+
+    let mut o_entry = SubCommand::with_name("new")
+        .about("add metadata for file")
+        .version("0.0.1");
+    o_entry = o_entry.arg(
+        Arg::with_name("PATH")
+        .help("Path to operate on")
+        .required(true)
+        .index(1)
+        );
+    o_entry = o_entry.arg(
+        Arg::with_name("adddigest")
+        .short("d")
+        .long("digest")
+        .help("Additional digest to store")
+        .multiple(true)
+        .takes_value(true)
+        .number_of_values(1)
+        .possible_values(DIGEST_TYPE_NAMES)
+        );
+    o_entry = o_entry.arg(
+        Arg::with_name("title")
+        .long("title")
+        .help("Title of work")
+        .takes_value(true)
+        );
+    o_entry = o_entry.arg(
+        Arg::with_name("author")
+        .long("author")
+        .help("Author(s) of work, separated by \"and\", e.g. \"Nakamoto, Satoshi and {Government of Kenya}\"")
+        .takes_value(true)
+        );
+    o_entry = o_entry.arg(
+        Arg::with_name("subject")
+        .long("subject")
+        .help("Comma-separated keyword list")
+        .takes_value(true)
+        );
+    o_entry = o_entry.arg(
+        Arg::with_name("language")
+        .long("language")
+        .help("Language identifier of work")
+        .takes_value(true)
+        );
+    o_entry = o_entry.arg(
+        Arg::with_name("type")
+        .long("type")
+        .help("Entry type of work, as bibtex entry type")
+        .takes_value(true)
+        );
+    o_entry = o_entry.arg(
+        Arg::with_name("mime")
+        .long("mime")
+        .help("MIME type of file")
+        .takes_value(true)
+        );
+    o_entry = o_entry.arg(
+        Arg::with_name("issued")
+        .long("issued")
+        .help("Publication date of work, as YYYY, YYYY-MM or YYYY-MM-DD")
+        .takes_value(true)
+        );
+    for (k, _, _, _, _) in text_fields() {
+        o_entry = o_entry.arg(
+            Arg::with_name(k)
+            .long(k)
+            .help("Value of the Dublin Core term of the same name")
+            .takes_value(true)
+            );
+    }
+    o_entry = o_entry.arg(
+        Arg::with_name("xattr")
+        .short("x")
+        .long("xattr")
+        .help("Also apply metadata as extended attributes on file")
+        );
+    o_entry = o_entry.arg(
+        Arg::with_name("batch")
+        .short("b")
+        .long("batch")
+        .help("Do not prompt for values not given as options")
+        );
+    o = o.subcommand(o_entry);
+
+    let mut o_cache = SubCommand::with_name("cache")
+        .about("Manage the file digest cache")
+        .version("0.0.1");
+    o_cache = o_cache.subcommand(
+        SubCommand::with_name("prune")
+        .about("Remove cached digests of files which have been changed or removed")
+        );
+    o = o.subcommand(o_cache);
 
     o.get_matches()
 }
@@ -130,18 +380,6 @@ fn args_setup() -> ArgMatches<'static> {
 // kitab apply <path> - recursively 
 
     fn resolve_directory(args: &ArgMatches) -> PathBuf {
-        let r = match args.value_of("store") {
-            Some(v) => {
-                v
-            },
-            _ => {
-                ""
-            },
-        };
-        if r.len() != 0 {
-            return PathBuf::from(r)
-        }
-        
 
         match BaseDirs::new() {
             Some(v) => {
@@ -157,6 +395,46 @@ fn args_setup() -> ArgMatches<'static> {
     }
 }
 
+/// Print the error and exit with a non-zero status.
+fn fail(e: &dyn std::fmt::Display) -> ! {
+    eprintln!("kitab: {}", e);
+    std::process::exit(EXIT_ERROR);
+}
+
+/// Outcome of probing a file for import sources.
+enum ImportSource {
+    /// Records read from the source of the given format.
+    Found(&'static str, Vec<MetaData>),
+    /// No valid import source.
+    NotFound,
+    /// Contents not probed because of the file size.
+    Skipped,
+    /// The file could not be read.
+    Failed(Error),
+}
+
+fn resolve_store(args: &ArgMatches) -> String {
+    if let Some(v) = args.value_of("store") {
+        if !v.is_empty() {
+            return String::from(v);
+        }
+    }
+    let d = resolve_directory(args);
+    d.to_string_lossy().into_owned()
+}
+
+/// The digest cache is kept next to the store, in a file with the `.digests` suffix.
+fn resolve_cache(store_locator: &str) -> PathBuf {
+    let mut p = store_locator;
+    for scheme in ["file://", "sqlite://"] {
+        if let Some(v) = p.strip_prefix(scheme) {
+            p = v;
+        }
+    }
+    let p = p.trim_end_matches("/");
+    PathBuf::from(format!("{}.digests", p))
+}
+
 fn str_to_path(args: &ArgMatches) -> PathBuf {
     let mut p_canon: PathBuf;
     match args.value_of("PATH") {
@@ -167,42 +445,47 @@ fn str_to_path(args: &ArgMatches) -> PathBuf {
                     p_canon = v.clone();
                 },
                 Err(e) => {
-                    panic!("path error: {}", e);
+                    fail(&format!("{}: {}", v, e));
                 },
             };
         },
         None => {
-            panic!("path required"); 
+            fail(&"path required");
         },
     }
     p_canon
 }
 
-fn store(index_path: &Path, m: &MetaData) {
-    let fp = index_path.join(m.fingerprint());
-    create_dir_all(&index_path);
-    debug!("writing record for title {} to {:?}", m.title(), &fp);
-
-    let ff = File::create(&fp).unwrap();
-    rdf_write(&m, &ff).unwrap();
-    debug!("stored as rdf {:?}", fp);
+fn store(st: &dyn Store, m: &MetaData) -> bool {
+    debug!("writing record for title {}", m.title());
+    store_result(m, st.put(m))
 }
 
-fn exec_import_xattr(f: &Path, index_path: &Path, digests: &Vec<RecordDigest>) -> bool {
-    let mut m = match MetaData::from_xattr(f) {
-        Ok(r) => {
-            r
-        }
+/// Report the result of adding the record to the store.
+fn store_result(m: &MetaData, r: Result<Vec<MergeConflict>, Error>) -> bool {
+    match r {
+        Ok(conflicts) => {
+            for v in conflicts {
+                outln!("{}\t{}: kept \"{}\", discarded \"{}\"", m.urn(), v.field, v.existing, v.other);
+            }
+            debug!("stored as rdf {:?}", m);
+            true
+        },
+        Err(e @ Error::AlreadyExists(_)) => {
+            eprintln!("kitab: {}", e);
+            false
+        },
         Err(e) => {
-            return false;
-        }
-    };
-
-    debug!("successfully processed xattr import source");
-
-    info!("importing xattr source {:?}", &m);
+            eprintln!("kitab: failed to store {}: {}", m.urn(), e);
+            false
+        },
+    }
+}
 
-    let mut digest_types: Vec<DigestType> = vec!();
+/// Returns the digest types to calculate for sources that are keyed by the digests of the file
+/// itself.
+fn file_digest_types(digests: &[RecordDigest]) -> Vec<DigestType> {
+    let mut digest_types: Vec<DigestType> = vec!(DigestType::Sha512);
 
     for v in digests.iter() {
         match v {
@@ -213,131 +496,792 @@ fn exec_import_xattr(f: &Path, index_path: &Path, digests: &Vec<RecordDigest>) -
                 digest_types.push(DigestType::Sha512);
             },
             _ => {
-                warn!("digest specifier {:?} is invalid in xattr import context.", v);
+                warn!("digest specifier {:?} is invalid in xattr import context.", v);
             },
         };
     }
+    digest_types
+}
 
-    for v in digests_from_path(f, &digest_types) {
-        m.set_fingerprint(v);
-        store(index_path, &m);
-    }
-    true
+fn exec_import_xattr(f: &Path, digests: &[RecordDigest], cache: Option<&DigestCache>) -> Option<Vec<MetaData>> {
+    let digest_types = file_digest_types(digests);
+
+    let m = match MetaData::from_xattr_digests(f, &digest_types, cache) {
+        Ok(r) => {
+            r
+        }
+        Err(e) => {
+            debug!("no xattr import source in {:?}: {}", f, e);
+            return None;
+        }
+    };
+
+    debug!("successfully processed xattr import source");
+    Some(vec!(m))
+}
+
+#[cfg(feature = "embedded")]
+fn exec_import_embedded(f: &Path, digests: &[RecordDigest], cache: Option<&DigestCache>) -> Option<Vec<MetaData>> {
+    let digest_types = file_digest_types(digests);
+
+    let m = match embedded::from_path(f, &digest_types, cache) {
+        Ok(r) => {
+            r
+        }
+        Err(e) => {
+            debug!("no embedded import source in {:?}: {}", f, e);
+            return None;
+        }
+    };
+
+    debug!("successfully processed embedded import source");
+    Some(vec!(m))
 }
 
-fn exec_import_rdf(f: &Path, index_path: &Path) -> bool {
-    let f = File::open(f).unwrap();
-    let entries = match rdf_read_all(&f) {
+fn exec_import_rdf(f: &Path) -> Option<Vec<MetaData>> {
+    let entries = match File::open(f).map_err(Error::from).and_then(rdf_read_all) {
         Ok(v) => {
             v
         },
         Err(e) => {
-            return false;
+            debug!("no rdf import source in {:?}: {}", f, e);
+            return None;
         }
     };
 
     debug!("successfully processed rdf import source");
-
-    for m in entries {
-        info!("importing rdf source {:?}", &m);
-        store(index_path, &m);
-    }
-    true
+    Some(entries)
 }
 
-fn exec_import_biblatex(f: &Path, index_path: &Path, digests: &Vec<RecordDigest>) -> bool {
-    let f = File::open(f).unwrap();
-    let entries = match biblatex_read_all(&f, digests) {
+fn exec_import_biblatex(f: &Path, digests: &Vec<RecordDigest>) -> Option<Vec<MetaData>> {
+    let entries = match File::open(f).map_err(Error::from).and_then(|v| biblatex_read_all(v, digests)) {
         Ok(v) => {
             v
         },
         Err(e) => {
-            return false;
+            debug!("no biblatex import source in {:?}: {}", f, e);
+            return None;
         }       
     };
 
     debug!("successfully processed biblatex import source");
+    Some(entries)
+}
 
-    for m in entries {
-        info!("importing biblatex source {:?}", &m);
-        store(index_path, &m);
-    }
+fn exec_import_csl(f: &Path, digests: &Vec<RecordDigest>) -> Option<Vec<MetaData>> {
+    let entries = match File::open(f).map_err(Error::from).and_then(|v| csl_read_all(v, digests)) {
+        Ok(v) => {
+            v
+        },
+        Err(e) => {
+            debug!("no csl-json import source in {:?}: {}", f, e);
+            return None;
+        }
+    };
 
-    true
+    debug!("successfully processed csl-json import source");
+    Some(entries)
+}
+
+fn exec_import_ris(f: &Path, digests: &Vec<RecordDigest>) -> Option<Vec<MetaData>> {
+    let entries = match File::open(f).map_err(Error::from).and_then(|v| ris_read_all(v, digests)) {
+        Ok(v) => {
+            v
+        },
+        Err(e) => {
+            debug!("no ris import source in {:?}: {}", f, e);
+            return None;
+        }
+    };
+
+    debug!("successfully processed ris import source");
+    Some(entries)
 }
 
-fn exec_apply(p: &Path, index_path: &Path, mut extra_digest_types: Vec<DigestType>) -> bool {
+/// Options of the apply subcommand.
+struct ApplyOptions {
+    /// Report the changes without writing them.
+    dry_run: bool,
+    /// Remove extended attributes that the record does not define.
+    prune: bool,
+    /// Write the record into the file contents where supported.
+    embed: bool,
+    /// Write sidecar files in the given mode instead of extended attributes.
+    sidecar: Option<SidecarMode>,
+    /// Number of files hashed concurrently.
+    jobs: usize,
+}
+
+fn exec_apply(p: &Path, st: &dyn Store, mut extra_digest_types: Vec<DigestType>, opts: ApplyOptions, cache: Option<&DigestCache>, report: &mut Report) {
+    let ApplyOptions{ dry_run, prune, embed, sidecar, jobs } = opts;
     let mut digest_types: Vec<DigestType> = vec!(DigestType::Sha512);
     digest_types.append(&mut extra_digest_types);
-    for entry in WalkDir::new(&p)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| !e.file_type().is_dir()) {
-            let ep = entry.path();
-            for digest in digests_from_path(ep, &digest_types) {
-                let z_hex = hex::encode(digest.fingerprint());
-
-                let fp = index_path.join(&z_hex);
-                match fp.canonicalize() {
-                    Ok(v) => {
-                        let f = File::open(&v).unwrap();
-                        let m = rdf_read(f);
-                        info!("apply {:?} -> {:?}", entry, &m);
-                        m.to_xattr(&ep);
-                    },
-                    Err(e) => {
-                        debug!("metadata not found for {:?} -> {:?}", entry, z_hex);
-                    },
-                };
+    let mut count_match: usize = 0;
+    let mut count_change: usize = 0;
+    let mut count_add: usize = 0;
+    let mut count_modify: usize = 0;
+    let mut count_remove: usize = 0;
+    let mut xattr_support = XattrSupport::new();
+    let mut sidecar_dirs: BTreeMap<PathBuf, Vec<MetaData>> = BTreeMap::new();
+    let work = |ep: &PathBuf| {
+        digests_from_path(ep, &digest_types, cache)
+    };
+    process_ordered(&files(p), jobs, work, |ep, digests| {
+        let ep = ep.as_path();
+        report.scanned += 1;
+        let digests = match digests {
+            Ok(v) => {
+                v
+            },
+            Err(e) => {
+                eprintln!("kitab: {}: {}", ep.display(), e);
+                report.failed += 1;
+                return;
+            },
+        };
+        for digest in digests {
+            match st.get(&digest) {
+                Ok(m) => {
+                    count_match += 1;
+                    report.matched += 1;
+                    if !dry_run {
+                        let m = match embed {
+                            true => {
+                                match exec_embed(ep, st, &m, &digest_types, cache) {
+                                    Some(v) => {
+                                        v
+                                    },
+                                    None => {
+                                        report.failed += 1;
+                                        break;
+                                    },
+                                }
+                            },
+                            false => {
+                                m
+                            },
+                        };
+                        info!("apply {:?} -> {:?}", ep, &m);
+                        let r = match sidecar {
+                            Some(SidecarMode::File) => {
+                                sidecar::write(&m, ep)
+                            },
+                            Some(SidecarMode::Directory) => {
+                                let dir = sidecar::sidecar_path(ep, SidecarMode::Directory);
+                                let dir = dir.parent().unwrap_or(Path::new("")).to_path_buf();
+                                sidecar_dirs.entry(dir).or_insert(vec!()).push(m);
+                                break;
+                            },
+                            None => {
+                                match xattr_support.supported(ep) {
+                                    Ok(true) => {
+                                        m.to_xattr(ep).and_then(|_| {
+                                            match prune {
+                                                true => {
+                                                    m.prune_xattr(ep)
+                                                },
+                                                false => {
+                                                    Ok(())
+                                                },
+                                            }
+                                        })
+                                    },
+                                    Ok(false) => {
+                                        Err(Error::XattrUnsupported(ep.to_path_buf()))
+                                    },
+                                    Err(e) => {
+                                        Err(e)
+                                    },
+                                }
+                            },
+                        };
+                        match r {
+                            Ok(_) => {
+                                report.applied += 1;
+                            },
+                            Err(e @ Error::XattrUnsupported(_)) => {
+                                eprintln!("kitab: {}, use --sidecar to write sidecar files instead", e);
+                                report.failed += 1;
+                            },
+                            Err(e) => {
+                                eprintln!("kitab: {}: {}", ep.display(), e);
+                                report.failed += 1;
+                            },
+                        };
+                        break;
+                    }
+
+                    if let Some(mode) = sidecar {
+                        let sidecar_path = sidecar::sidecar_path(ep, mode);
+                        match sidecar::diff(&m, ep, mode) {
+                            Ok(Some(v)) => {
+                                count_change += 1;
+                                outln!("{}", ep.display());
+                                match v {
+                                    SidecarChange::Add => {
+                                        count_add += 1;
+                                        outln!("\t+ {} {}", sidecar_path.display(), m.urn());
+                                    },
+                                    SidecarChange::Change => {
+                                        count_modify += 1;
+                                        outln!("\t~ {} {}", sidecar_path.display(), m.urn());
+                                    },
+                                };
+                            },
+                            Ok(None) => {},
+                            Err(e) => {
+                                eprintln!("kitab: {}: {}", sidecar_path.display(), e);
+                                report.failed += 1;
+                            },
+                        };
+                        break;
+                    }
+
+                    let current = match xattr_values_from_path(ep) {
+                        Ok(v) => {
+                            v
+                        },
+                        Err(e) => {
+                            eprintln!("kitab: {}: {}", ep.display(), e);
+                            report.failed += 1;
+                            break;
+                        },
+                    };
+                    let changes = m.xattr_diff(&current, prune);
+                    if changes.is_empty() {
+                        break;
+                    }
+                    count_change += 1;
+                    outln!("{}", ep.display());
+                    for v in changes {
+                        match v {
+                            XattrChange::Add(k, new) => {
+                                count_add += 1;
+                                outln!("\t+ {} {:?}", k, new);
+                            },
+                            XattrChange::Change(k, old, new) => {
+                                count_modify += 1;
+                                outln!("\t~ {} {:?} -> {:?}", k, old, new);
+                            },
+                            XattrChange::Remove(k, old) => {
+                                count_remove += 1;
+                                outln!("\t- {} {:?}", k, old);
+                            },
+                        };
+                    }
+                    break;
+                },
+                Err(e) => {
+                    debug!("metadata not found for {:?} -> {:?}: {}", ep, digest, e);
+                },
+            };
+        }
+    });
+    for (dir, entries) in sidecar_dirs.iter() {
+        info!("write {} records to sidecar in {:?}", entries.len(), dir);
+        match sidecar::write_dir(entries, dir) {
+            Ok(_) => {
+                report.applied += entries.len();
+            },
+            Err(e) => {
+                eprintln!("kitab: {}: {}", dir.display(), e);
+                report.failed += entries.len();
+            },
+        };
+    }
+    if dry_run {
+        match sidecar {
+            Some(_) => {
+                outln!("{} files matched, {} would change: {} sidecar records added, {} changed", count_match, count_change, count_add, count_modify);
+            },
+            None => {
+                outln!("{} files matched, {} would change: {} attributes added, {} changed, {} removed", count_match, count_change, count_add, count_modify, count_remove);
+            },
+        };
+    }
+}
+
+/// Write the record into the contents of the file, and add the new digests of the file to the
+/// record in the store.
+///
+/// Files in unsupported containers are left as they are. Returns the record with the new digests,
+/// or `None` if the file or the store could not be written.
+#[cfg(feature = "embedded")]
+fn exec_embed(ep: &Path, st: &dyn Store, m: &MetaData, digest_types: &Vec<DigestType>, cache: Option<&DigestCache>) -> Option<MetaData> {
+    match embedded::container_from_path(ep) {
+        Ok(Some(_)) => {},
+        Ok(None) => {
+            debug!("no embedded metadata support for {:?}, skipping embed", ep);
+            return Some(m.clone());
+        },
+        Err(e) => {
+            eprintln!("kitab: {}: {}", ep.display(), e);
+            return None;
+        },
+    };
+
+    info!("embed {:?} -> {:?}", ep, m);
+    let mut m = m.clone();
+    let r = embedded::to_path(&m, ep)
+        .and_then(|_| {
+            digests_from_path(ep, digest_types, cache)
+        })
+        .and_then(|digests| {
+            for v in digests {
+                m.add_digest(v);
             }
+            debug!("adding embedded file digests {:?} to record {}", m.aliases(), m.urn());
+            st.replace(&m)
+        });
+    match r {
+        Ok(_) => {
+            Some(m)
+        },
+        Err(e) => {
+            eprintln!("kitab: {}: {}", ep.display(), e);
+            None
+        },
+    }
+}
+
+#[cfg(not(feature = "embedded"))]
+fn exec_embed(_ep: &Path, _st: &dyn Store, m: &MetaData, _digest_types: &Vec<DigestType>, _cache: Option<&DigestCache>) -> Option<MetaData> {
+    Some(m.clone())
+}
+
+fn exec_search(st: &dyn Store, args: &ArgMatches) -> bool {
+    let mut q = Query::new();
+    q.title = args.value_of("title").map(String::from);
+    q.creator = args.value_of("author").map(String::from);
+    q.subject = args.value_of("subject").map(String::from);
+    q.language = args.value_of("language").map(String::from);
+    q.typ = args.value_of("type").map(String::from);
+    q.mime = args.value_of("mime").map(String::from);
+
+    let r = st.search(&q);
+    for m in r.iter() {
+        println!("{}\t{}\t{}", m.urn(), m.title(), m.author());
     }
+    debug!("search matched {} entries", r.len());
     true
 }
 
-fn exec_import(p: &Path, index_path: &Path, digests: Vec<RecordDigest>) {
-    for entry in WalkDir::new(&p)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| !e.file_type().is_dir()) {
+fn exec_export(st: &dyn Store, format: &str) -> bool {
+    let entries: Vec<MetaData> = st.iter().collect();
+    debug!("export {} entries", entries.len());
 
-        let fp = entry.path();
-        debug!("attempt xattr import {:?}", fp);
-        if exec_import_xattr(fp, index_path, &digests) {
-            continue;
+    match format {
+        "bibtex" => {
+            match biblatex_write_all(&entries, stdout()) {
+                Ok(_) => {
+                    true
+                },
+                Err(e) => {
+                    warn!("bibtex export failed: {:?}", e);
+                    false
+                },
+            }
+        },
+        "csl" => {
+            match csl_write_all(&entries, stdout()) {
+                Ok(_) => {
+                    true
+                },
+                Err(e) => {
+                    warn!("csl-json export failed: {:?}", e);
+                    false
+                },
+            }
+        },
+        _ => {
+            false
+        },
+    }
+}
+
+/// Read metadata from the first valid import source format of the file, if any.
+fn read_import_source(fp: &Path, digests: &Vec<RecordDigest>, cache: Option<&DigestCache>) -> ImportSource {
+    if sidecar::is_sidecar(fp) {
+        debug!("attempt sidecar import {:?}", fp);
+        return match sidecar::read(fp, cache) {
+            Ok(v) => {
+                debug!("successfully processed sidecar import source");
+                ImportSource::Found("sidecar", v)
+            },
+            Err(e) => {
+                ImportSource::Failed(e)
+            },
+        };
+    }
+
+    debug!("attempt xattr import {:?}", fp);
+    if let Some(v) = exec_import_xattr(fp, digests, cache) {
+        return ImportSource::Found("xattr", v);
+    }
+
+    #[cfg(feature = "embedded")]
+    {
+        debug!("attempt embedded import {:?}", fp);
+        if let Some(v) = exec_import_embedded(fp, digests, cache) {
+            return ImportSource::Found("embedded", v);
         }
+    }
 
-        let st = entry.metadata().unwrap();
-        if st.len() > 1048576 {
-            warn!("skipping metadata content probe for file >1MB");
-            continue;
+    let fst = match fp.metadata() {
+        Ok(v) => {
+            v
+        },
+        Err(e) => {
+            return ImportSource::Failed(Error::from(e));
+        },
+    };
+    if fst.len() > 1048576 {
+        warn!("skipping metadata content probe for file >1MB");
+        return ImportSource::Skipped;
+    }
+
+    debug!("attempt rdf import {:?}", fp);
+    if let Some(v) = exec_import_rdf(fp) {
+        return ImportSource::Found("rdf", v);
+    }
+
+    debug!("attempt csl-json import {:?}", fp);
+    if let Some(v) = exec_import_csl(fp, digests) {
+        return ImportSource::Found("csl", v);
+    }
+
+    debug!("attempt biblatex import {:?}", fp);
+    if let Some(v) = exec_import_biblatex(fp, digests) {
+        return ImportSource::Found("biblatex", v);
+    }
+
+    debug!("attempt ris import {:?}", fp);
+    if let Some(v) = exec_import_ris(fp, digests) {
+        return ImportSource::Found("ris", v);
+    }
+    ImportSource::NotFound
+}
+
+/// Import records from all files under the path.
+///
+/// Returns [Error::AlreadyExists](Error::AlreadyExists) if the import was aborted because an entry
+/// was refused by the `fail` conflict policy.
+fn exec_import(p: &Path, st: &dyn Store, digests: Vec<RecordDigest>, cache: Option<&DigestCache>, jobs: usize, report: &mut Report) -> Result<(), Error> {
+    let mut abort: Option<Error> = None;
+    let work = |fp: &PathBuf| {
+        read_import_source(fp, &digests, cache)
+    };
+    process_ordered_while(&files(p), jobs, work, |fp, r| {
+        report.scanned += 1;
+        match r {
+            ImportSource::Found(source, entries) => {
+                report.matched += 1;
+                let mut ok = true;
+                let results = st.put_all(&entries);
+                for (m, r) in entries.iter().zip(results) {
+                    info!("importing {} source {:?} from {:?}", source, m, fp);
+                    match r {
+                        Err(e @ Error::AlreadyExists(_)) => {
+                            abort = Some(e);
+                            ok = false;
+                        },
+                        r => {
+                            if store_result(m, r) {
+                                report.add_imported(source);
+                            } else {
+                                ok = false;
+                            }
+                        },
+                    };
+                }
+                if !ok {
+                    report.failed += 1;
+                }
+            },
+            ImportSource::NotFound => {
+                debug!("no import source in {:?}", fp);
+            },
+            ImportSource::Skipped => {
+                report.skipped += 1;
+            },
+            ImportSource::Failed(e) => {
+                eprintln!("kitab: {}: {}", fp.display(), e);
+                report.failed += 1;
+            },
+        };
+        abort.is_none()
+    });
+    match abort {
+        Some(e) => {
+            Err(e)
+        },
+        None => {
+            Ok(())
+        },
+    }
+}
+
+/// Prompt for a value on standard input.
+///
+/// Returns `None` at end of input.
+fn prompt(label: &str, default: Option<String>) -> Result<Option<String>, Error> {
+    match &default {
+        Some(v) => {
+            print!("{} ({}): ", label, v);
+        },
+        None => {
+            print!("{}: ", label);
+        },
+    };
+    stdout().flush()?;
+
+    let mut s = String::new();
+    if stdin().read_line(&mut s)? == 0 {
+        return Ok(None);
+    }
+    let v = s.trim();
+    if v.is_empty() {
+        return Ok(Some(default.unwrap_or_default()));
+    }
+    Ok(Some(String::from(v)))
+}
+
+/// Resolve value for a metadata field from the given option, or prompt for it.
+///
+/// The value is passed to `check`, and a value failing the check is prompted for again. If
+/// `batch` is set, the default is used instead of prompting, and an invalid value results in `None`.
+///
+/// Returns an error if standard input ends before a valid value is given.
+fn entry_value(args: &ArgMatches, key: &str, label: &str, default: Option<String>, batch: bool, check: &dyn Fn(&str) -> bool) -> Result<Option<String>, Error> {
+    if let Some(v) = args.value_of(key) {
+        if check(v) {
+            return Ok(Some(String::from(v)));
+        }
+        warn!("invalid value for {}: {}", label, v);
+        return Ok(None);
+    }
+    if batch {
+        let v = default.unwrap_or_default();
+        if check(&v) {
+            return Ok(Some(v));
+        }
+        warn!("missing value for {}", label);
+        return Ok(None);
+    }
+    loop {
+        let v = match prompt(label, default.clone())? {
+            Some(v) => {
+                v
+            },
+            None => {
+                println!();
+                let e = io::Error::new(io::ErrorKind::UnexpectedEof, format!("end of input, no value for {}", label));
+                return Err(Error::from(e));
+            },
+        };
+        if check(&v) {
+            return Ok(Some(v));
         }
+        eprintln!("invalid value for {}: \"{}\"", label, v);
+    }
+}
+
+/// Build a new record for the file from the options given, prompting for missing values unless
+/// `batch` is set.
+///
+/// Returns `None` if a value is invalid or missing.
+#[cfg_attr(not(feature = "magic"), allow(unused_variables))]
+fn entry_from_args(p: &Path, args: &ArgMatches, batch: bool) -> Result<Option<MetaData>, Error> {
+    let mut m = MetaData::empty();
+
+    let required = |v: &str| {
+        !v.is_empty()
+    };
+    let optional = |_v: &str| {
+        true
+    };
+
+    match entry_value(args, "title", "Title", None, batch, &required)? {
+        Some(v) => {
+            m.set_title(&v);
+        },
+        None => {
+            return Ok(None);
+        },
+    };
+
+    match entry_value(args, "author", "Author", None, batch, &required)? {
+        Some(v) => {
+            m.set_author(&v);
+        },
+        None => {
+            return Ok(None);
+        },
+    };
 
-        debug!("attempt rdf import {:?}", fp);
-        if exec_import_rdf(fp, index_path) { 
-            continue;
-        } 
+    let check_typ = |v: &str| {
+        EntryType::from_str(&v.to_lowercase()).is_ok()
+    };
+    match entry_value(args, "type", "Type", Some(String::from("misc")), batch, &check_typ)? {
+        Some(v) => {
+            m.set_typ(&v.to_lowercase());
+        },
+        None => {
+            return Ok(None);
+        },
+    };
 
-        debug!("attempt biblatex import {:?}", fp);
-        if exec_import_biblatex(fp, index_path, &digests) {
-            continue;
+    if let Some(v) = entry_value(args, "subject", "Subject (comma,separated list)", None, batch, &optional)? {
+        if !v.is_empty() {
+            m.set_subject(&v);
         }
     }
+
+    let check_language = |v: &str| {
+        v.is_empty() || v.parse::<LanguageIdentifier>().is_ok()
+    };
+    match entry_value(args, "language", "Language", None, batch, &check_language)? {
+        Some(v) => {
+            if !v.is_empty() {
+                m.set_language(&v)?;
+            }
+        },
+        None => {
+            return Ok(None);
+        },
+    };
+
+    #[cfg(feature = "magic")]
+    m.set_mime_magic(p);
+
+    let mime_default = m.mime().map(|v| v.to_string());
+    let check_mime = |v: &str| {
+        v.is_empty() || Mime::from_str(v).is_ok()
+    };
+    match entry_value(args, "mime", "Mime", mime_default, batch, &check_mime)? {
+        Some(v) => {
+            if !v.is_empty() {
+                m.set_mime_str(&v)?;
+            }
+        },
+        None => {
+            return Ok(None);
+        },
+    };
+
+    let check_issued = |v: &str| {
+        v.is_empty() || publish_date_from_str(v).is_ok()
+    };
+    match entry_value(args, "issued", "Issued (YYYY[-MM[-DD]])", None, batch, &check_issued)? {
+        Some(v) => {
+            if !v.is_empty() {
+                m.set_publish_date(publish_date_from_str(&v)?);
+            }
+        },
+        None => {
+            return Ok(None);
+        },
+    };
+
+    for (k, _, _, _, set) in text_fields() {
+        let label = format!("{}{}", k[..1].to_uppercase(), &k[1..]);
+        if let Some(v) = entry_value(args, k, &label, None, batch, &optional)? {
+            if !v.is_empty() {
+                set(&mut m, &v);
+            }
+        }
+    }
+
+    Ok(Some(m))
 }
 
-fn exec_entry(p: &Path, index_path: &Path) -> bool {
+fn exec_entry(p: &Path, st: &dyn Store, args: &ArgMatches, mut extra_digest_types: Vec<DigestType>, cache: Option<&DigestCache>) -> bool {
     if !p.is_file() {
         return false; 
     }
-    true
+
+    let batch = args.is_present("batch");
+
+    let mut digest_types: Vec<DigestType> = vec!(DigestType::Sha512);
+    digest_types.append(&mut extra_digest_types);
+    let digests = match digests_from_path(p, &digest_types, cache) {
+        Ok(v) => {
+            v
+        },
+        Err(e) => {
+            eprintln!("kitab: {}: {}", p.display(), e);
+            return false;
+        },
+    };
+
+    let mut m = match entry_from_args(p, args, batch) {
+        Ok(Some(v)) => {
+            v
+        },
+        Ok(None) => {
+            return false;
+        },
+        Err(e) => {
+            eprintln!("kitab: {}: {}", p.display(), e);
+            return false;
+        },
+    };
+
+    if !m.validate() {
+        eprintln!("kitab: {}: invalid entry, title and at least one author are required", p.display());
+        return false;
+    }
+
+    if args.is_present("xattr") {
+        if let Err(e) = m.to_xattr(p) {
+            eprintln!("kitab: could not apply xattr for {}: {}", p.display(), e);
+        }
+    }
+
+    for digest in digests {
+        m.add_digest(digest);
+    }
+    info!("new entry {:?}", &m);
+    store(st, &m)
 }
 
 fn main() {
     env_logger::init();
 
     let args = args_setup();
+    REPORT_JSON.store(args.value_of("report") == Some("json"), Ordering::Relaxed);
+
+    let mut report: Option<Report> = None;
+
+    let store_locator = resolve_store(&args);
+    info!("have store {:?}", &store_locator);
+    let mut st = match store_open(&store_locator) {
+        Ok(v) => {
+            v
+        },
+        Err(e) => {
+            fail(&format!("cannot open store {}: {}", store_locator, e));
+        },
+    };
 
-    let index_dir = resolve_directory(&args);
-    info!("have index directory {:?}", &index_dir);
+    let cache = match args.is_present("nocache") {
+        true => {
+            None
+        },
+        false => {
+            let cache_path = resolve_cache(&store_locator);
+            match DigestCache::open(&cache_path) {
+                Ok(v) => {
+                    info!("have digest cache {:?} with {} entries", &cache_path, v.len());
+                    Some(v)
+                },
+                Err(e) => {
+                    warn!("cannot open digest cache {:?}, continuing without: {}", &cache_path, e);
+                    None
+                },
+            }
+        },
+    };
    
     match args.subcommand_matches("import") {
         Some(arg) => {
@@ -346,7 +1290,7 @@ fn main() {
             match arg.values_of("setdigest") {
                 Some(r) => {
                     for digest_str in r {
-                        match from_urn(&digest_str) {
+                        match parse_digest(digest_str) {
                             Ok(digest) => {
                                 info!("using digest {}", digest_str);
                                 digests.push(digest);
@@ -357,7 +1301,7 @@ fn main() {
                                         v
                                     },
                                     Err(e) => {
-                                        panic!("invalid digest specifier: {:?}", e);
+                                        fail(&e);
                                     },
                                 };
                                 let digest_empty = RecordDigest::EmptyWithType(digest_type);
@@ -368,14 +1312,49 @@ fn main() {
                 },
                 None => {},
             };
-            info!("import from path {:?}", &p);
-            return exec_import(&p, index_dir.as_path(), digests);
+            let policy = match ConflictPolicy::from_str(arg.value_of("onconflict").unwrap()) {
+                Ok(v) => {
+                    v
+                },
+                Err(e) => {
+                    fail(&e);
+                },
+            };
+            st.set_policy(policy);
+            let jobs = match arg.value_of("jobs").unwrap().parse::<usize>() {
+                Ok(v) => {
+                    resolve_jobs(v)
+                },
+                Err(e) => {
+                    fail(&format!("{}, {}", Error::field("jobs", arg.value_of("jobs").unwrap()), e));
+                },
+            };
+            info!("import from path {:?} with conflict policy {:?} and {} jobs", &p, policy, jobs);
+            let mut rpt = Report::new();
+            if let Err(e) = exec_import(&p, st.as_ref(), digests, cache.as_ref(), jobs, &mut rpt) {
+                eprintln!("kitab: {}, import aborted", e);
+            }
+            report = Some(rpt);
         },
         _ => {},
     };
 
 
     let mut r = true;
+    if let Some(arg) = args.subcommand_matches("search") {
+        if !exec_search(st.as_ref(), arg) {
+            r = false;
+        }
+    }
+
+    if let Some(arg) = args.subcommand_matches("export") {
+        let format = arg.value_of("format").unwrap();
+        info!("export to format {}", format);
+        if !exec_export(st.as_ref(), format) {
+            r = false;
+        }
+    }
+
     match args.subcommand_matches("apply") {
         Some(arg) => {
             let p = str_to_path(&arg);
@@ -389,7 +1368,7 @@ fn main() {
                                 digests.push(digest);
                             },
                             Err(e) => {
-                                panic!("invalid digest URN: {:?}", e);
+                                fail(&e);
                             },
                         }
                     }
@@ -397,22 +1376,101 @@ fn main() {
                 None => {},
             };
 
-            info!("apply from path {:?}", &p);
-            if !exec_apply(p.as_path(), index_dir.as_path(), digests) {
-                r = false; 
-            }
+            let jobs = match arg.value_of("jobs").unwrap().parse::<usize>() {
+                Ok(v) => {
+                    resolve_jobs(v)
+                },
+                Err(e) => {
+                    fail(&format!("{}, {}", Error::field("jobs", arg.value_of("jobs").unwrap()), e));
+                },
+            };
+            info!("apply from path {:?} with {} jobs", &p, jobs);
+            let dry_run = arg.is_present("dryrun");
+            let prune = arg.is_present("prune");
+            let embed = arg.is_present("embed");
+            let sidecar = match arg.is_present("sidecar") {
+                true => {
+                    match SidecarMode::from_str(arg.value_of("sidecar").unwrap_or("file")) {
+                        Ok(v) => {
+                            Some(v)
+                        },
+                        Err(e) => {
+                            fail(&e);
+                        },
+                    }
+                },
+                false => {
+                    None
+                },
+            };
+            let opts = ApplyOptions{
+                dry_run,
+                prune,
+                embed,
+                sidecar,
+                jobs,
+            };
+            let mut rpt = Report::new();
+            exec_apply(p.as_path(), st.as_ref(), digests, opts, cache.as_ref(), &mut rpt);
+            report = Some(rpt);
         },
         _ => {},
     }
 
-//    match args.subcommand_matches("new") {
-//        Some(v) => {
-//            let p = str_to_path(v);
-//            info!("new metadata for path {:?}", &p);
-//            if !exec_entry(p.as_path(), index_dir.as_path()) {
-//                r = false; 
-//            }
-//        },
-//        _ => {},
-//    }
+    if let Some(arg) = args.subcommand_matches("new") {
+        let p = str_to_path(arg);
+        let mut digests: Vec<DigestType> = Vec::new();
+        if let Some(r) = arg.values_of("adddigest") {
+            for digest_str in r {
+                match DigestType::from_str(digest_str) {
+                    Ok(digest) => {
+                        info!("using digest type {}", digest_str);
+                        digests.push(digest);
+                    },
+                    Err(e) => {
+                        fail(&e);
+                    },
+                }
+            }
+        }
+
+        info!("new metadata for path {:?}", &p);
+        if !exec_entry(p.as_path(), st.as_ref(), arg, digests, cache.as_ref()) {
+            r = false; 
+        }
+    }
+
+    if let Some(arg) = args.subcommand_matches("cache") {
+        match (arg.subcommand_matches("prune"), &cache) {
+            (Some(_), Some(v)) => {
+                let removed = v.prune();
+                println!("{} entries pruned, {} remaining", removed, v.len());
+            },
+            (Some(_), None) => {
+                warn!("no digest cache to prune");
+                r = false;
+            },
+            _ => {},
+        };
+    }
+
+    if let Some(v) = &cache {
+        if let Err(e) = v.save() {
+            warn!("could not save digest cache {:?}: {}", v.path(), e);
+        }
+    }
+    if !r {
+        std::process::exit(EXIT_ERROR);
+    }
+    if let Some(v) = report {
+        match args.value_of("report") {
+            Some("json") => {
+                println!("{}", v.to_json());
+            },
+            _ => {
+                println!("{}", v);
+            },
+        };
+        std::process::exit(v.exit_code());
+    }
 }
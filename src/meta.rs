@@ -5,110 +5,252 @@ use hex;
 use mime::{
     Mime
 };
-use sha2::{
-    Sha512,
-    Sha256,
-    Digest,
-};
-use std::fs::{
-    File,
-    metadata,
-};
+use std::fs::File;
 use std::path::Path;
 use std::io::{
-    Read,
     BufRead,
     BufReader,
 };
 use unic_langid_impl::LanguageIdentifier;
 use std::str::FromStr;
-use std::os::linux::fs::MetadataExt;
 
 use biblatex::EntryType;
 
-#[cfg(feature = "md5")]
-use md5::Context;
-
 #[cfg(feature = "magic")]
 use tree_magic;
 
 use crate::dc::{
     DCMetaData,
+    Creator,
+    creator,
     DC_XATTR_TITLE,
     DC_XATTR_CREATOR,
     DC_XATTR_SUBJECT,
     DC_XATTR_LANGUAGE,
     DC_XATTR_TYPE,
     DC_XATTR_MEDIATYPE,
+    DC_XATTR_PUBLISHER,
+    DC_XATTR_DATE,
+    DC_XATTR_IDENTIFIER,
+    DC_XATTR_DESCRIPTION,
+    DC_XATTR_CONTRIBUTOR,
+    DC_XATTR_SOURCE,
+    DC_XATTR_RIGHTS,
+    DC_XATTR_ISPARTOF,
+    DC_XATTR_EXTENT,
+    DC_XATTR_ISSUED,
+    DC_IRI_PUBLISHER,
+    DC_IRI_DATE,
+    DC_IRI_IDENTIFIER,
+    DC_IRI_DESCRIPTION,
+    DC_IRI_CONTRIBUTOR,
+    DC_IRI_SOURCE,
+    DC_IRI_RIGHTS,
+    DC_IRI_ISPARTOF,
+    DC_IRI_EXTENT,
+};
+use crate::error::{
+    Error,
+    Format,
+    ERRNO_NODATA,
 };
-use crate::error::ParseError;
 use crate::digest;
+use crate::cache::DigestCache;
 
 use log::{
     debug,
+    warn,
 };
 
 /// Date elements as d/m/Y tuple.
+///
+/// Day, or day and month, are zero for dates only known by month or year.
 pub type PublishDate = (u8, u8, u32);
 
+/// Check whether the publication date exists in the calendar, to the precision it is known.
+///
+/// The year must be set, and the day must be within the month, e.g. `(31, 2, 2020)` is invalid.
+pub fn publish_date_valid(date: &PublishDate) -> bool {
+    let (d, m, y) = *date;
+    if y == 0 || m > 12 || (m == 0 && d > 0) {
+        return false;
+    }
+    let leap = (y % 4 == 0 && y % 100 != 0) || y % 400 == 0;
+    let days = match m {
+        2 if leap => {
+            29
+        },
+        2 => {
+            28
+        },
+        4 | 6 | 9 | 11 => {
+            30
+        },
+        _ => {
+            31
+        },
+    };
+    d <= days
+}
+
+/// Parse a publication date from an ISO 8601 `YYYY`, `YYYY-MM` or `YYYY-MM-DD` string.
+pub fn publish_date_from_str(s: &str) -> Result<PublishDate, Error> {
+    let parts: Vec<&str> = s.trim().split("-").collect();
+    if parts.len() > 3 || parts[0].len() != 4 {
+        return Err(Error::field("date", s));
+    }
+    let mut r: Vec<u32> = vec!();
+    for v in parts.iter() {
+        match v.parse::<u32>() {
+            Ok(n) => {
+                r.push(n);
+            },
+            Err(_) => {
+                return Err(Error::field("date", s));
+            },
+        };
+    }
+    r.resize(3, 0);
+    if r[1] > 12 || r[2] > 31 || (parts.len() > 1 && r[1] == 0) || (parts.len() > 2 && r[2] == 0) {
+        return Err(Error::field("date", s));
+    }
+    let date = (r[2] as u8, r[1] as u8, r[0]);
+    if !publish_date_valid(&date) {
+        return Err(Error::field("date", s));
+    }
+    Ok(date)
+}
+
+/// Returns the ISO 8601 representation of the publication date, to the precision it is known.
+pub fn publish_date_to_string(date: &PublishDate) -> String {
+    match date {
+        (0, 0, y) => {
+            format!("{:04}", y)
+        },
+        (0, m, y) => {
+            format!("{:04}-{:02}", y, m)
+        },
+        (d, m, y) => {
+            format!("{:04}-{:02}-{:02}", y, m, d)
+        },
+    }
+}
+
 /// Alias for file name (basename).
 pub type FileName = String;
 
 /// Alias for absolute file path.
 pub type FilePath = String;
 
+/// Change to a single extended attribute of a file, see [MetaData::xattr_diff](MetaData::xattr_diff).
+#[derive(Debug, PartialEq)]
+pub enum XattrChange {
+    /// Attribute name and value to be added.
+    Add(String, String),
+    /// Attribute name, current value and new value.
+    Change(String, String, String),
+    /// Attribute name and value to be removed.
+    Remove(String, String),
+}
+
+/// Optional free-text field of the metadata, as Dublin Core term name, term IRI, extended
+/// attribute name, getter and setter.
+pub type TextField = (&'static str, &'static str, &'static str, fn(&MetaData) -> Option<String>, fn(&mut MetaData, &str));
+
+/// Returns all optional free-text fields of the metadata.
+pub fn text_fields() -> Vec<TextField> {
+    vec!(
+        ("publisher", DC_IRI_PUBLISHER, DC_XATTR_PUBLISHER, MetaData::publisher, MetaData::set_publisher),
+        ("date", DC_IRI_DATE, DC_XATTR_DATE, MetaData::date, MetaData::set_date),
+        ("identifier", DC_IRI_IDENTIFIER, DC_XATTR_IDENTIFIER, MetaData::identifier, MetaData::set_identifier),
+        ("description", DC_IRI_DESCRIPTION, DC_XATTR_DESCRIPTION, MetaData::description, MetaData::set_description),
+        ("contributor", DC_IRI_CONTRIBUTOR, DC_XATTR_CONTRIBUTOR, MetaData::contributor, MetaData::set_contributor),
+        ("source", DC_IRI_SOURCE, DC_XATTR_SOURCE, MetaData::source, MetaData::set_source),
+        ("rights", DC_IRI_RIGHTS, DC_XATTR_RIGHTS, MetaData::rights, MetaData::set_rights),
+        ("isPartOf", DC_IRI_ISPARTOF, DC_XATTR_ISPARTOF, MetaData::part_of, MetaData::set_part_of),
+        ("extent", DC_IRI_EXTENT, DC_XATTR_EXTENT, MetaData::extent, MetaData::set_extent),
+    )
+}
+
+/// Disagreement on the value of a field when merging two metadata entries, see [MetaData::merge](MetaData::merge).
+#[derive(Debug, PartialEq)]
+pub struct MergeConflict {
+    /// Name of the field, as the Dublin Core term.
+    pub field: String,
+    /// Value of the field in the entry merged into, which is kept.
+    pub existing: String,
+    /// Value of the field in the entry merged from, which is discarded.
+    pub other: String,
+}
+
 /// Represents the full metadata for a media file.
+#[derive(Clone)]
 pub struct MetaData {
     /// The Dublin Core vocabulary parts of the metadata.
     dc: DCMetaData,
     /// The digest of the file that the metadata is keyed to.
     digest: digest::RecordDigest,
+    /// Additional digests of the same file, e.g. using other digest types.
+    aliases: Vec<digest::RecordDigest>,
     /// Optional local filename, e.g. to use for HTTP `Content-Disposition` header, rename matching files to client's original name, etc.
     local_name: Option<FileName>,
     /// Publication date of the content that the media represents.
     publish_date: PublishDate,
 }
 
-pub fn digests_from_path(filepath: &path::Path, digest_types: &Vec<digest::DigestType>) -> Vec<digest::RecordDigest> {
-    let mut r: Vec<digest::RecordDigest> = vec!();
-    for v in digest_types {
-        match v {
-            digest::DigestType::Sha512 => {
-                let digest = digest_sha512_from_path(filepath);
-                r.push(digest);
-            },
-            digest::DigestType::Sha256 => {
-                let digest = digest_sha256_from_path(filepath);
-                r.push(digest);
-            },
-            #[cfg(feature = "md5")]
-            digest::DigestType::MD5 => {
-                let digest = digest_md5_from_path(filepath);
-                r.push(digest);
-            },
-        };
+/// Calculates digests of all given types for a file, reading the file only once.
+///
+/// # Arguments
+///
+/// * `filepath` - Absolute path to file to calculate digests for.
+/// * `digest_types` - Digest types to calculate. Duplicates are ignored.
+/// * `cache` - Digest cache to look up and store digests in, if any.
+pub fn digests_from_path(filepath: &path::Path, digest_types: &Vec<digest::DigestType>, cache: Option<&DigestCache>) -> Result<Vec<digest::RecordDigest>, Error> {
+    debug!("calculate digests {:?} for file {:?}", digest_types, filepath);
+    match cache {
+        Some(v) => {
+            v.digests_from_path(filepath, digest_types)
+        },
+        None => {
+            let f = File::open(filepath)?;
+            Ok(digest::digests_for(f, digest_types)?)
+        },
     }
-    r
 }
 
-#[cfg(feature = "md5")]
-pub fn digest_md5_from_path(filepath: &path::Path) -> digest::RecordDigest {
-    let mut ctx = md5::Context::new();
-    let mut f = File::open(filepath).unwrap();
-    let mut buf = [0; 512];
+/// Returns the current values of the extended attributes of the file in `filepath` that metadata
+/// may be represented by, see [MetaData::xattr_values](MetaData::xattr_values).
+///
+/// The values are read as they are, without parsing or completing them.
+pub fn xattr_values_from_path(filepath: &path::Path) -> Result<Vec<(&'static str, Option<String>)>, Error> {
+    let mut r: Vec<(&'static str, Option<String>)> = vec!();
+    for (k, _) in MetaData::empty().xattr_values() {
+        let v = xattr::get(filepath, k).map_err(|e| Error::from_xattr(e, filepath))?;
+        r.push((k, v.map(|v| String::from_utf8_lossy(&v).into_owned())));
+    }
+    Ok(r)
+}
 
-    let mut run = true;
-    while run {
-        let c = f.read(&mut buf[..]).unwrap();
-        if c < 512 {
-            run = false;
-        }
-        if c > 0 {
-            ctx.consume(&buf[..c]);
-        }
+/// Returns the value of an extended attribute as a string.
+fn xattr_str(v: &[u8]) -> Result<&str, Error> {
+    match std::str::from_utf8(v) {
+        Ok(v) => {
+            Ok(v)
+        },
+        Err(_) => {
+            Err(Error::parse(Format::Xattr, "attribute value is not valid utf-8"))
+        },
     }
-    let d = ctx.compute();
-    digest::RecordDigest::MD5(d.to_vec())
+}
+
+/// Generates the `md5` digest of a file.
+///
+/// # Arguments
+///
+/// * `filepath` - Absolute path to file to calculate digest for.
+#[cfg(feature = "md5")]
+pub fn digest_md5_from_path(filepath: &path::Path) -> Result<digest::RecordDigest, Error> {
+    Ok(digests_from_path(filepath, &vec!(digest::DigestType::MD5), None)?.remove(0))
 }
 
 /// Generates the native `sha512` digest of a file.
@@ -116,21 +258,8 @@ pub fn digest_md5_from_path(filepath: &path::Path) -> digest::RecordDigest {
 /// # Arguments
 ///
 /// * `filepath` - Absolute path to file to calculate digest for.
-pub fn digest_sha512_from_path(filepath: &path::Path) -> digest::RecordDigest {
-    let mut h = Sha512::new();
-    let st = metadata(filepath).unwrap();
-    let bs: u64 = st.st_blksize();
-    let sz: u64 = st.st_size();
-    let mut b: Vec<u8> = vec!(0; bs as usize);
-    let mut f = File::open(filepath).unwrap();
-    let mut i: usize = 0;
-    while i < sz as usize {
-        let c = f.read(&mut b).unwrap();
-        h.update(&b[..c]);
-        i += c;
-    }
-    let r = h.finalize().to_vec();
-    digest::RecordDigest::Sha512(r)
+pub fn digest_sha512_from_path(filepath: &path::Path) -> Result<digest::RecordDigest, Error> {
+    Ok(digests_from_path(filepath, &vec!(digest::DigestType::Sha512), None)?.remove(0))
 }
 
 /// Generates the native `sha256` digest of a file.
@@ -138,21 +267,8 @@ pub fn digest_sha512_from_path(filepath: &path::Path) -> digest::RecordDigest {
 /// # Arguments
 ///
 /// * `filepath` - Absolute path to file to calculate digest for.
-pub fn digest_sha256_from_path(filepath: &path::Path) -> digest::RecordDigest {
-    let mut h = Sha256::new();
-    let st = metadata(filepath).unwrap();
-    let bs: u64 = st.st_blksize();
-    let sz: u64 = st.st_size();
-    let mut b: Vec<u8> = vec!(0; bs as usize);
-    let mut f = File::open(filepath).unwrap();
-    let mut i: usize = 0;
-    while i < sz as usize {
-        let c = f.read(&mut b).unwrap();
-        h.update(&b[..c]);
-        i += c;
-    }
-    let r = h.finalize().to_vec();
-    digest::RecordDigest::Sha256(r)
+pub fn digest_sha256_from_path(filepath: &path::Path) -> Result<digest::RecordDigest, Error> {
+    Ok(digests_from_path(filepath, &vec!(digest::DigestType::Sha256), None)?.remove(0))
 }
 
 impl MetaData {
@@ -171,6 +287,7 @@ impl MetaData {
         let mut m = MetaData{
                 dc: dc,
                 digest: digest::RecordDigest::Empty,
+                aliases: vec!(),
                 local_name: filename,
                 publish_date: (0, 0, 0),
         };
@@ -185,6 +302,7 @@ impl MetaData {
         MetaData{
                 dc: dc,
                 digest: digest::RecordDigest::Empty,
+                aliases: vec!(),
                 //local_name: filepath.to_str().unwrap().to_string(),
                 local_name: None,
                 publish_date: (0, 0, 0),
@@ -196,22 +314,44 @@ impl MetaData {
         self.dc.title = String::from(title);
     }
 
-    /// Set the [DCMetaData::author](DCMetaData::author) value.
+    /// Set the [DCMetaData::creators](DCMetaData::creators) value from names in BibTeX name
+    /// format, separated by `and`. See [creator::parse_list](crate::dc::creator::parse_list).
     pub fn set_author(&mut self, author: &str) {
-        self.dc.author = String::from(author);
+        self.dc.creators = creator::parse_list(author);
+    }
+
+    /// Set the [DCMetaData::creators](DCMetaData::creators) value.
+    pub fn set_creators(&mut self, creators: Vec<Creator>) {
+        self.dc.creators = creators;
+    }
+
+    /// Add a creator after the current [DCMetaData::creators](DCMetaData::creators).
+    pub fn add_creator(&mut self, creator: Creator) {
+        self.dc.creators.push(creator);
+    }
+
+    /// Returns the current [DCMetaData::creators](DCMetaData::creators) value.
+    pub fn creators(&self) -> Vec<Creator> {
+        self.dc.creators.clone()
     }
 
     /// Set the digest as [digest::RecordDigest::Sha512](digest::RecordDigest::Sha512) instance of the provided
     /// fingerprint.
+    ///
+    /// If the digest was one of the additional digests, it is removed from those.
     pub fn set_fingerprint(&mut self, fingerprint: digest::RecordDigest) {
+        self.aliases.retain(|v| {
+            *v != fingerprint
+        });
         self.digest = fingerprint; //digest::from_vec(fingerprint).unwrap();
     }
 
     /// Set the digest from the given URN string.
     ///
     /// The URN must specify a valid supported [digest](digest::from_urn) scheme.
-    pub fn set_fingerprint_urn(&mut self, urn: &str) {
-        self.digest = digest::from_urn(urn).unwrap();
+    pub fn set_fingerprint_urn(&mut self, urn: &str) -> Result<(), Error> {
+        self.set_fingerprint(digest::from_urn(urn)?);
+        Ok(())
     }
 
     /// Returns the current [DCMetaData::title](DCMetaData::title) value.
@@ -219,14 +359,20 @@ impl MetaData {
         self.dc.title.clone()
     }
 
-    /// Returns the current [DCMetaData::author](DCMetaData::author) value.
+    /// Returns the names of the [DCMetaData::creators](DCMetaData::creators) as written in
+    /// running text, separated by comma.
     pub fn author(&self) -> String {
-        self.dc.author.clone()
+        self.dc.creators.iter()
+            .map(|v| {
+                v.name()
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
     }
 
     /// Set the [DCMetaData::typ](DCMetaData::typ) value.
     pub fn set_typ(&mut self, typ: &str) {
-        self.dc.typ = EntryType::from_str(typ).unwrap();
+        self.dc.typ = EntryType::new(typ);
     }
 
     /// Returns the current [DCMetaData::typ](DCMetaData::typ) value.
@@ -234,14 +380,61 @@ impl MetaData {
         self.dc.typ.clone()
     }
 
-    /// Set the current [DCMetaData::subject](DCMetaData::subject) value.
+    /// Set the current [DCMetaData::subjects](DCMetaData::subjects) value from a comma-separated
+    /// keyword list.
     pub fn set_subject(&mut self, v: &str) {
-        self.dc.subject = Some(String::from(v));
+        self.dc.subjects = vec!();
+        for k in v.split(",") {
+            self.add_subject(k);
+        }
     }
 
-    /// Returns the current [DCMetaData::subject](DCMetaData::subject) value.
+    /// Returns the current [DCMetaData::subjects](DCMetaData::subjects) value as a
+    /// comma-separated keyword list, or `None` if there are no keywords.
     pub fn subject(&self) -> Option<String> {
-        return self.dc.subject.clone();
+        if self.dc.subjects.is_empty() {
+            return None;
+        }
+        Some(self.dc.subjects.join(","))
+    }
+
+    /// Returns the current [DCMetaData::subjects](DCMetaData::subjects) value.
+    pub fn subjects(&self) -> Vec<String> {
+        self.dc.subjects.clone()
+    }
+
+    /// Add a keyword to the [DCMetaData::subjects](DCMetaData::subjects).
+    ///
+    /// Leading, trailing and repeated whitespace is removed from the keyword. Returns false if
+    /// the keyword is empty, contains a comma, or already exists, ignoring case.
+    pub fn add_subject(&mut self, v: &str) -> bool {
+        let k = normalise_keyword(v);
+        if k.is_empty() || k.contains(",") || self.has_subject(k.as_str()) {
+            return false;
+        }
+        self.dc.subjects.push(k);
+        true
+    }
+
+    /// Remove a keyword from the [DCMetaData::subjects](DCMetaData::subjects), ignoring case.
+    ///
+    /// Returns false if the keyword does not exist.
+    pub fn remove_subject(&mut self, v: &str) -> bool {
+        let k = normalise_keyword(v).to_lowercase();
+        let c = self.dc.subjects.len();
+        self.dc.subjects.retain(|s| {
+            s.to_lowercase() != k
+        });
+        c != self.dc.subjects.len()
+    }
+
+    /// Check whether the keyword is one of the [DCMetaData::subjects](DCMetaData::subjects),
+    /// ignoring case.
+    pub fn has_subject(&self, v: &str) -> bool {
+        let k = normalise_keyword(v).to_lowercase();
+        self.dc.subjects.iter().any(|s| {
+            s.to_lowercase() == k
+        })
     }
 
     /// Set the current [DCMetaData::mime](DCMetaData::mime) value.
@@ -250,15 +443,16 @@ impl MetaData {
     }
 
     /// Set the current [DCMetaData::mime](DCMetaData::mime) value from the given MIME identifier string.
-    pub fn set_mime_str(&mut self, s: &str) {
+    pub fn set_mime_str(&mut self, s: &str) -> Result<(), Error> {
         match Mime::from_str(s) {
             Ok(v) => {
                 self.set_mime(v);
+                Ok(())
             },
             Err(e) => {
-                panic!("invalid mime");
+                Err(Error::field("MediaType", s))
             },
-        };
+        }
     }
 
     /// Returns the current [DCMetaData::mime](DCMetaData::mime) value.
@@ -267,9 +461,16 @@ impl MetaData {
     }
 
     /// Set the current [DCMetaData::language](DCMetaData::language) value.
-    pub fn set_language(&mut self, s: &str) {
-        let v = s.parse().unwrap();
-        self.dc.language = Some(v);
+    pub fn set_language(&mut self, s: &str) -> Result<(), Error> {
+        match s.parse() {
+            Ok(v) => {
+                self.dc.language = Some(v);
+                Ok(())
+            },
+            Err(_) => {
+                Err(Error::field("language", s))
+            },
+        }
     }
 
     /// Returns the current [DCMetaData::language](DCMetaData::language) value.
@@ -277,6 +478,190 @@ impl MetaData {
         self.dc.language.clone()
     }
 
+    /// Set the current [DCMetaData::publisher](DCMetaData::publisher) value.
+    pub fn set_publisher(&mut self, v: &str) {
+        self.dc.publisher = Some(String::from(v));
+    }
+
+    /// Returns the current [DCMetaData::publisher](DCMetaData::publisher) value.
+    pub fn publisher(&self) -> Option<String> {
+        self.dc.publisher.clone()
+    }
+
+    /// Set the current [DCMetaData::date](DCMetaData::date) value.
+    pub fn set_date(&mut self, v: &str) {
+        self.dc.date = Some(String::from(v));
+    }
+
+    /// Returns the current [DCMetaData::date](DCMetaData::date) value.
+    pub fn date(&self) -> Option<String> {
+        self.dc.date.clone()
+    }
+
+    /// Set the current [DCMetaData::identifier](DCMetaData::identifier) value.
+    pub fn set_identifier(&mut self, v: &str) {
+        self.dc.identifier = Some(String::from(v));
+    }
+
+    /// Returns the current [DCMetaData::identifier](DCMetaData::identifier) value.
+    pub fn identifier(&self) -> Option<String> {
+        self.dc.identifier.clone()
+    }
+
+    /// Set the current [DCMetaData::description](DCMetaData::description) value.
+    pub fn set_description(&mut self, v: &str) {
+        self.dc.description = Some(String::from(v));
+    }
+
+    /// Returns the current [DCMetaData::description](DCMetaData::description) value.
+    pub fn description(&self) -> Option<String> {
+        self.dc.description.clone()
+    }
+
+    /// Set the current [DCMetaData::contributor](DCMetaData::contributor) value.
+    pub fn set_contributor(&mut self, v: &str) {
+        self.dc.contributor = Some(String::from(v));
+    }
+
+    /// Returns the current [DCMetaData::contributor](DCMetaData::contributor) value.
+    pub fn contributor(&self) -> Option<String> {
+        self.dc.contributor.clone()
+    }
+
+    /// Set the [DCMetaData::contributor](DCMetaData::contributor) value from a list of creators,
+    /// in BibTeX name format separated by `and`.
+    pub fn set_contributors(&mut self, contributors: &[Creator]) {
+        match contributors.len() {
+            0 => {
+                self.dc.contributor = None;
+            },
+            _ => {
+                self.dc.contributor = Some(creator::format_list(contributors));
+            },
+        };
+    }
+
+    /// Returns the creators in the current [DCMetaData::contributor](DCMetaData::contributor)
+    /// value.
+    pub fn contributors(&self) -> Vec<Creator> {
+        match &self.dc.contributor {
+            Some(v) => {
+                creator::parse_list(v)
+            },
+            None => {
+                vec!()
+            },
+        }
+    }
+
+    /// Set the current [DCMetaData::source](DCMetaData::source) value.
+    pub fn set_source(&mut self, v: &str) {
+        self.dc.source = Some(String::from(v));
+    }
+
+    /// Returns the current [DCMetaData::source](DCMetaData::source) value.
+    pub fn source(&self) -> Option<String> {
+        self.dc.source.clone()
+    }
+
+    /// Set the current [DCMetaData::rights](DCMetaData::rights) value.
+    pub fn set_rights(&mut self, v: &str) {
+        self.dc.rights = Some(String::from(v));
+    }
+
+    /// Returns the current [DCMetaData::rights](DCMetaData::rights) value.
+    pub fn rights(&self) -> Option<String> {
+        self.dc.rights.clone()
+    }
+
+    /// Set the current [DCMetaData::part_of](DCMetaData::part_of) value.
+    pub fn set_part_of(&mut self, v: &str) {
+        self.dc.part_of = Some(String::from(v));
+    }
+
+    /// Returns the current [DCMetaData::part_of](DCMetaData::part_of) value.
+    pub fn part_of(&self) -> Option<String> {
+        self.dc.part_of.clone()
+    }
+
+    /// Set the current [DCMetaData::extent](DCMetaData::extent) value.
+    pub fn set_extent(&mut self, v: &str) {
+        self.dc.extent = Some(String::from(v));
+    }
+
+    /// Returns the current [DCMetaData::extent](DCMetaData::extent) value.
+    pub fn extent(&self) -> Option<String> {
+        self.dc.extent.clone()
+    }
+
+    /// Set the publication date of the content that the media represents.
+    pub fn set_publish_date(&mut self, date: PublishDate) {
+        self.publish_date = date;
+    }
+
+    /// Returns the publication date of the content that the media represents, if known.
+    pub fn publish_date(&self) -> Option<PublishDate> {
+        match self.publish_date {
+            (_, _, 0) => {
+                None
+            },
+            v => {
+                Some(v)
+            },
+        }
+    }
+
+    /// Returns the digest of the file that the metadata is keyed to.
+    pub fn digest(&self) -> &digest::RecordDigest {
+        &self.digest
+    }
+
+    /// Add a digest of the same file.
+    ///
+    /// If no digest is set yet, the digest becomes the one that the metadata is keyed to.
+    /// Digests already known are ignored.
+    pub fn add_digest(&mut self, digest: digest::RecordDigest) {
+        if self.has_digest(&digest) {
+            return;
+        }
+        match self.digest {
+            digest::RecordDigest::Empty => {
+                self.digest = digest;
+            },
+            _ => {
+                self.aliases.push(digest);
+            },
+        };
+    }
+
+    /// Check whether the given digest is one of the digests of the metadata.
+    pub fn has_digest(&self, digest: &digest::RecordDigest) -> bool {
+        if self.digest == *digest {
+            return true;
+        }
+        self.aliases.contains(digest)
+    }
+
+    /// Returns all digests of the file, starting with the one the metadata is keyed to.
+    pub fn digests(&self) -> Vec<digest::RecordDigest> {
+        let mut r: Vec<digest::RecordDigest> = vec!();
+        match self.digest {
+            digest::RecordDigest::Empty => {},
+            _ => {
+                r.push(self.digest.clone());
+            },
+        };
+        for v in self.aliases.iter() {
+            r.push(v.clone());
+        }
+        r
+    }
+
+    /// Returns the additional digests of the file.
+    pub fn aliases(&self) -> Vec<digest::RecordDigest> {
+        self.aliases.clone()
+    }
+
     ///
     pub fn urn(&self) -> String {
         self.digest.urn()
@@ -289,68 +674,86 @@ impl MetaData {
     }
 
     /// Instantiate metadata from the extended attributes of the file in `filepath`.
-    pub fn from_xattr(filepath: &path::Path) -> Result<MetaData, ParseError> {
+    ///
+    /// The metadata is keyed to the native `sha512` digest of the file.
+    pub fn from_xattr(filepath: &path::Path) -> Result<MetaData, Error> {
+        MetaData::from_xattr_digests(filepath, &vec!(digest::DigestType::Sha512), None)
+    }
+
+    /// Instantiate metadata from the extended attributes of the file in `filepath`, with digests
+    /// of the given types.
+    ///
+    /// The digests are calculated in a single pass over the file, and only if the file has valid
+    /// metadata attributes. The metadata is keyed to the first of the digests. If no digest types
+    /// are given, the file contents are not read at all. Digests are looked up in and added to
+    /// `cache`, if given.
+    pub fn from_xattr_digests(filepath: &path::Path, digest_types: &Vec<digest::DigestType>, cache: Option<&DigestCache>) -> Result<MetaData, Error> {
 
         let mut title: String = String::new();
         let mut author: String = String::new();
         let mut typ: EntryType = EntryType::Unknown(String::new());
         let filename: FileName; 
 
-        debug!("Calculate digest for file {:?}",  &filepath);
-        let digest = digest_sha512_from_path(filepath);
-        debug!("Calculated digest {} for file {:?}", hex::encode(digest.fingerprint()), &filepath);
-
-        filename = filepath.file_name()
-            .unwrap()
-            .to_os_string()
-            .into_string()
-            .unwrap();
+        filename = match filepath.file_name() {
+            Some(v) => {
+                v.to_string_lossy().into_owned()
+            },
+            None => {
+                return Err(Error::parse(Format::Xattr, "path has no file name"));
+            },
+        };
 
         let title_src = match xattr::get(filepath, "user.dcterms:title") {
             Ok(v) => {
                 v
             },
             Err(e) => {
-                return Err(ParseError::new("title missing"));
+                return Err(Error::from_xattr(e, filepath));
             }
         };
         match title_src {
             Some(v) => {
-                let s = std::str::from_utf8(&v).unwrap();
+                let s = xattr_str(&v)?;
                 title.push_str(s);
             },
             None => {},
         }
 
-        let author_src = xattr::get(filepath, "user.dcterms:creator").unwrap();
+        let author_src = xattr::get(filepath, "user.dcterms:creator").map_err(|e| Error::from_xattr(e, filepath))?;
         match author_src {
             Some(v) => {
-                let s = std::str::from_utf8(&v).unwrap();
+                let s = xattr_str(&v)?;
                 author.push_str(s);
             },
             None => {},
         }
 
 
-        let typ_src = xattr::get(filepath, "user.dcterms:type").unwrap();
+        let typ_src = xattr::get(filepath, "user.dcterms:type").map_err(|e| Error::from_xattr(e, filepath))?;
         match typ_src {
             Some(v) => {
-                let s = std::str::from_utf8(&v).unwrap();
+                let s = xattr_str(&v)?;
                 typ = EntryType::new(s);
             },
             None => {},
         }
 
-        let mut metadata = MetaData::new(title.as_str(), author.as_str(), typ, digest, Some(filename));
+        let mut metadata = MetaData::new(title.as_str(), "", typ, digest::RecordDigest::Empty, Some(filename));
+        metadata.set_creators(creator::parse_stored(author.as_str()));
         if !metadata.validate() {
-            return Err(ParseError::new("invalid input"));
+            return Err(Error::parse(Format::Xattr, "title missing"));
         }
 
+        for v in digests_from_path(filepath, digest_types, cache)? {
+            metadata.add_digest(v);
+        }
+        debug!("calculated digests {:?} for file {:?}", metadata.digests(), &filepath);
+
         match xattr::get(filepath, "user.dcterms:subject") {
             Ok(v) => {
                 match v {
                     Some(v) => {
-                        let s = std::str::from_utf8(&v).unwrap();
+                        let s = xattr_str(&v)?;
                         metadata.set_subject(s);
                     },
                     None => {},
@@ -363,8 +766,8 @@ impl MetaData {
             Ok(v) => {
                 match v {
                     Some(v) => {
-                        let s = std::str::from_utf8(&v).unwrap();
-                        metadata.set_mime_str(s);
+                        let s = xattr_str(&v)?;
+                        metadata.set_mime_str(s)?;
                     },
                     None => {},
                 }
@@ -376,8 +779,8 @@ impl MetaData {
             Ok(v) => {
                 match v {
                     Some(v) => {
-                        let s = std::str::from_utf8(&v).unwrap();
-                        metadata.set_language(s);
+                        let s = xattr_str(&v)?;
+                        metadata.set_language(s)?;
                     },
                     None => {},
                 }
@@ -385,6 +788,25 @@ impl MetaData {
             _ => {},
         }
 
+        if let Ok(Some(v)) = xattr::get(filepath, DC_XATTR_ISSUED) {
+            let s = xattr_str(&v)?;
+            match publish_date_from_str(s) {
+                Ok(date) => {
+                    metadata.set_publish_date(date);
+                },
+                Err(e) => {
+                    debug!("invalid publish date {:?} for {:?}: {:?}", s, filepath, e);
+                },
+            };
+        }
+
+        for (_, _, k, _, set) in text_fields() {
+            if let Ok(Some(v)) = xattr::get(filepath, k) {
+                let s = xattr_str(&v)?;
+                set(&mut metadata, s);
+            }
+        }
+
         #[cfg(feature = "magic")]
         metadata.set_mime_magic(filepath);
 
@@ -392,50 +814,111 @@ impl MetaData {
     }
 
 
+    /// Returns the extended attribute values representing the metadata.
+    ///
+    /// Every attribute that the metadata may be represented by is included, with `None` as
+    /// value for the attributes that are not defined.
+    pub fn xattr_values(&self) -> Vec<(&'static str, Option<String>)> {
+        let mut r: Vec<(&'static str, Option<String>)> = vec!(
+            (DC_XATTR_TITLE, Some(self.dc.title.clone())),
+            (DC_XATTR_CREATOR, Some(creator::format_list(&self.dc.creators))),
+            (DC_XATTR_TYPE, Some(self.dc.typ.to_string())),
+            (DC_XATTR_LANGUAGE, self.dc.language.as_ref().map(|v| v.to_string())),
+            (DC_XATTR_MEDIATYPE, self.dc.mime.as_ref().map(|v| v.to_string())),
+            (DC_XATTR_SUBJECT, self.subject()),
+            (DC_XATTR_ISSUED, self.publish_date().map(|v| publish_date_to_string(&v))),
+        );
+        for (_, _, k, get, _) in text_fields() {
+            r.push((k, get(self)));
+        }
+        r
+    }
+
+    /// Returns the changes to extended attributes that applying the metadata would result in.
+    ///
+    /// # Arguments
+    ///
+    /// * `current` - The extended attribute values currently set on the file. See
+    ///   [xattr_values_from_path](xattr_values_from_path).
+    /// * `prune` - Whether attributes that the metadata does not define are removed, see
+    ///   [MetaData::prune_xattr](MetaData::prune_xattr).
+    pub fn xattr_diff(&self, current: &[(&'static str, Option<String>)], prune: bool) -> Vec<XattrChange> {
+        let mut r: Vec<XattrChange> = vec!();
+        for (k, v) in self.xattr_values() {
+            let old = current.iter()
+                .find(|(kk, _)| {
+                    *kk == k
+                })
+                .and_then(|(_, vv)| {
+                    vv.clone()
+                });
+            match (old, v) {
+                (None, Some(new)) => {
+                    r.push(XattrChange::Add(String::from(k), new));
+                },
+                (Some(old), Some(new)) => {
+                    if old != new {
+                        r.push(XattrChange::Change(String::from(k), old, new));
+                    }
+                },
+                (Some(old), None) => {
+                    if prune {
+                        r.push(XattrChange::Remove(String::from(k), old));
+                    }
+                },
+                (None, None) => {},
+            };
+        }
+        r
+    }
+
     /// Applies the metadata as extended file attributes of the file in `filepath`.
     ///
     /// Will always export:
     ///
     /// * [title](DCMetaData::DC_XATTR_TITLE)
-    /// * [creator](DCMetaData::DC_XATTR_CREATOR)
+    /// * [creator](DCMetaData::DC_XATTR_CREATOR), as all creators in BibTeX name format separated
+    ///   by `and`
     /// * [category of file contents](DCMetaData::DC_XATTR_TYPE)
     ///
     /// Will export, if defined:
     ///
     /// * [language](DCMetaData::DC_XATTR_LANGUAGE)
     /// * [MIME type of file](DCMetaData::DC_XATTR_MEDIATYPE)
-    /// * [A description of the subject matter of the file contents](DCMetaData::DC_XATTR_SUBJECT)
-    pub fn to_xattr(&self, filepath: &path::Path) -> Result<(), std::io::Error> {
-        let filename = filepath.file_name()
-            .unwrap()
-            .to_os_string()
-            .into_string()
-            .unwrap();
-
-        xattr::set(filepath, DC_XATTR_TITLE, self.dc.title.as_bytes());
-        xattr::set(filepath, DC_XATTR_CREATOR, self.dc.author.as_bytes());
-        xattr::set(filepath, DC_XATTR_TYPE, self.dc.typ.to_string().as_bytes());
-
-        match &self.dc.language {
-            Some(v) => {
-                xattr::set(filepath, DC_XATTR_LANGUAGE, v.to_string().as_bytes());
-            },
-            _ => {},
-        };
+    /// * [A description of the subject matter of the file contents](DCMetaData::DC_XATTR_SUBJECT),
+    ///   as comma-separated keyword list
+    /// * [publication date](crate::dc::DC_XATTR_ISSUED)
+    /// * the optional free-text fields listed by [text_fields](text_fields), e.g.
+    ///   [publisher](crate::dc::DC_XATTR_PUBLISHER)
+    ///
+    /// Attributes that are not defined are left as they are on the file. Use
+    /// [MetaData::prune_xattr](MetaData::prune_xattr) to remove them.
+    pub fn to_xattr(&self, filepath: &path::Path) -> Result<(), Error> {
+        for (k, v) in self.xattr_values() {
+            if let Some(v) = v {
+                xattr::set(filepath, k, v.as_bytes()).map_err(|e| Error::from_xattr(e, filepath))?;
+            }
+        }
 
-        match &self.dc.mime {
-            Some(v) => {
-                xattr::set(filepath, DC_XATTR_MEDIATYPE, v.to_string().as_bytes());
-            },
-            _ => {},
-        };
+        Ok(())
+    }
 
-        match &self.dc.subject {
-            Some(v) => {
-                xattr::set(filepath, DC_XATTR_SUBJECT, v.as_bytes());
-            },
-            _ => {},
-        };
+    /// Removes the extended attributes of the file in `filepath` that the metadata may be
+    /// represented by, but does not define.
+    pub fn prune_xattr(&self, filepath: &path::Path) -> Result<(), Error> {
+        for (k, v) in self.xattr_values() {
+            match v {
+                Some(_) => {},
+                None => {
+                    match xattr::remove(filepath, k) {
+                        Err(e) if e.raw_os_error() != Some(ERRNO_NODATA) => {
+                            return Err(Error::from_xattr(e, filepath));
+                        },
+                        _ => {},
+                    };
+                },
+            };
+        }
 
         Ok(())
     }
@@ -447,7 +930,7 @@ impl MetaData {
                 debug!("found title: {}", object);
             },
             "author" => {
-                self.set_author(object);
+                self.set_creators(creator::parse_stored(object));
                 debug!("found author: {}", object);
             },
             "subject" => {
@@ -459,11 +942,17 @@ impl MetaData {
                 debug!("found typ: {}", object);
             },
             "language" => {
-                self.set_language(object);
+                if self.set_language(object).is_err() {
+                    warn!("invalid language: {}", object);
+                    return false;
+                }
                 debug!("found language: {}", object);
             },
             "mime" => {
-                self.set_mime_str(object);
+                if self.set_mime_str(object).is_err() {
+                    warn!("invalid mime: {}", object);
+                    return false;
+                }
                 debug!("found mime: {}", object);
             },
             _ => {
@@ -489,18 +978,24 @@ impl MetaData {
     pub fn set_mime_magic(&mut self, path: &path::Path) {
         if self.mime() == None {
             let mime = tree_magic::from_filepath(path);
-            self.set_mime_str(&mime);
-            debug!("magic set mime {}", mime);
+            match self.set_mime_str(&mime) {
+                Ok(_) => {
+                    debug!("magic set mime {}", mime);
+                },
+                Err(e) => {
+                    warn!("magic returned invalid mime {}: {}", mime, e);
+                },
+            };
         }
     }
 
     /// Parse metadata from simplified metadata format contained in file in `path`.
     ///
     /// see [MetaData::from_file](MetaData::from_file)
-    pub fn from_path(p: &path::Path) -> Result<MetaData, std::io::Error> {
-        let f = File::open(&p).unwrap();
+    pub fn from_path(p: &path::Path) -> Result<MetaData, Error> {
+        let f = File::open(p)?;
         debug!("openning {}", p.display());
-        let mut m = MetaData::from_file(f).unwrap();
+        let m = MetaData::from_file(f)?;
         Ok(m)
     }
 
@@ -529,19 +1024,101 @@ impl MetaData {
     }
 
 
+    /// Merge the fields of another metadata entry into this one.
+    ///
+    /// Fields not defined in this entry are set from `other`, and the digests and subject
+    /// keywords of both entries are combined. Where both entries define a field with different values, the value of this
+    /// entry is kept, and the disagreement is returned.
+    pub fn merge(&mut self, other: &MetaData) -> Vec<MergeConflict> {
+        let mut r: Vec<MergeConflict> = vec!();
+
+        if let Some(v) = merge_value("title", Some(self.title()), Some(other.title()), &mut r) {
+            self.set_title(v.as_str());
+        }
+        if merge_value("creator", Some(creator::format_list(&self.dc.creators)), Some(creator::format_list(&other.dc.creators)), &mut r).is_some() {
+            self.set_creators(other.creators());
+        }
+        if let Some(v) = merge_value("type", Some(self.typ().to_string()), Some(other.typ().to_string()), &mut r) {
+            self.set_typ(v.as_str());
+        }
+        for v in other.subjects() {
+            self.add_subject(v.as_str());
+        }
+        if merge_value("MediaType", self.mime().map(|v| v.to_string()), other.mime().map(|v| v.to_string()), &mut r).is_some() {
+            self.dc.mime = other.mime();
+        }
+        if merge_value("language", self.language().map(|v| v.to_string()), other.language().map(|v| v.to_string()), &mut r).is_some() {
+            self.dc.language = other.language();
+        }
+
+        let issued = self.publish_date().map(|v| publish_date_to_string(&v));
+        let issued_other = other.publish_date().map(|v| publish_date_to_string(&v));
+        if merge_value("issued", issued, issued_other, &mut r).is_some() {
+            self.publish_date = other.publish_date;
+        }
+
+        for (k, _, _, get, set) in text_fields() {
+            if let Some(v) = merge_value(k, get(self), get(other), &mut r) {
+                set(self, v.as_str());
+            }
+        }
+
+        if self.local_name.is_none() {
+            self.local_name = other.local_name.clone();
+        }
+        for v in other.digests() {
+            self.add_digest(v);
+        }
+        debug!("merged {:?} into {:?} with {} conflicts", other, self, r.len());
+        r
+    }
+
     /// Check whether a Metadata instance represents a valid entry.
     pub fn validate(&self) -> bool {
         let empty = String::new();
         if self.title() == empty {
             return false;
         }
-        if self.author() == empty {
+        if self.dc.creators.is_empty() {
             return false;
         }
         true
     }
 }
 
+/// Normalise a subject keyword, by removing leading, trailing and repeated whitespace.
+fn normalise_keyword(v: &str) -> String {
+    v.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Returns the value to set for a field when merging, if any.
+///
+/// Empty strings count as undefined values. Differing values are added to `conflicts`.
+fn merge_value(field: &str, existing: Option<String>, other: Option<String>, conflicts: &mut Vec<MergeConflict>) -> Option<String> {
+    let existing = existing.filter(|v| !v.is_empty());
+    let other = other.filter(|v| !v.is_empty());
+    match (existing, other) {
+        (None, Some(v)) => {
+            Some(v)
+        },
+        (Some(existing), Some(other)) => {
+            if existing != other {
+                conflicts.push(MergeConflict{
+                    field: String::from(field),
+                    existing,
+                    other,
+                });
+            }
+            None
+        },
+        _ => {
+            None
+        },
+    }
+}
+
 impl fmt::Debug for MetaData {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", format_args!("title \"{}\" author \"{}\" digest {}", self.title(), self.author(), self.urn()))
@@ -550,7 +1127,16 @@ impl fmt::Debug for MetaData {
 
 #[cfg(test)]
 mod tests {
-    use super::MetaData;
+    use super::{
+        MetaData,
+        MergeConflict,
+        XattrChange,
+        publish_date_from_str,
+        publish_date_to_string,
+        digest_sha512_from_path,
+        xattr_values_from_path,
+    };
+    use crate::error::Error;
     use std::path;
     use tempfile::NamedTempFile;
     use biblatex::EntryType;
@@ -561,8 +1147,13 @@ mod tests {
     use crate::digest;
     use env_logger;
     use crate::dc::{
+        Creator,
         DC_XATTR_TITLE,
         DC_XATTR_CREATOR,
+        DC_XATTR_LANGUAGE,
+        DC_XATTR_SUBJECT,
+        DC_XATTR_TYPE,
+        DC_XATTR_MEDIATYPE,
     };
 
     #[test]
@@ -570,7 +1161,7 @@ mod tests {
         let s = path::Path::new("testdata/bitcoin.pdf");
         let meta = MetaData::from_xattr(s).unwrap();
         assert_eq!(meta.dc.title, "Bitcoin: A Peer-to-Peer Electronic Cash System");
-        assert_eq!(meta.dc.author, "Satoshi Nakamoto");
+        assert_eq!(meta.author(), "Satoshi Nakamoto");
         assert_eq!(meta.urn(), String::from("sha512:2ac531ee521cf93f8419c2018f770fbb42c65396178e079a416e7038d3f9ab9fc2c35c4d838bc8b5dd68f4c13759fe9cdf90a46528412fefe1294cb26beabf4e"));
         assert_eq!(meta.fingerprint(), String::from("2ac531ee521cf93f8419c2018f770fbb42c65396178e079a416e7038d3f9ab9fc2c35c4d838bc8b5dd68f4c13759fe9cdf90a46528412fefe1294cb26beabf4e"));
     }
@@ -587,9 +1178,12 @@ mod tests {
         let digest_sha = digest::from_vec(digest).unwrap();
         let mut m = MetaData::new("foo", "bar", EntryType::Article, digest_sha, Some(fps));
         m.set_subject("baz");
-        m.set_mime_str("foo/bar");
-        m.set_language("nb-NO");
-        m.to_xattr(fp);
+        m.set_mime_str("foo/bar").unwrap();
+        m.set_language("nb-NO").unwrap();
+        m.set_publisher("xyzzy");
+        m.set_part_of("inky pinky");
+        m.set_publish_date((0, 11, 2019));
+        m.to_xattr(fp).unwrap();
         
         let m_check = MetaData::from_xattr(fp).unwrap();
         assert_eq!(m_check.title(), "foo");
@@ -600,6 +1194,186 @@ mod tests {
         assert_eq!(m_check.subject().unwrap(), "baz");
         assert_eq!(m_check.mime().unwrap(), "foo/bar");
         assert_eq!(m_check.language().unwrap(), "nb-NO");
+        assert_eq!(m_check.publisher().unwrap(), "xyzzy");
+        assert_eq!(m_check.part_of().unwrap(), "inky pinky");
+        assert_eq!(m_check.rights(), None);
+        assert_eq!(m_check.publish_date().unwrap(), (0, 11, 2019));
+
+        let mut m = MetaData::new("foo", "bar", EntryType::Article, m.digest().clone(), None);
+        m.set_subject("baz");
+        m.to_xattr(fp).unwrap();
+        let m_check = MetaData::from_xattr(fp).unwrap();
+        assert_eq!(m_check.publisher().unwrap(), "xyzzy");
+        assert_eq!(m_check.language().unwrap(), "nb-NO");
+
+        m.prune_xattr(fp).unwrap();
+        let m_check = MetaData::from_xattr(fp).unwrap();
+        assert_eq!(m_check.publisher(), None);
+        assert_eq!(m_check.language(), None);
+        assert_eq!(m_check.subject().unwrap(), "baz");
+    }
+
+    #[test]
+    fn test_invalid_field() {
+        let mut m = MetaData::empty();
+        let e = m.set_language("xx-").unwrap_err();
+        assert!(matches!(e, Error::InvalidField{field: "language", ..}));
+        assert!(m.set_mime_str("foo").is_err());
+        assert!(m.set_fingerprint_urn("sha256:foo").is_err());
+        assert_eq!(m.language(), None);
+        assert_eq!(m.mime(), None);
+
+        let e = digest_sha512_from_path(path::Path::new("testdata/nonexistent")).unwrap_err();
+        assert!(matches!(e, Error::Io(_)));
+    }
+
+    #[test]
+    fn test_publish_date() {
+        assert_eq!(publish_date_from_str("2019").unwrap(), (0, 0, 2019));
+        assert_eq!(publish_date_from_str("2019-11").unwrap(), (0, 11, 2019));
+        assert_eq!(publish_date_from_str("2019-11-08").unwrap(), (8, 11, 2019));
+        assert!(publish_date_from_str("2019-13").is_err());
+        assert!(publish_date_from_str("2019-00-08").is_err());
+        assert!(publish_date_from_str("19").is_err());
+        assert!(publish_date_from_str("november").is_err());
+        assert!(publish_date_from_str("2019-02-31").is_err());
+        assert!(publish_date_from_str("2019-04-31").is_err());
+        assert!(publish_date_from_str("2019-02-29").is_err());
+        assert_eq!(publish_date_from_str("2020-02-29").unwrap(), (29, 2, 2020));
+        assert!(publish_date_from_str("1900-02-29").is_err());
+        assert!(publish_date_from_str("2000-02-29").is_ok());
+        assert!(publish_date_from_str("0000").is_err());
+
+        assert_eq!(publish_date_to_string(&(0, 0, 2019)), "2019");
+        assert_eq!(publish_date_to_string(&(0, 11, 2019)), "2019-11");
+        assert_eq!(publish_date_to_string(&(8, 11, 2019)), "2019-11-08");
+
+        let mut m = MetaData::empty();
+        assert_eq!(m.publish_date(), None);
+        m.set_publish_date((0, 11, 2019));
+        assert_eq!(m.publish_date().unwrap(), (0, 11, 2019));
+    }
+
+    #[test]
+    fn test_metadata_digests() {
+        let d_sha512 = digest::from_vec(vec!(0x2a; 64)).unwrap();
+        let d_sha256 = digest::RecordDigest::Sha256(vec!(0x2a; 32));
+        let mut m = MetaData::empty();
+        assert_eq!(m.digests().len(), 0);
+
+        m.add_digest(d_sha512.clone());
+        m.add_digest(d_sha256.clone());
+        m.add_digest(d_sha512.clone());
+        assert!(m.has_digest(&d_sha256));
+        assert_eq!(m.digests(), vec!(d_sha512.clone(), d_sha256.clone()));
+        assert_eq!(m.aliases(), vec!(d_sha256.clone()));
+
+        m.set_fingerprint(d_sha256.clone());
+        assert_eq!(m.digests(), vec!(d_sha256.clone()));
+
+        m.add_digest(d_sha512.clone());
+        m.set_fingerprint_urn(&d_sha512.urn()).unwrap();
+        assert_eq!(m.digests(), vec!(d_sha512));
+        assert_eq!(m.aliases().len(), 0);
+    }
+
+    #[test]
+    fn test_metadata_xattr_diff() {
+        let digest = digest::from_vec(vec!(0x2a; 64)).unwrap();
+        let mut m = MetaData::new("foo", "bar", EntryType::Article, digest.clone(), None);
+        m.set_language("nb-NO").unwrap();
+
+        let r = m.xattr_diff(&[], false);
+        assert_eq!(r.len(), 4);
+        assert!(r.contains(&XattrChange::Add(String::from(DC_XATTR_TITLE), String::from("foo"))));
+
+        let mut m_current = MetaData::new("foo", "baz", EntryType::Article, digest, None);
+        m_current.set_subject("xyzzy");
+        let r = m.xattr_diff(&m_current.xattr_values(), true);
+        assert_eq!(r, vec!(
+            XattrChange::Change(String::from(DC_XATTR_CREATOR), String::from("baz"), String::from("bar")),
+            XattrChange::Add(String::from(DC_XATTR_LANGUAGE), String::from("nb-NO")),
+            XattrChange::Remove(String::from(DC_XATTR_SUBJECT), String::from("xyzzy")),
+        ));
+        let r = m.xattr_diff(&m_current.xattr_values(), false);
+        assert_eq!(r.len(), 2);
+    }
+
+    #[test]
+    fn test_metadata_xattr_diff_path() {
+        let f = NamedTempFile::new_in(".").unwrap();
+        let fp = f.path();
+        write(&f, "%PDF-1.4\n%%EOF\n".as_bytes()).unwrap();
+        xattr::set(fp, DC_XATTR_CREATOR, "bar".as_bytes()).unwrap();
+
+        let digest = digest::from_vec(vec!(0x2a; 64)).unwrap();
+        let mut m = MetaData::new("foo", "bar", EntryType::Article, digest, None);
+        m.set_mime_str("application/pdf").unwrap();
+
+        let current = xattr_values_from_path(fp).unwrap();
+        let r = m.xattr_diff(&current, false);
+        assert_eq!(r, vec!(
+            XattrChange::Add(String::from(DC_XATTR_TITLE), String::from("foo")),
+            XattrChange::Add(String::from(DC_XATTR_TYPE), String::from("article")),
+            XattrChange::Add(String::from(DC_XATTR_MEDIATYPE), String::from("application/pdf")),
+        ));
+
+        xattr::set(fp, DC_XATTR_TITLE, "foo".as_bytes()).unwrap();
+        xattr::set(fp, DC_XATTR_TYPE, "article".as_bytes()).unwrap();
+        let current = xattr_values_from_path(fp).unwrap();
+        let r = m.xattr_diff(&current, false);
+        assert_eq!(r, vec!(
+            XattrChange::Add(String::from(DC_XATTR_MEDIATYPE), String::from("application/pdf")),
+        ));
+    }
+
+    #[test]
+    fn test_metadata_subjects() {
+        let mut m = MetaData::empty();
+        assert_eq!(m.subject(), None);
+
+        m.set_subject("bitcoin, cryptocurrency,  digital   cash ,,Bitcoin");
+        assert_eq!(m.subjects(), vec!("bitcoin", "cryptocurrency", "digital cash"));
+        assert_eq!(m.subject().unwrap(), "bitcoin,cryptocurrency,digital cash");
+
+        assert!(m.add_subject(" p2p "));
+        assert!(!m.add_subject("P2P"));
+        assert!(!m.add_subject("foo,bar"));
+        assert!(m.has_subject("Digital Cash"));
+
+        assert!(m.remove_subject("Cryptocurrency"));
+        assert!(!m.remove_subject("cryptocurrency"));
+        assert_eq!(m.subject().unwrap(), "bitcoin,digital cash,p2p");
+    }
+
+    #[test]
+    fn test_metadata_merge() {
+        let digest = digest::from_vec(vec!(0x2a; 64)).unwrap();
+        let digest_alias = digest::RecordDigest::Sha256(vec!(0x2b; 32));
+        let mut m = MetaData::new("foo", "bar", EntryType::Article, digest.clone(), None);
+        m.set_language("nb-NO").unwrap();
+
+        let mut m_other = MetaData::new("foo", "baz", EntryType::Article, digest_alias.clone(), None);
+        m_other.set_subject("xyzzy");
+        m_other.set_language("en-US").unwrap();
+
+        let r = m.merge(&m_other);
+        assert_eq!(r, vec!(
+            MergeConflict{
+                field: String::from("creator"),
+                existing: String::from("bar"),
+                other: String::from("baz"),
+            },
+            MergeConflict{
+                field: String::from("language"),
+                existing: String::from("nb-NO"),
+                other: String::from("en-US"),
+            },
+        ));
+        assert_eq!(m.author(), "bar");
+        assert_eq!(m.subject().unwrap(), "xyzzy");
+        assert_eq!(m.language().unwrap(), "nb-NO");
+        assert_eq!(m.digests(), vec!(digest, digest_alias));
     }
 
     #[test]
@@ -614,6 +1388,36 @@ mod tests {
         assert_eq!(m_check.language().unwrap(), "nb-NO");
     }
 
+    #[test]
+    fn test_metadata_xattr_legacy_creators() {
+        let f = NamedTempFile::new_in(".").unwrap();
+        let fp = f.path();
+        write(&f, [0, 1, 2, 3]).unwrap();
+        xattr::set(fp, DC_XATTR_TITLE, "foo".as_bytes()).unwrap();
+        xattr::set(fp, DC_XATTR_CREATOR, "Gowri Sankar Ramachandran, Rahul Radhakrishnan and Bhaskar Krishnamachari".as_bytes()).unwrap();
+        let m = MetaData::from_xattr(fp).unwrap();
+        assert_eq!(m.creators(), vec!(
+            Creator::person("Gowri Sankar", "Ramachandran"),
+            Creator::person("Rahul", "Radhakrishnan"),
+            Creator::person("Bhaskar", "Krishnamachari"),
+        ));
+
+        xattr::set(fp, DC_XATTR_CREATOR, "Government of Kenya".as_bytes()).unwrap();
+        let m = MetaData::from_xattr(fp).unwrap();
+        assert_eq!(m.creators(), vec!(Creator::organisation("Government of Kenya")));
+
+        xattr::set(fp, DC_XATTR_CREATOR, "Satoshi Nakamoto, Hal Finney".as_bytes()).unwrap();
+        let m = MetaData::from_xattr(fp).unwrap();
+        assert_eq!(m.creators(), vec!(
+            Creator::person("Satoshi", "Nakamoto"),
+            Creator::person("Hal", "Finney"),
+        ));
+
+        m.to_xattr(fp).unwrap();
+        let m_check = MetaData::from_xattr(fp).unwrap();
+        assert_eq!(m_check.creators(), m.creators());
+    }
+
     #[test]
     fn test_metadata_xattr_magic() {
         let s = path::Path::new("testdata/bitcoin.pdf");
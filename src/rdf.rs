@@ -20,21 +20,32 @@ use rio_api::model::{
     Literal,
     Triple,
     Subject,
+    Term,
 };
 use urn::{
-    Urn,
     Error as UrnError,
 };
 
 use log::{
     debug,
     info,
+    warn,
     error,
 };
 
 use crate::digest;
-use crate::meta::MetaData;
-use crate::error::ParseError;
+use crate::digest::urn as digest_urn;
+use crate::meta::{
+    MetaData,
+    text_fields,
+    publish_date_from_str,
+    publish_date_to_string,
+};
+use crate::error::{
+    Error,
+    Format,
+};
+use crate::dc::creator;
 use crate::dc::{
     DC_IRI_TITLE,
     DC_IRI_CREATOR,
@@ -42,8 +53,19 @@ use crate::dc::{
     DC_IRI_LANGUAGE,
     DC_IRI_TYPE,
     DC_IRI_MEDIATYPE,
+    DC_IRI_ISSUED,
 };
 
+/// Datatype of publication dates known to the day.
+pub const XSD_IRI_DATE: &str = "http://www.w3.org/2001/XMLSchema#date";
+/// Datatype of publication dates known to the month.
+pub const XSD_IRI_GYEARMONTH: &str = "http://www.w3.org/2001/XMLSchema#gYearMonth";
+/// Datatype of publication dates known to the year.
+pub const XSD_IRI_GYEAR: &str = "http://www.w3.org/2001/XMLSchema#gYear";
+
+/// Predicate linking the subject of a record to the URN of an additional digest of the same file.
+pub const OWL_IRI_SAMEAS: &str = "http://www.w3.org/2002/07/owl#sameAs";
+
 #[derive(Debug)]
 /// Error states when processing RDF data.
 pub enum RdfError {
@@ -56,17 +78,23 @@ pub enum RdfError {
 
 /// Write metadata entry in the native rdf-turtle format.
 ///
+/// Each creator is written as a separate `dcterms:creator` literal, in BibTeX name format, see
+/// [Creator](crate::dc::Creator). Each subject keyword is written as a separate `dcterms:subject`
+/// literal.
+///
+/// Additional digests of the record are written as [OWL_IRI_SAMEAS](OWL_IRI_SAMEAS) links from the
+/// subject to the URN of each digest.
+///
 /// On success, returns the number of bytes written.
 ///
 /// # Arguments 
 ///
 /// * `entry` - metadata to write.
 /// * `w` - writer implementation providing the destination.
-pub fn write(entry: &MetaData, w: impl Write) -> Result<usize, std::io::Error> {
+pub fn write(entry: &MetaData, w: impl Write) -> Result<usize, Error> {
     let mut tfmt = TurtleFormatter::new(w);
     
-    //let urn_str = format!("URN:sha512:{}", entry.fingerprint());
-    let urn_str = format!("URN:{}", entry.urn());
+    let urn_str = digest_urn::to_urn(entry.digest());
     let urn = Subject::NamedNode(
         NamedNode{
             iri: urn_str.as_str(),
@@ -77,28 +105,28 @@ pub fn write(entry: &MetaData, w: impl Write) -> Result<usize, std::io::Error> {
         subject: urn,
         predicate: NamedNode { iri: DC_IRI_TITLE }.into(),
         object: Literal::Simple { value: entry.title().as_str() }.into(),
-    });
-    tfmt.format(&Triple{
-        subject: urn,
-        predicate: NamedNode { iri: DC_IRI_CREATOR }.into(),
-        object: Literal::Simple { value: entry.author().as_str() }.into(),
-    });
+    })?;
+    for v in entry.creators() {
+        let creator = v.to_bibtex();
+        tfmt.format(&Triple{
+            subject: urn,
+            predicate: NamedNode { iri: DC_IRI_CREATOR },
+            object: Literal::Simple { value: creator.as_str() }.into(),
+        })?;
+    }
     let typ = entry.typ().to_string();
     tfmt.format(&Triple{
         subject: urn,
         predicate: NamedNode { iri: DC_IRI_TYPE }.into(),
         object: Literal::Simple { value: typ.as_str() }.into(),
-    });
-    match entry.subject() {
-        Some(v) => {
-            tfmt.format(&Triple{
-                subject: urn,
-                predicate: NamedNode { iri: DC_IRI_SUBJECT }.into(),
-                object: Literal::Simple { value: v.as_str() }.into(),
-            });
-        },
-        _ => (),
-    };
+    })?;
+    for v in entry.subjects() {
+        tfmt.format(&Triple{
+            subject: urn,
+            predicate: NamedNode { iri: DC_IRI_SUBJECT },
+            object: Literal::Simple { value: v.as_str() }.into(),
+        })?;
+    }
 
     match entry.mime() {
         Some(v) => {
@@ -107,7 +135,7 @@ pub fn write(entry: &MetaData, w: impl Write) -> Result<usize, std::io::Error> {
                 subject: urn,
                 predicate: NamedNode { iri: DC_IRI_MEDIATYPE }.into(),
                 object: Literal::Simple { value: m.as_str() }.into(),
-            });
+            })?;
         },
         _ => (),
     };
@@ -119,81 +147,194 @@ pub fn write(entry: &MetaData, w: impl Write) -> Result<usize, std::io::Error> {
                 subject: urn,
                 predicate: NamedNode { iri: DC_IRI_LANGUAGE }.into(),
                 object: Literal::Simple { value: m.as_str() }.into(),
-            });
+            })?;
         },
         _ => (),
     };
 
-    tfmt.finish();
+    if let Some(v) = entry.publish_date() {
+        let date_str = publish_date_to_string(&v);
+        let datatype = match v {
+            (0, 0, _) => {
+                XSD_IRI_GYEAR
+            },
+            (0, _, _) => {
+                XSD_IRI_GYEARMONTH
+            },
+            _ => {
+                XSD_IRI_DATE
+            },
+        };
+        tfmt.format(&Triple{
+            subject: urn,
+            predicate: NamedNode { iri: DC_IRI_ISSUED },
+            object: Literal::Typed { value: date_str.as_str(), datatype: NamedNode { iri: datatype } }.into(),
+        })?;
+    }
+
+    for (_, iri, _, get, _) in text_fields() {
+        if let Some(v) = get(entry) {
+            tfmt.format(&Triple{
+                subject: urn,
+                predicate: NamedNode { iri },
+                object: Literal::Simple { value: v.as_str() }.into(),
+            })?;
+        }
+    }
+
+    for v in entry.aliases() {
+        let alias_str = digest_urn::to_urn(&v);
+        tfmt.format(&Triple{
+            subject: urn,
+            predicate: NamedNode { iri: OWL_IRI_SAMEAS },
+            object: NamedNode { iri: alias_str.as_str() }.into(),
+        })?;
+    }
+
+    tfmt.finish()?;
     Ok(0)
 }
 
 
+/// Returns the value of a literal term, without quotes, escapes and datatype.
+fn literal_value(term: &Term) -> String {
+    match term {
+        Term::Literal(Literal::Simple { value }) => {
+            String::from(*value)
+        },
+        Term::Literal(Literal::LanguageTaggedString { value, .. }) => {
+            String::from(*value)
+        },
+        Term::Literal(Literal::Typed { value, .. }) => {
+            String::from(*value)
+        },
+        _ => {
+            term.to_string()
+        },
+    }
+}
+
 fn handle_parse_match(metadata: &mut MetaData, triple: Triple) -> Result<(), RdfError> {
-    let subject_iri = triple.subject.to_string();
-    let l = subject_iri.len()-1;
-    //let subject = &subject_iri[1..l];
-    let subject = &subject_iri[1..l];
-    match subject[0..4].to_lowercase().as_str() {
-        "urn:"  => {},
+    let subject = match triple.subject {
+        Subject::NamedNode(v) => {
+            v.iri
+        },
         _ => {
-            return Err(RdfError::UrnError(UrnError::InvalidNid));
+            return Err(RdfError::UrnError(UrnError::InvalidScheme));
         },
     };
-    let digest_urn = match digest::from_urn(&subject[4..]) {
-        Err(e) => {
-            error!("error {:?}", &subject);
-            return Err(RdfError::UrnError(UrnError::InvalidNid));
+    let digest = match digest_urn::parse(subject) {
+        Ok(digest::RecordDigest::Empty) => {
+            error!("invalid subject urn {:?}", subject);
+            return Err(RdfError::UrnError(UrnError::InvalidNss));
         },
         Ok(v) => {
-            &subject[4..]
+            v
+        },
+        Err(e) => {
+            error!("invalid subject urn {:?}: {:?}", subject, e);
+            return Err(RdfError::UrnError(UrnError::InvalidNss));
+        },
+    };
+    match metadata.digest() {
+        digest::RecordDigest::Empty => {
+            debug!("setting fingerprint {:?}", digest);
+            metadata.set_fingerprint(digest);
+        },
+        v => {
+            if *v != digest {
+                return Err(RdfError::HashMismatchError);
+            }
         },
     };
-    let subject_urn = Urn::from_str(subject).unwrap();
-
-    let v = subject_urn.nss();
-    let b = hex::decode(&v).unwrap();
-    if metadata.fingerprint().len() == 0 {
-        debug!("setting fingerprint {}", v);
-        metadata.set_fingerprint_urn(digest_urn);
-    } else if metadata.fingerprint() != v {
-        return Err(RdfError::HashMismatchError);
-    }
 
     let field = triple.predicate.iri;
     match field {
         DC_IRI_TITLE => {
-            let title = triple.object.to_string().replace("\"", "");
+            let title = literal_value(&triple.object);
             metadata.set_title(title.as_str());
             debug!("found title: {}", title);
         },
         DC_IRI_CREATOR => {
-            let author = triple.object.to_string().replace("\"", "");
-            metadata.set_author(author.as_str());
+            let author = literal_value(&triple.object);
+            for v in creator::parse_stored(author.as_str()) {
+                metadata.add_creator(v);
+            }
             debug!("found author: {}", author);
         },
         DC_IRI_SUBJECT => {
-            let mut subject = triple.object.to_string().replace("\"", "");
-            metadata.set_subject(subject.as_str());
+            let subject = literal_value(&triple.object);
+            for v in subject.split(",") {
+                metadata.add_subject(v);
+            }
             debug!("found subject: {}", subject);
         },
         DC_IRI_LANGUAGE => {
-            let mut lang = triple.object.to_string().replace("\"", "");
-            metadata.set_language(lang.as_str());
+            let lang = literal_value(&triple.object);
+            if metadata.set_language(lang.as_str()).is_err() {
+                warn!("skipping invalid language: {}", lang);
+                return Ok(());
+            }
             debug!("found language: {}", lang);
         },
         DC_IRI_TYPE => {
-            let mut typ = triple.object.to_string().replace("\"", "");
+            let typ = literal_value(&triple.object);
             metadata.set_typ(typ.as_str());
             debug!("found entry type: {}", typ);
         },
         DC_IRI_MEDIATYPE => {
-            let mut mime_type = triple.object.to_string().replace("\"", "");
-            metadata.set_mime_str(mime_type.as_str());
+            let mime_type = literal_value(&triple.object);
+            if metadata.set_mime_str(mime_type.as_str()).is_err() {
+                warn!("skipping invalid mime type: {}", mime_type);
+                return Ok(());
+            }
             debug!("found mime type: {}", mime_type);
         },
+        DC_IRI_ISSUED => {
+            let date_str = literal_value(&triple.object);
+            match publish_date_from_str(date_str.as_str()) {
+                Ok(v) => {
+                    metadata.set_publish_date(v);
+                    debug!("found publish date: {}", date_str);
+                },
+                Err(e) => {
+                    debug!("skipping invalid publish date {}: {:?}", date_str, e);
+                },
+            };
+        },
+        OWL_IRI_SAMEAS => {
+            let alias_iri = match triple.object {
+                Term::NamedNode(v) => {
+                    v.iri
+                },
+                _ => {
+                    return Err(RdfError::UrnError(UrnError::InvalidNid));
+                },
+            };
+            match digest_urn::parse(alias_iri) {
+                Ok(digest::RecordDigest::Empty) => {
+                    return Err(RdfError::UrnError(UrnError::InvalidNss));
+                },
+                Ok(v) => {
+                    metadata.add_digest(v);
+                    debug!("found digest alias: {}", alias_iri);
+                },
+                Err(_) => {
+                    return Err(RdfError::UrnError(UrnError::InvalidNid));
+                },
+            };
+        },
         _ => {
-            debug!("skipping unknown predicate: {}", field);
+            match text_fields().into_iter().find(|(_, iri, _, _, _)| *iri == field) {
+                Some((k, _, _, _, set)) => {
+                    let v = literal_value(&triple.object);
+                    set(metadata, v.as_str());
+                    debug!("found {}: {}", k, v);
+                },
+                None => {
+                    debug!("skipping unknown predicate: {}", field);
+                },
+            };
         },
     };
     Ok(())
@@ -201,12 +342,13 @@ fn handle_parse_match(metadata: &mut MetaData, triple: Triple) -> Result<(), Rdf
 
 /// Read one or more metadata entries from the rdf-turtle source.
 ///
-/// Will return `ParseError` if any of the records are invalid.
+/// Will return [Error::Parse](Error::Parse) if the source is not valid rdf-turtle, or has no
+/// records.
 ///
 /// # Arguments 
 ///
 /// * `r` - reader implementation providing the source.
-pub fn read_all(r: impl Read) -> Result<Vec<MetaData>, ParseError> {
+pub fn read_all(r: impl Read) -> Result<Vec<MetaData>, Error> {
     let mut rr: Vec<MetaData> = vec!();
     let bf = BufReader::new(r);
     let mut tp = TurtleParser::new(bf, None);
@@ -216,7 +358,7 @@ pub fn read_all(r: impl Read) -> Result<Vec<MetaData>, ParseError> {
         match r {
             Triple{subject, predicate, object } => {
                 match handle_parse_match(&mut rr[i], r) {
-                    Err(HashMismatchError) => {
+                    Err(RdfError::HashMismatchError) => {
                         rr.push(MetaData::empty());
                         i += 1;
                         match handle_parse_match(&mut rr[i], r) {
@@ -226,25 +368,34 @@ pub fn read_all(r: impl Read) -> Result<Vec<MetaData>, ParseError> {
                             _ => {},
                         };
                     },
+                    Err(e) => {
+                        error!("error parsing rdf source: {:?}", e);
+                    },
                     _ => {},
                 };
             },
         }
         Ok(())
     });
+    if let Err(e) = r {
+        return Err(Error::parse(Format::Rdf, &e.to_string()));
+    }
     // TODO: should check validity of all records
     if rr[0].fingerprint() == "" {
-        return Err(ParseError::new("empty fingerprint"));
+        return Err(Error::parse(Format::Rdf, "empty fingerprint"));
     }
     Ok(rr)
 }
 
 /// Read a single metadata entry from the rdf-turtle source.
 ///
+/// Will return [Error::Parse](Error::Parse) if the source is not valid rdf-turtle, or has no
+/// record.
+///
 /// # Arguments 
 ///
 /// * `r` - reader implementation providing the source.
-pub fn read(r: impl Read) -> MetaData {
+pub fn read(r: impl Read) -> Result<MetaData, Error> {
     let mut rr: Vec<MetaData> = vec!();
     let mut metadata = MetaData::empty();
     let bf = BufReader::new(r);
@@ -263,7 +414,13 @@ pub fn read(r: impl Read) -> MetaData {
         }
         Ok(())
     });
-    metadata
+    if let Err(e) = r {
+        return Err(Error::parse(Format::Rdf, &e.to_string()));
+    }
+    if metadata.fingerprint() == "" {
+        return Err(Error::parse(Format::Rdf, "empty fingerprint"));
+    }
+    Ok(metadata)
 }
 
 #[cfg(test)]
@@ -271,9 +428,15 @@ mod tests {
     use super::{
         write,
         read,
+        read_all,
     };
     use super::MetaData;
     use crate::digest;
+    use crate::error::{
+        Error,
+        Format,
+    };
+    use crate::dc::Creator;
     use std::io::stdout;
     use std::fs::File;
     use std::default::Default;
@@ -287,8 +450,8 @@ mod tests {
         let digest_sha = digest::from_vec(Vec::from(digest)).unwrap();
         let mut m = MetaData::new("foo", "bar", EntryType::Article, digest_sha, None);
         m.set_subject("baz");
-        m.set_mime_str("foo/bar");
-        m.set_language("nb-NO");
+        m.set_mime_str("foo/bar").unwrap();
+        m.set_language("nb-NO").unwrap();
         //let v = stdout();
         let mut v: Vec<u8> = vec!();
         let r = write(&m, v);
@@ -297,6 +460,158 @@ mod tests {
     #[test]
     fn test_turtle_read() {
         let f = File::open("testdata/meta.ttl").unwrap();
-        read(&f);
+        read(&f).unwrap();
+    }
+
+    #[test]
+    fn test_turtle_text_fields() {
+        let digest_sha = digest::from_vec(vec!(0x2a; 64)).unwrap();
+        let mut m = MetaData::new("foo", "bar", EntryType::Book, digest_sha, None);
+        m.set_publisher("Xyzzy Press");
+        m.set_identifier("urn:isbn:9780000000000");
+        m.set_description("A \"quoted\" description");
+        m.set_part_of("Inky Pinky Series");
+        m.set_extent("42 pages");
+        m.set_publish_date((0, 11, 2019));
+        let mut v: Vec<u8> = vec!();
+        write(&m, &mut v).unwrap();
+        let s = String::from_utf8(v.clone()).unwrap();
+        assert!(s.contains("\"2019-11\"^^<http://www.w3.org/2001/XMLSchema#gYearMonth>"));
+
+        let m_check = read(v.as_slice()).unwrap();
+        assert_eq!(m_check.publisher().unwrap(), "Xyzzy Press");
+        assert_eq!(m_check.identifier().unwrap(), "urn:isbn:9780000000000");
+        assert_eq!(m_check.description().unwrap(), "A \"quoted\" description");
+        assert_eq!(m_check.part_of().unwrap(), "Inky Pinky Series");
+        assert_eq!(m_check.extent().unwrap(), "42 pages");
+        assert_eq!(m_check.rights(), None);
+        assert_eq!(m_check.publish_date().unwrap(), (0, 11, 2019));
+    }
+
+    #[test]
+    fn test_turtle_escaped_literals() {
+        let digest_sha = digest::from_vec(vec!(0x2a; 64)).unwrap();
+        let mut m = MetaData::new("The \"Foo\" \\ Bar", "bar", EntryType::Article, digest_sha, None);
+        m.set_description("The \"Foo\" \\ Bar");
+        let mut v: Vec<u8> = vec!();
+        write(&m, &mut v).unwrap();
+
+        let m_check = read(v.as_slice()).unwrap();
+        assert_eq!(m_check.title(), "The \"Foo\" \\ Bar");
+        assert_eq!(m_check.description(), Some(m_check.title()));
+
+        let src = format!("<urn:sha256:{}> <https://purl.org/dc/terms/title> \"Inky \\\"Pinky\\\" Ponky\" .
+<urn:sha256:{}> <https://purl.org/dc/terms/MediaType> \"application/pdf\"^^<http://www.w3.org/2001/XMLSchema#string> .
+",
+            "2b".repeat(32),
+            "2b".repeat(32),
+        );
+        let m = read(src.as_bytes()).unwrap();
+        assert_eq!(m.title(), "Inky \"Pinky\" Ponky");
+        assert_eq!(m.mime().unwrap(), "application/pdf");
+    }
+
+    #[test]
+    fn test_turtle_creators() {
+        let f = File::open("testdata/meta.ttl").unwrap();
+        let r = read_all(&f).unwrap();
+        assert_eq!(r[0].creators(), vec!(Creator::organisation("European Parliamentary Research Service, Scientific Foresight Unit (STOA)")));
+        assert_eq!(r[1].creators().len(), 3);
+        assert_eq!(r[1].creators(), vec!(
+            Creator::person("Gowri Sankar", "Ramachandran"),
+            Creator::person("Rahul", "Radhakrishnan"),
+            Creator::person("Bhaskar", "Krishnamachari"),
+        ));
+
+        let mut v: Vec<u8> = vec!();
+        write(&r[1], &mut v).unwrap();
+        let m_check = read(v.as_slice()).unwrap();
+        assert_eq!(m_check.creators(), r[1].creators());
+
+        let mut m = r[1].clone();
+        m.set_creators(vec!(Creator::person("Ursula K.", "Le Guin"), Creator::organisation("Government of Kenya")));
+        let mut v: Vec<u8> = vec!();
+        write(&m, &mut v).unwrap();
+        let m_check = read(v.as_slice()).unwrap();
+        assert_eq!(m_check.creators(), m.creators());
+    }
+
+    #[test]
+    fn test_turtle_subjects() {
+        let digest_sha = digest::from_vec(vec!(0x2a; 64)).unwrap();
+        let mut m = MetaData::new("foo", "bar", EntryType::Article, digest_sha, None);
+        m.set_subject("bitcoin, cryptocurrency");
+        let mut v: Vec<u8> = vec!();
+        write(&m, &mut v).unwrap();
+        let s = String::from_utf8(v.clone()).unwrap();
+        assert!(s.contains("\"bitcoin\" , \"cryptocurrency\""));
+
+        let m_check = read(v.as_slice()).unwrap();
+        assert_eq!(m_check.subjects(), vec!("bitcoin", "cryptocurrency"));
+
+        let f = File::open("testdata/meta.ttl").unwrap();
+        let r = read_all(&f).unwrap();
+        assert_eq!(r[0].subjects(), vec!("blockchain", "regulation", "government"));
+    }
+
+    #[test]
+    fn test_turtle_aliases() {
+        let digest_sha = digest::from_vec(vec!(0x2a; 64)).unwrap();
+        let digest_alias = digest::RecordDigest::Sha256(vec!(0x2b; 32));
+        let mut m = MetaData::new("foo", "bar", EntryType::Article, digest_sha, None);
+        m.add_digest(digest_alias);
+        let mut v: Vec<u8> = vec!();
+        write(&m, &mut v).unwrap();
+
+        let r = read_all(v.as_slice()).unwrap();
+        assert_eq!(r.len(), 1);
+        assert_eq!(r[0].title(), "foo");
+        assert_eq!(r[0].digests(), m.digests());
+    }
+
+    #[test]
+    fn test_turtle_urn() {
+        let digest_sha = digest::from_vec(vec!(0x2a; 64)).unwrap();
+        let m = MetaData::new("foo", "bar", EntryType::Article, digest_sha, None);
+        let mut v: Vec<u8> = vec!();
+        write(&m, &mut v).unwrap();
+        let s = String::from_utf8(v).unwrap();
+        assert!(s.starts_with(&format!("<urn:sha512:{}>", "2a".repeat(64))));
+
+        let src = format!("<URN:HASH::SHA256:{}> <https://purl.org/dc/terms/title> \"foo\" .
+<urn:sha256:{}> <https://purl.org/dc/terms/creator> \"bar\" .
+<urn:sha256:{}> <http://www.w3.org/2002/07/owl#sameAs> <ni:///sha-512;{}> .
+<urn:hash::sha256:{}> <https://purl.org/dc/terms/title> \"baz\" .
+",
+            "2B".repeat(32),
+            "2b".repeat(32),
+            "2b".repeat(32),
+            "Kioq".repeat(21) + "Kg",
+            "2c".repeat(32),
+        );
+        let r = read_all(src.as_bytes()).unwrap();
+        assert_eq!(r.len(), 2);
+        assert_eq!(r[0].urn(), format!("sha256:{}", "2b".repeat(32)));
+        assert_eq!(r[0].author(), "bar");
+        assert_eq!(r[0].aliases(), vec!(digest::RecordDigest::Sha512(vec!(0x2a; 64))));
+        assert_eq!(r[1].title(), "baz");
+    }
+
+    #[test]
+    fn test_turtle_invalid() {
+        let e = read_all("<urn:sha256:foo> bar".as_bytes()).unwrap_err();
+        assert!(matches!(e, Error::Parse{format: Format::Rdf, ..}));
+        assert!(read("".as_bytes()).is_err());
+
+        // invalid field values are skipped
+        let src = format!("<urn:sha256:{}> <https://purl.org/dc/terms/title> \"foo\" .
+<urn:sha256:{}> <https://purl.org/dc/terms/language> \"xx-\" .
+",
+            "2b".repeat(32),
+            "2b".repeat(32),
+        );
+        let m = read(src.as_bytes()).unwrap();
+        assert_eq!(m.title(), "foo");
+        assert_eq!(m.language(), None);
     }
 }
@@ -0,0 +1,116 @@
+//! Summary of the files processed in a run of the tool.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde_json::json;
+
+/// Exit status when all files were processed successfully.
+pub const EXIT_OK: i32 = 0;
+
+/// Exit status when the command could not be run, e.g. for invalid arguments or an unavailable
+/// store.
+pub const EXIT_ERROR: i32 = 1;
+
+/// Exit status when the command ran, but one or more files failed.
+pub const EXIT_FAILED: i32 = 2;
+
+/// Counts of the outcomes for the files processed by `import` or `apply`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    /// Files found under the path.
+    pub scanned: usize,
+    /// Files with an import source, or with a record in the store to apply.
+    pub matched: usize,
+    /// Records imported, by source format.
+    pub imported: BTreeMap<String, usize>,
+    /// Files that metadata was applied to.
+    pub applied: usize,
+    /// Files not probed for import sources, because of their size.
+    pub skipped: usize,
+    /// Files that could not be read, parsed, stored or applied to.
+    pub failed: usize,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Report::default()
+    }
+
+    /// Count a record imported from a source of the given format.
+    pub fn add_imported(&mut self, format: &str) {
+        *self.imported.entry(String::from(format)).or_insert(0) += 1;
+    }
+
+    /// Returns the number of records imported from all formats.
+    pub fn imported_total(&self) -> usize {
+        self.imported.values().sum()
+    }
+
+    /// Returns the exit status of the tool for the run.
+    pub fn exit_code(&self) -> i32 {
+        match self.failed {
+            0 => {
+                EXIT_OK
+            },
+            _ => {
+                EXIT_FAILED
+            },
+        }
+    }
+
+    /// Returns the report as a JSON object.
+    pub fn to_json(&self) -> String {
+        json!({
+            "scanned": self.scanned,
+            "matched": self.matched,
+            "imported": self.imported,
+            "applied": self.applied,
+            "skipped": self.skipped,
+            "failed": self.failed,
+        }).to_string()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} scanned, {} matched, {} imported", self.scanned, self.matched, self.imported_total())?;
+        if !self.imported.is_empty() {
+            let formats: Vec<String> = self.imported.iter()
+                .map(|(k, v)| {
+                    format!("{} {}", k, v)
+                })
+                .collect();
+            write!(f, " ({})", formats.join(", "))?;
+        }
+        write!(f, ", {} applied, {} skipped, {} failed", self.applied, self.skipped, self.failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Report,
+        EXIT_OK,
+        EXIT_FAILED,
+    };
+
+    #[test]
+    fn test_report() {
+        let mut r = Report::new();
+        r.scanned = 4;
+        r.matched = 3;
+        r.add_imported("rdf");
+        r.add_imported("xattr");
+        r.add_imported("rdf");
+        r.skipped = 1;
+        assert_eq!(r.imported_total(), 3);
+        assert_eq!(r.exit_code(), EXIT_OK);
+        assert_eq!(r.to_string(), "4 scanned, 3 matched, 3 imported (rdf 2, xattr 1), 0 applied, 1 skipped, 0 failed");
+
+        r.failed = 1;
+        assert_eq!(r.exit_code(), EXIT_FAILED);
+        let v: serde_json::Value = serde_json::from_str(&r.to_json()).unwrap();
+        assert_eq!(v["imported"]["rdf"], 2);
+        assert_eq!(v["failed"], 1);
+    }
+}
@@ -0,0 +1,422 @@
+//! Import from [RIS](https://en.wikipedia.org/wiki/RIS_(file_format)) files, as offered for
+//! download by many publishers and library catalogues.
+//!
+//! Digests of a record are read as whitespace-separated URNs from `N1` notes, the same as the
+//! `note` field of [biblatex](crate::biblatex) entries, and from the custom tags `C1` to `C8`.
+use std::io::{
+    BufRead,
+    BufReader,
+    Read,
+};
+
+use biblatex::EntryType;
+use log::{
+    debug,
+    warn,
+};
+
+use crate::meta::{
+    MetaData,
+    PublishDate,
+};
+use crate::dc::Creator;
+use crate::error::{
+    Error,
+    Format,
+};
+use crate::digest::RecordDigest;
+use crate::digest::urn as digest_urn;
+
+/// Tags that may carry digest URNs.
+const DIGEST_TAGS: [&str; 9] = ["N1", "C1", "C2", "C3", "C4", "C5", "C6", "C7", "C8"];
+
+/// Returns the entry type for a RIS reference type.
+pub fn entry_type_from_ris(s: &str) -> EntryType {
+    match s {
+        "JOUR" | "JFULL" | "ABST" | "INPR" | "MGZN" | "NEWS" | "EJOUR" => {
+            EntryType::Article
+        },
+        "BOOK" | "EBOOK" | "EDBOOK" => {
+            EntryType::Book
+        },
+        "CHAP" | "ECHAP" => {
+            EntryType::InCollection
+        },
+        "CONF" | "CPAPER" => {
+            EntryType::InProceedings
+        },
+        "ENCYC" | "DICT" => {
+            EntryType::InReference
+        },
+        "RPRT" | "GOVDOC" => {
+            EntryType::Report
+        },
+        "THES" => {
+            EntryType::Thesis
+        },
+        "ELEC" | "WEB" | "BLOG" => {
+            EntryType::Online
+        },
+        "COMP" => {
+            EntryType::Software
+        },
+        "DATA" | "AGGR" => {
+            EntryType::Dataset
+        },
+        "PAT" => {
+            EntryType::Patent
+        },
+        "UNPB" | "MANSCPT" => {
+            EntryType::Unpublished
+        },
+        "PAMP" => {
+            EntryType::Booklet
+        },
+        _ => {
+            EntryType::Misc
+        },
+    }
+}
+
+/// Tags and values of a single RIS record, in the order they appear.
+struct Record {
+    fields: Vec<(String, String)>,
+}
+
+impl Record {
+    /// Returns the first non-empty value of the first of the given tags present in the record.
+    fn value(&self, tags: &[&str]) -> Option<String> {
+        for k in tags {
+            for (tag, v) in self.fields.iter() {
+                if tag == k && !v.is_empty() {
+                    return Some(v.clone());
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns all non-empty values of the given tags, in the order they appear.
+    fn values(&self, tags: &[&str]) -> Vec<String> {
+        self.fields.iter()
+            .filter(|(tag, v)| {
+                tags.contains(&tag.as_str()) && !v.is_empty()
+            })
+            .map(|(_, v)| {
+                v.clone()
+            })
+            .collect()
+    }
+}
+
+/// Split a RIS line into its tag and value, e.g. `TY  - JOUR`.
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let tag = line.get(..2)?;
+    let rest = line.get(2..)?;
+    if !tag.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+        return None;
+    }
+    let value = rest.trim_start_matches(' ').strip_prefix("-")?;
+    Some((tag, value.trim()))
+}
+
+/// Split the source into records.
+///
+/// Lines that are not tagged are continuations of the value on the previous line.
+fn parse_records(r: impl Read) -> Result<Vec<Record>, Error> {
+    let mut records: Vec<Record> = vec!();
+    let mut current: Option<Record> = None;
+    for (i, line) in BufReader::new(r).lines().enumerate() {
+        let line = line?;
+        let line = line.trim_start_matches('\u{feff}').trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        match (parse_line(line), current.as_mut()) {
+            (Some(("TY", v)), None) => {
+                current = Some(Record{
+                    fields: vec!((String::from("TY"), String::from(v))),
+                });
+            },
+            (Some(("ER", _)), Some(_)) => {
+                records.push(current.take().unwrap());
+            },
+            (Some((tag, v)), Some(record)) => {
+                record.fields.push((String::from(tag), String::from(v)));
+            },
+            (None, Some(record)) => {
+                if let Some((_, v)) = record.fields.last_mut() {
+                    v.push(' ');
+                    v.push_str(line.trim());
+                }
+            },
+            _ => {
+                return Err(Error::parse(Format::Ris, &format!("unexpected content on line {}", i + 1)));
+            },
+        };
+    }
+    if current.is_some() {
+        return Err(Error::parse(Format::Ris, "missing end of record"));
+    }
+    Ok(records)
+}
+
+/// Parse a RIS author name, e.g. `Nakamoto, Satoshi`, or `Doe, John, Jr.`.
+///
+/// A name without comma is parsed as written in running text, so it is only taken to be an
+/// organisation if it looks like one, see [Creator::from_text](crate::dc::Creator::from_text).
+fn parse_creator(s: &str) -> Creator {
+    let parts: Vec<&str> = s.split(",")
+        .map(|v| {
+            v.trim()
+        })
+        .collect();
+    if parts.len() == 1 {
+        return Creator::from_text(parts[0]);
+    }
+    Creator::Person{
+        given: String::from(*parts.get(1).unwrap_or(&"")),
+        family: String::from(parts[0]),
+        prefix: String::new(),
+        suffix: String::from(*parts.get(2).unwrap_or(&"")),
+    }
+}
+
+/// Returns the publication date of the record from the `DA`, `PY` or `Y1` tags.
+///
+/// Dates are given as `YYYY/MM/DD/other`, where all but the year may be empty.
+fn parse_publish_date(record: &Record) -> Option<PublishDate> {
+    let v = record.value(&["DA", "PY", "Y1"])?;
+    let mut parts = v.split(['/', '-']);
+    let year = parts.next()?.trim().parse::<u32>().ok()?;
+    let month = parts.next().and_then(|v| v.trim().parse::<u8>().ok()).unwrap_or(0);
+    let day = match month {
+        0 => {
+            0
+        },
+        _ => {
+            parts.next().and_then(|v| v.trim().parse::<u8>().ok()).unwrap_or(0)
+        },
+    };
+    let date = (day, month, year);
+    if !crate::meta::publish_date_valid(&date) {
+        debug!("invalid date {}", v);
+        return None;
+    }
+    Some(date)
+}
+
+/// Returns the identifier of the record, in order of preference from `DO`, `SN` and `UR`.
+///
+/// Serial numbers of 8 characters are taken to be ISSN, others ISBN.
+fn parse_identifier(record: &Record) -> Option<String> {
+    if let Some(v) = record.value(&["DO"]) {
+        return Some(format!("doi:{}", v));
+    }
+    if let Some(v) = record.value(&["SN"]) {
+        let v = v.split_whitespace().next().unwrap_or("");
+        let c = v.chars().filter(|c| c.is_ascii_alphanumeric()).count();
+        if c == 8 {
+            return Some(format!("urn:issn:{}", v));
+        }
+        return Some(format!("urn:isbn:{}", v));
+    }
+    record.value(&["UR", "L2"])
+}
+
+/// Parse digest URNs from the notes and custom tags of the record.
+///
+/// Words that are not valid digest URNs are ignored.
+fn parse_digests(record: &Record) -> Vec<RecordDigest> {
+    let mut r: Vec<RecordDigest> = vec!();
+    for note in record.values(&DIGEST_TAGS) {
+        for v in note.split_whitespace() {
+            match digest_urn::parse(v) {
+                Ok(RecordDigest::Empty) => {},
+                Ok(digest) => {
+                    if !r.contains(&digest) {
+                        r.push(digest);
+                    }
+                },
+                Err(e) => {
+                    debug!("skipping non-digest note content {:?}: {:?}", v, e);
+                },
+            };
+        }
+    }
+    r
+}
+
+/// Read one or more metadata entries from the RIS source.
+///
+/// Will return [Error::Parse](Error::Parse) if the source is not valid RIS, or if any of the
+/// records have no title or no digest.
+///
+/// # Arguments
+///
+/// * `r` - reader implementation providing the source.
+/// * `digests` - digests to add to the record, in which case the source must have a single record.
+pub fn read_all(r: impl Read, digests: &Vec<RecordDigest>) -> Result<Vec<MetaData>, Error> {
+    let records = parse_records(r)?;
+    if records.is_empty() {
+        return Err(Error::parse(Format::Ris, "no records"));
+    }
+    if records.len() > 1 && !digests.is_empty() {
+        return Err(Error::parse(Format::Ris, "more than one ris record parsed while static digest provided"));
+    }
+
+    let mut rr: Vec<MetaData> = vec!();
+    for (i, record) in records.iter().enumerate() {
+        let title = match record.value(&["TI", "T1", "CT"]) {
+            Some(v) => {
+                v
+            },
+            None => {
+                return Err(Error::parse(Format::Ris, &format!("missing title in record {}", i + 1)));
+            },
+        };
+
+        let mut use_digests = parse_digests(record);
+        for v in digests {
+            use_digests.push(v.clone());
+        }
+        if use_digests.is_empty() {
+            return Err(Error::parse(Format::Ris, &format!("no digests found in record {}", i + 1)));
+        }
+
+        let typ = entry_type_from_ris(record.value(&["TY"]).unwrap_or(String::new()).as_str());
+        let mut m = MetaData::new(title.as_str(), "", typ, RecordDigest::Empty, None);
+        let creators: Vec<Creator> = record.values(&["AU", "A1"]).iter()
+            .map(|v| {
+                parse_creator(v)
+            })
+            .collect();
+        m.set_creators(creators);
+        for dd in use_digests.into_iter() {
+            m.add_digest(dd);
+        }
+
+        for v in record.values(&["KW"]) {
+            for k in v.split([',', ';']) {
+                m.add_subject(k);
+            }
+        }
+
+        if let Some(v) = record.value(&["LA"]) {
+            if let Err(e) = m.set_language(v.as_str()) {
+                warn!("skipping invalid language in record {}: {}", i + 1, e);
+            }
+        }
+
+        let editors: Vec<Creator> = record.values(&["A2", "ED"]).iter()
+            .map(|v| {
+                parse_creator(v)
+            })
+            .collect();
+        m.set_contributors(&editors);
+
+        if let Some(v) = parse_publish_date(record) {
+            m.set_publish_date(v);
+        }
+
+        if let Some(v) = parse_identifier(record) {
+            m.set_identifier(v.as_str());
+        }
+
+        for (tags, set) in [
+            (&["PB"][..], MetaData::set_publisher as fn(&mut MetaData, &str)),
+            (&["AB", "N2"][..], MetaData::set_description),
+            (&["T2", "JF", "JO", "BT", "T3"][..], MetaData::set_part_of),
+            (&["DB"][..], MetaData::set_source),
+        ] {
+            if let Some(v) = record.value(tags) {
+                set(&mut m, v.as_str());
+            }
+        }
+
+        match (record.value(&["SP"]), record.value(&["EP"])) {
+            (Some(sp), Some(ep)) => {
+                m.set_extent(format!("pp. {}--{}", sp, ep).as_str());
+            },
+            (Some(sp), None) => {
+                m.set_extent(format!("pp. {}", sp).as_str());
+            },
+            _ => {},
+        };
+
+        debug!("read metadata {:?}", &m);
+        rr.push(m);
+    }
+    Ok(rr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_all;
+    use std::fs::File;
+    use biblatex::EntryType;
+    use crate::digest;
+    use crate::dc::Creator;
+    use crate::error::{
+        Error,
+        Format,
+    };
+
+    #[test]
+    fn test_ris_read() {
+        let f = File::open("testdata/meta.ris").unwrap();
+        let r = read_all(&f, &vec!()).unwrap();
+        assert_eq!(r.len(), 2);
+
+        let m = &r[0];
+        assert_eq!(m.title(), "Bitcoin: A Peer-to-Peer Electronic Cash System");
+        assert_eq!(m.creators(), vec!(Creator::person("Satoshi", "Nakamoto")));
+        assert_eq!(m.typ(), EntryType::Article);
+        assert_eq!(m.subjects(), vec!("bitcoin", "cryptocurrency"));
+        assert_eq!(m.language().unwrap(), "en");
+        assert_eq!(m.publish_date().unwrap(), (31, 10, 2008));
+        assert_eq!(m.urn(), "sha512:2ac531ee521cf93f8419c2018f770fbb42c65396178e079a416e7038d3f9ab9fc2c35c4d838bc8b5dd68f4c13759fe9cdf90a46528412fefe1294cb26beabf4e");
+
+        let m = &r[1];
+        assert_eq!(m.title(), "The Data Protection Act, 2019");
+        assert_eq!(m.creators(), vec!(Creator::organisation("Government of Kenya")));
+        assert_eq!(m.typ(), EntryType::Report);
+        assert_eq!(m.digests().len(), 2);
+        assert_eq!(m.publish_date().unwrap(), (0, 11, 2019));
+        assert_eq!(m.publisher().unwrap(), "The Government Printer, Nairobi");
+        assert_eq!(m.part_of().unwrap(), "Kenya Gazette Supplement, ACTS 2019");
+        assert_eq!(m.identifier().unwrap(), "http://kenyalaw.org/kl/fileadmin/pdfdownloads/Acts/2019/TheDataProtectionAct__No24of2019.pdf");
+        assert_eq!(m.extent().unwrap(), "pp. 901--948");
+    }
+
+    #[test]
+    fn test_ris_digests() {
+        let d = digest::RecordDigest::Sha256(vec!(0x2a; 32));
+        let src = "TY  - BOOK\r\nTI  - foo\r\nAU  - Threepwood, Guybrush\r\nSN  - 978-0-00-000000-0\r\nER  - \r\n";
+        let r = read_all(src.as_bytes(), &vec!(d.clone())).unwrap();
+        assert_eq!(r[0].typ(), EntryType::Book);
+        assert_eq!(r[0].digests(), vec!(d.clone()));
+        assert_eq!(r[0].identifier().unwrap(), "urn:isbn:978-0-00-000000-0");
+        assert_eq!(r[0].creators(), vec!(Creator::person("Guybrush", "Threepwood")));
+
+        let src = "TY  - BOOK\r\nTI  - foo\r\nAU  - Guybrush Threepwood\r\nAU  - Ministry for the Environment\r\nAU  - Threepwood\r\nER  - \r\n";
+        let r = read_all(src.as_bytes(), &vec!(d.clone())).unwrap();
+        assert_eq!(r[0].creators(), vec!(
+            Creator::person("Guybrush", "Threepwood"),
+            Creator::organisation("Ministry for the Environment"),
+            Creator::person("", "Threepwood"),
+        ));
+        assert!(r[0].publish_date().is_none());
+
+        let src = "TY  - BOOK\r\nTI  - foo\r\nDA  - 2019/02/31\r\nER  - \r\n";
+        let r = read_all(src.as_bytes(), &vec!(d.clone())).unwrap();
+        assert!(r[0].publish_date().is_none());
+
+        let e = read_all("TY  - BOOK\nTI  - foo\nER  - \n".as_bytes(), &vec!()).unwrap_err();
+        assert_eq!(e.to_string(), "invalid ris data: no digests found in record 1");
+        let e = read_all("@article{foo,}".as_bytes(), &vec!(d.clone())).unwrap_err();
+        assert!(matches!(e, Error::Parse{format: Format::Ris, ..}));
+        let e = read_all("TY  - BOOK\nTI  - foo\n".as_bytes(), &vec!(d)).unwrap_err();
+        assert!(matches!(e, Error::Parse{format: Format::Ris, ..}));
+    }
+}
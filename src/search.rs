@@ -0,0 +1,165 @@
+use mime::Mime;
+use std::str::FromStr;
+
+use crate::meta::MetaData;
+
+/// Criteria for selecting metadata records.
+///
+/// All criteria that are set must match for a record to match the query. A query with no
+/// criteria set matches all records.
+#[derive(Default)]
+pub struct Query {
+    /// Case-insensitive substring of [DCMetaData::title](crate::dc::DCMetaData::title).
+    pub title: Option<String>,
+    /// Case-insensitive substring of [DCMetaData::author](crate::dc::DCMetaData::author).
+    pub creator: Option<String>,
+    /// Keyword which must be in the [DCMetaData::subjects](crate::dc::DCMetaData::subjects) list.
+    pub subject: Option<String>,
+    /// Language identifier. Matches either the full identifier or its language subtag.
+    pub language: Option<String>,
+    /// Entry type, as bibtex entry type string.
+    pub typ: Option<String>,
+    /// MIME type. If only the top-level type is given, e.g. `application`, any subtype matches.
+    pub mime: Option<String>,
+}
+
+fn contains_fold(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(needle.to_lowercase().as_str())
+}
+
+impl Query {
+    /// Create a new query with no criteria set.
+    pub fn new() -> Query {
+        Query::default()
+    }
+
+    /// Check whether the given metadata record matches the query.
+    pub fn matches(&self, m: &MetaData) -> bool {
+        if let Some(v) = &self.title {
+            if !contains_fold(m.title().as_str(), v) {
+                return false;
+            }
+        }
+
+        if let Some(v) = &self.creator {
+            if !contains_fold(m.author().as_str(), v) {
+                return false;
+            }
+        }
+
+        if let Some(v) = &self.subject {
+            if !m.has_subject(v) {
+                return false;
+            }
+        }
+
+        if let Some(v) = &self.language {
+            let language = match m.language() {
+                Some(l) => {
+                    l
+                },
+                None => {
+                    return false;
+                },
+            };
+            let needle = v.to_lowercase();
+            if language.to_string().to_lowercase() != needle
+                && language.language.as_str().to_lowercase() != needle {
+                return false;
+            }
+        }
+
+        if let Some(v) = &self.typ {
+            if m.typ().to_string().to_lowercase() != v.to_lowercase() {
+                return false;
+            }
+        }
+
+        if let Some(v) = &self.mime {
+            let mime = match m.mime() {
+                Some(r) => {
+                    r
+                },
+                None => {
+                    return false;
+                },
+            };
+            if v.contains("/") {
+                match Mime::from_str(v) {
+                    Ok(r) => {
+                        if r.essence_str() != mime.essence_str() {
+                            return false;
+                        }
+                    },
+                    Err(_) => {
+                        return false;
+                    },
+                };
+            } else if mime.type_().as_str() != v.to_lowercase() {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Query;
+    use crate::meta::MetaData;
+    use crate::digest;
+    use biblatex::EntryType;
+
+    fn metadata() -> MetaData {
+        let mut digest = Vec::with_capacity(64);
+        digest.resize(64, 0x2a);
+        let digest_sha = digest::from_vec(digest).unwrap();
+        let mut m = MetaData::new("Bitcoin: A Peer-to-Peer Electronic Cash System", "Satoshi Nakamoto", EntryType::Article, digest_sha, None);
+        m.set_subject("bitcoin, cryptocurrency");
+        m.set_mime_str("application/pdf").unwrap();
+        m.set_language("en-US").unwrap();
+        m
+    }
+
+    #[test]
+    fn test_query_match() {
+        let m = metadata();
+
+        let mut q = Query::new();
+        assert!(q.matches(&m));
+
+        q.title = Some(String::from("peer-to-peer"));
+        q.creator = Some(String::from("nakamoto"));
+        q.subject = Some(String::from("Cryptocurrency"));
+        q.language = Some(String::from("en"));
+        q.typ = Some(String::from("article"));
+        q.mime = Some(String::from("application"));
+        assert!(q.matches(&m));
+
+        q.mime = Some(String::from("application/pdf"));
+        q.language = Some(String::from("en-us"));
+        assert!(q.matches(&m));
+    }
+
+    #[test]
+    fn test_query_mismatch() {
+        let m = metadata();
+
+        let mut q = Query::new();
+        q.subject = Some(String::from("crypto"));
+        assert!(!q.matches(&m));
+
+        let mut q = Query::new();
+        q.language = Some(String::from("nb"));
+        assert!(!q.matches(&m));
+
+        let mut q = Query::new();
+        q.typ = Some(String::from("book"));
+        assert!(!q.matches(&m));
+
+        let mut q = Query::new();
+        q.mime = Some(String::from("text/plain"));
+        assert!(!q.matches(&m));
+    }
+}
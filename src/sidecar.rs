@@ -0,0 +1,442 @@
+//! Sidecar files holding the records of media files, for filesystems without support for
+//! extended attributes, such as FAT, exFAT, many SMB mounts and tmpfs without user xattrs.
+//!
+//! A sidecar is either `<file>.ttl` next to a file, holding the record for that file, or
+//! `.kitab.ttl` in a directory, holding the records for the files in that directory. Both are
+//! rdf-turtle, as written by [rdf::write](crate::rdf::write).
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::os::unix::fs::MetadataExt;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::str::FromStr;
+
+use log::debug;
+
+use crate::cache::DigestCache;
+use crate::digest::{
+    DigestType,
+    RecordDigest,
+};
+use crate::error::Error;
+use crate::meta::{
+    MetaData,
+    digests_from_path,
+};
+use crate::rdf;
+
+/// Extension of sidecar files next to the file they hold the record for.
+pub const SIDECAR_EXT: &str = "ttl";
+
+/// File name of the sidecar holding the records for all files in a directory.
+pub const DIR_SIDECAR: &str = ".kitab.ttl";
+
+/// Attribute read to probe for extended attribute support. It need not exist.
+const XATTR_PROBE: &str = "user.kitab.probe";
+
+/// Where to write sidecar files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SidecarMode {
+    /// One `<file>.ttl` per file.
+    File,
+    /// One `.kitab.ttl` per directory.
+    Directory,
+}
+
+impl FromStr for SidecarMode {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<SidecarMode, Self::Err> {
+        match s {
+            "file" => {
+                Ok(SidecarMode::File)
+            },
+            "dir" => {
+                Ok(SidecarMode::Directory)
+            },
+            _ => {
+                Err(Error::field("sidecar mode", s))
+            },
+        }
+    }
+}
+
+/// Returns the path of the sidecar holding the record for the file.
+pub fn sidecar_path(filepath: &Path, mode: SidecarMode) -> PathBuf {
+    match mode {
+        SidecarMode::File => {
+            let mut s = filepath.as_os_str().to_os_string();
+            s.push(".");
+            s.push(SIDECAR_EXT);
+            PathBuf::from(s)
+        },
+        SidecarMode::Directory => {
+            filepath.with_file_name(DIR_SIDECAR)
+        },
+    }
+}
+
+/// Returns the file that a `<file>.ttl` sidecar holds the record for, if that file exists.
+pub fn media_path(sidecar: &Path) -> Option<PathBuf> {
+    if sidecar.extension()? != SIDECAR_EXT || sidecar.file_name()? == DIR_SIDECAR {
+        return None;
+    }
+    let p = sidecar.with_extension("");
+    match p.is_file() {
+        true => {
+            Some(p)
+        },
+        false => {
+            None
+        },
+    }
+}
+
+/// Check whether the path is a sidecar of either kind.
+pub fn is_sidecar(p: &Path) -> bool {
+    p.file_name() == Some(DIR_SIDECAR.as_ref()) || media_path(p).is_some()
+}
+
+/// Check whether the filesystem holding the file supports user extended attributes.
+///
+/// The filesystem is probed by reading an attribute, so the file is not changed.
+pub fn xattr_supported(filepath: &Path) -> Result<bool, Error> {
+    match xattr::get(filepath, XATTR_PROBE) {
+        Ok(_) => {
+            Ok(true)
+        },
+        Err(e) => {
+            match Error::from_xattr(e, filepath) {
+                Error::XattrUnsupported(_) => {
+                    Ok(false)
+                },
+                e => {
+                    Err(e)
+                },
+            }
+        },
+    }
+}
+
+/// Extended attribute support of filesystems, probed once per filesystem.
+#[derive(Debug, Default)]
+pub struct XattrSupport {
+    devices: HashMap<u64, bool>,
+}
+
+impl XattrSupport {
+    pub fn new() -> XattrSupport {
+        XattrSupport::default()
+    }
+
+    /// Check whether the filesystem holding the file supports user extended attributes.
+    pub fn supported(&mut self, filepath: &Path) -> Result<bool, Error> {
+        let dev = fs::metadata(filepath)?.dev();
+        if let Some(v) = self.devices.get(&dev) {
+            return Ok(*v);
+        }
+        let r = xattr_supported(filepath)?;
+        debug!("extended attributes supported on device {} of {:?}: {}", dev, filepath, r);
+        self.devices.insert(dev, r);
+        Ok(r)
+    }
+}
+
+/// Write the record to the `<file>.ttl` sidecar of the file, replacing any existing sidecar.
+pub fn write(entry: &MetaData, filepath: &Path) -> Result<(), Error> {
+    let f = File::create(sidecar_path(filepath, SidecarMode::File))?;
+    rdf::write(entry, f)?;
+    Ok(())
+}
+
+/// Change that writing a record would make to a sidecar.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SidecarChange {
+    /// The sidecar has no record for the file yet, or does not exist.
+    Add,
+    /// The sidecar has a different record for the file.
+    Change,
+}
+
+/// Returns the records in the sidecar, or none if it does not exist.
+///
+/// Unlike [read](read), the records are not checked against the files they are for.
+fn read_existing(sidecar: &Path) -> Result<Vec<MetaData>, Error> {
+    match File::open(sidecar) {
+        Ok(f) => {
+            rdf::read_all(f)
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Ok(vec!())
+        },
+        Err(e) => {
+            Err(Error::Io(e))
+        },
+    }
+}
+
+/// Check whether two records share a digest, and so are for the same file.
+fn same_file(a: &MetaData, b: &MetaData) -> bool {
+    a.digests().iter().any(|v| {
+        b.has_digest(v)
+    })
+}
+
+/// Returns the rdf-turtle of the record, for comparing records.
+fn turtle(entry: &MetaData) -> Result<Vec<u8>, Error> {
+    let mut v: Vec<u8> = vec!();
+    rdf::write(entry, &mut v)?;
+    Ok(v)
+}
+
+/// Returns the change that writing the record for the file would make to its sidecar, if any.
+///
+/// # Arguments
+///
+/// * `entry` - record to write.
+/// * `filepath` - file the record is for.
+/// * `mode` - kind of sidecar to write.
+pub fn diff(entry: &MetaData, filepath: &Path, mode: SidecarMode) -> Result<Option<SidecarChange>, Error> {
+    let existing = read_existing(&sidecar_path(filepath, mode))?;
+    let v = match existing.iter().find(|v| same_file(v, entry)) {
+        Some(v) => {
+            v
+        },
+        None => {
+            return Ok(Some(SidecarChange::Add));
+        },
+    };
+    match turtle(v)? == turtle(entry)? {
+        true => {
+            Ok(None)
+        },
+        false => {
+            Ok(Some(SidecarChange::Change))
+        },
+    }
+}
+
+/// Merge records into the records of an existing sidecar.
+///
+/// A record replaces the existing records sharing a digest with it, in place. Other records are
+/// added after the existing ones.
+fn merge(existing: Vec<MetaData>, entries: &[MetaData]) -> Vec<MetaData> {
+    let mut r = existing;
+    for m in entries {
+        let mut found = false;
+        let mut merged: Vec<MetaData> = vec!();
+        for v in r {
+            if !same_file(&v, m) {
+                merged.push(v);
+            } else if !found {
+                merged.push(m.clone());
+                found = true;
+            }
+        }
+        if !found {
+            merged.push(m.clone());
+        }
+        r = merged;
+    }
+    r
+}
+
+/// Write the records to the `.kitab.ttl` sidecar of the directory.
+///
+/// The records are merged with those already in the sidecar, so that records for other files in
+/// the directory are kept. An existing record is replaced by a record sharing a digest with it.
+/// The sidecar is replaced in a single step, and is left as it is if it cannot be parsed.
+pub fn write_dir(entries: &[MetaData], dir: &Path) -> Result<(), Error> {
+    let p = dir.join(DIR_SIDECAR);
+    let merged = merge(read_existing(&p)?, entries);
+    let mut p_tmp = p.clone().into_os_string();
+    p_tmp.push(".tmp");
+    let f = File::create(&p_tmp)?;
+    for v in merged.iter() {
+        rdf::write(v, &f)?;
+    }
+    fs::rename(&p_tmp, &p)?;
+    debug!("wrote {} records to sidecar {:?}, {} merged", merged.len(), p, entries.len());
+    Ok(())
+}
+
+/// Returns the files that records in the sidecar may be for.
+fn sidecar_files(sidecar: &Path) -> Result<Vec<PathBuf>, Error> {
+    if let Some(v) = media_path(sidecar) {
+        return Ok(vec!(v));
+    }
+    let dir = match sidecar.parent() {
+        Some(v) if !v.as_os_str().is_empty() => {
+            v
+        },
+        _ => {
+            Path::new(".")
+        },
+    };
+    let mut r: Vec<PathBuf> = vec!();
+    for v in fs::read_dir(dir)? {
+        let p = v?.path();
+        if p.is_file() && !is_sidecar(&p) {
+            r.push(p);
+        }
+    }
+    r.sort();
+    Ok(r)
+}
+
+/// Read the records of a sidecar, checking each against the digests of the files it is next to.
+///
+/// Returns [Error::DigestMismatch](Error::DigestMismatch) if a record in a `<file>.ttl` sidecar
+/// does not match the file, or a record in a `.kitab.ttl` sidecar matches none of the files in the
+/// directory, e.g. because the file was changed after the sidecar was written.
+///
+/// # Arguments
+///
+/// * `sidecar` - path of the sidecar.
+/// * `cache` - optional digest cache to use for the digests of the files.
+pub fn read(sidecar: &Path, cache: Option<&DigestCache>) -> Result<Vec<MetaData>, Error> {
+    let entries = rdf::read_all(File::open(sidecar)?)?;
+    let mut digest_types: Vec<DigestType> = vec!();
+    for m in entries.iter() {
+        for v in m.digests() {
+            match v.digest_type() {
+                Some(typ) if !digest_types.contains(&typ) => {
+                    digest_types.push(typ);
+                },
+                _ => {},
+            };
+        }
+    }
+
+    let mut digests: Vec<RecordDigest> = vec!();
+    for p in sidecar_files(sidecar)? {
+        digests.append(&mut digests_from_path(&p, &digest_types, cache)?);
+    }
+    for m in entries.iter() {
+        if !digests.iter().any(|v| m.has_digest(v)) {
+            return Err(Error::DigestMismatch(sidecar.to_path_buf()));
+        }
+    }
+    debug!("read {} records from sidecar {:?}", entries.len(), sidecar);
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        diff,
+        is_sidecar,
+        media_path,
+        read,
+        sidecar_path,
+        write,
+        write_dir,
+        xattr_supported,
+        SidecarChange,
+        SidecarMode,
+    };
+    use std::fs;
+    use std::path::Path;
+    use biblatex::EntryType;
+    use crate::digest::{
+        DigestType,
+        RecordDigest,
+    };
+    use crate::error::Error;
+    use crate::meta::{
+        MetaData,
+        digests_from_path,
+    };
+
+    #[test]
+    fn test_sidecar_path() {
+        let p = Path::new("foo/bar.pdf");
+        assert_eq!(sidecar_path(p, SidecarMode::File), Path::new("foo/bar.pdf.ttl"));
+        assert_eq!(sidecar_path(p, SidecarMode::Directory), Path::new("foo/.kitab.ttl"));
+        assert!(media_path(Path::new("testdata/bitcoin.pdf.ttl")).is_some());
+        assert!(!is_sidecar(Path::new("testdata/meta.ttl")));
+        assert!(is_sidecar(Path::new("testdata/.kitab.ttl")));
+        assert!(xattr_supported(Path::new("testdata/bitcoin.pdf")).is_ok());
+    }
+
+    #[test]
+    fn test_sidecar_read_write() {
+        let d = tempfile::tempdir().unwrap();
+        let fp = d.path().join("foo.txt");
+        fs::write(&fp, b"foo").unwrap();
+        let fp_other = d.path().join("bar.txt");
+        fs::write(&fp_other, b"bar").unwrap();
+
+        let mut m = MetaData::new("foo", "Bar Baz", EntryType::Article, RecordDigest::Empty, None);
+        for v in digests_from_path(&fp, &vec!(DigestType::Sha512, DigestType::Sha256), None).unwrap() {
+            m.add_digest(v);
+        }
+        write(&m, &fp).unwrap();
+        let r = read(&sidecar_path(&fp, SidecarMode::File), None).unwrap();
+        assert_eq!(r.len(), 1);
+        assert_eq!(r[0].title(), "foo");
+        assert_eq!(r[0].digests(), m.digests());
+
+        let mut m_other = MetaData::new("bar", "Bar Baz", EntryType::Article, RecordDigest::Empty, None);
+        for v in digests_from_path(&fp_other, &vec!(DigestType::Sha256), None).unwrap() {
+            m_other.add_digest(v);
+        }
+        write_dir(&[m.clone(), m_other], d.path()).unwrap();
+        let r = read(&sidecar_path(&fp, SidecarMode::Directory), None).unwrap();
+        assert_eq!(r.len(), 2);
+
+        fs::write(&fp, b"xyzzy").unwrap();
+        let e = read(&sidecar_path(&fp, SidecarMode::File), None).unwrap_err();
+        assert!(matches!(e, Error::DigestMismatch(_)));
+        let e = read(&sidecar_path(&fp, SidecarMode::Directory), None).unwrap_err();
+        assert!(matches!(e, Error::DigestMismatch(_)));
+    }
+
+    #[test]
+    fn test_sidecar_write_dir_merge() {
+        let d = tempfile::tempdir().unwrap();
+        let fp_foo = d.path().join("foo.txt");
+        fs::write(&fp_foo, b"foo").unwrap();
+        let fp_bar = d.path().join("bar.txt");
+        fs::write(&fp_bar, b"bar").unwrap();
+        let digest_types = vec!(DigestType::Sha512);
+
+        let mut m_foo = MetaData::new("foo", "Bar Baz", EntryType::Article, RecordDigest::Empty, None);
+        for v in digests_from_path(&fp_foo, &digest_types, None).unwrap() {
+            m_foo.add_digest(v);
+        }
+        let mut m_bar = MetaData::new("bar", "Bar Baz", EntryType::Article, RecordDigest::Empty, None);
+        for v in digests_from_path(&fp_bar, &digest_types, None).unwrap() {
+            m_bar.add_digest(v);
+        }
+
+        assert_eq!(diff(&m_foo, &fp_foo, SidecarMode::Directory).unwrap(), Some(SidecarChange::Add));
+        write_dir(&[m_foo.clone()], d.path()).unwrap();
+        assert_eq!(diff(&m_foo, &fp_foo, SidecarMode::Directory).unwrap(), None);
+
+        // a later run for another file in the directory keeps the first record
+        write_dir(&[m_bar.clone()], d.path()).unwrap();
+        let r = read(&sidecar_path(&fp_foo, SidecarMode::Directory), None).unwrap();
+        assert_eq!(r.len(), 2);
+        assert_eq!(r[0].title(), "foo");
+        assert_eq!(r[1].title(), "bar");
+
+        // a record for the same file replaces the existing one
+        m_foo.set_title("xyzzy");
+        assert_eq!(diff(&m_foo, &fp_foo, SidecarMode::Directory).unwrap(), Some(SidecarChange::Change));
+        assert_eq!(diff(&m_foo, &fp_foo, SidecarMode::File).unwrap(), Some(SidecarChange::Add));
+        write_dir(&[m_foo.clone()], d.path()).unwrap();
+        let r = read(&sidecar_path(&fp_foo, SidecarMode::Directory), None).unwrap();
+        assert_eq!(r.len(), 2);
+        assert_eq!(r[0].title(), "xyzzy");
+        assert_eq!(r[1].title(), "bar");
+
+        // an unparsable sidecar is not replaced
+        let p = sidecar_path(&fp_foo, SidecarMode::Directory);
+        fs::write(&p, b"xyzzy").unwrap();
+        assert!(write_dir(&[m_bar], d.path()).is_err());
+        assert_eq!(fs::read(&p).unwrap(), b"xyzzy");
+    }
+}
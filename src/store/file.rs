@@ -0,0 +1,274 @@
+use std::io::Write;
+use std::path::{
+    PathBuf,
+    Path,
+};
+use std::fs::{
+    File,
+    read_dir,
+    remove_file,
+    create_dir_all,
+};
+use std::os::unix::fs::symlink;
+
+use log::{
+    debug,
+};
+
+use crate::meta::MetaData;
+use crate::digest::RecordDigest;
+use crate::rdf;
+use crate::error::Error;
+use crate::store::{
+    Store,
+    ConflictPolicy,
+};
+
+/// Represents the filesystem storage location for metadata.
+///
+/// Each metadata entry is stored in the native rdf-turtle format, in a file named by the hex
+/// digest of the record. Additional digests of the record are symbolic links to that file.
+pub struct FileStore{
+    path: PathBuf,
+    policy: ConflictPolicy,
+}
+
+impl FileStore {
+    /// Create new store.
+    pub fn new(p: &Path) -> Self {
+        FileStore{
+            path: p.to_path_buf(),
+            policy: ConflictPolicy::default(),
+        }
+    }
+
+    /// Returns the filesystem location of the store.
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    fn entry_path(&self, digest: &RecordDigest) -> PathBuf {
+        self.path.join(hex::encode(digest.fingerprint()))
+    }
+
+    /// Generate new writer for adding / modifying a metadata entry in the store.
+    pub fn writer(&self, entry: &MetaData) -> Result<impl Write, std::io::Error> {
+        create_dir_all(&self.path)?;
+        let p = self.path.join(entry.fingerprint());
+        File::create(&p)
+    }
+
+    fn entry_paths(&self) -> Vec<PathBuf> {
+        let entries = match read_dir(&self.path) {
+            Ok(v) => {
+                v
+            },
+            Err(e) => {
+                debug!("cannot read store {:?}: {:?}", &self.path, e);
+                return vec!();
+            },
+        };
+        let mut paths: Vec<PathBuf> = entries.filter_map(Result::ok)
+            .filter(|v| {
+                match v.file_type() {
+                    Ok(t) => {
+                        t.is_file()
+                    },
+                    Err(_) => {
+                        false
+                    },
+                }
+            })
+            .map(|v| {
+                v.path()
+            })
+            .collect();
+        paths.sort();
+        paths
+    }
+}
+
+fn is_link(p: &Path) -> bool {
+    match p.symlink_metadata() {
+        Ok(v) => {
+            v.file_type().is_symlink()
+        },
+        Err(_) => {
+            false
+        },
+    }
+}
+
+impl Store for FileStore {
+    /// Additional digests of the entry are stored as symbolic links to the entry.
+    fn replace(&self, entry: &MetaData) -> Result<(), Error> {
+        let mut existing: Option<String> = None;
+        for v in entry.digests() {
+            if let Ok(m) = self.get(&v) {
+                match &existing {
+                    Some(urn) if *urn != m.urn() => {
+                        return Err(Error::Conflict(entry.urn()));
+                    },
+                    _ => {
+                        existing = Some(m.urn());
+                    },
+                };
+            }
+        }
+
+        let p = self.entry_path(entry.digest());
+        if is_link(&p) {
+            remove_file(&p)?;
+        }
+        let w = self.writer(entry)?;
+        rdf::write(entry, w)?;
+
+        for v in entry.aliases() {
+            let alias_path = self.entry_path(&v);
+            if alias_path.symlink_metadata().is_ok() {
+                remove_file(&alias_path)?;
+            }
+            symlink(entry.fingerprint(), &alias_path)?;
+            debug!("linked alias {:?} to {:?}", v, entry);
+        }
+        debug!("stored {:?} in {:?}", entry, &self.path);
+        Ok(())
+    }
+
+    fn policy(&self) -> ConflictPolicy {
+        self.policy
+    }
+
+    fn set_policy(&mut self, policy: ConflictPolicy) {
+        self.policy = policy;
+    }
+
+    fn get(&self, digest: &RecordDigest) -> Result<MetaData, Error> {
+        let p = self.entry_path(digest);
+        let f = match File::open(&p) {
+            Ok(v) => {
+                v
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(Error::NotFound(digest.urn()));
+            },
+            Err(e) => {
+                return Err(Error::Io(e));
+            },
+        };
+        rdf::read(f)
+    }
+
+    fn contains(&self, digest: &RecordDigest) -> bool {
+        self.entry_path(digest).is_file()
+    }
+
+    /// The entry is removed together with the links of all its additional digests.
+    fn delete(&self, digest: &RecordDigest) -> Result<(), Error> {
+        let m = self.get(digest)?;
+        for v in m.digests() {
+            let p = self.entry_path(&v);
+            if p.symlink_metadata().is_ok() {
+                remove_file(&p)?;
+            }
+        }
+        debug!("deleted {:?} from {:?}", digest, &self.path);
+        Ok(())
+    }
+
+    /// Entries are ordered by hex digest of the digest they are keyed to. Entries that cannot be
+    /// read are skipped.
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item=MetaData> + 'a> {
+        let it = self.entry_paths().into_iter()
+            .filter_map(|p| {
+                let r = File::open(&p)
+                    .map_err(Error::from)
+                    .and_then(rdf::read);
+                match r {
+                    Ok(v) => {
+                        Some(v)
+                    },
+                    Err(e) => {
+                        debug!("cannot read store entry {:?}: {}", &p, e);
+                        None
+                    },
+                }
+            });
+        Box::new(it)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use biblatex::EntryType;
+    use tempfile::tempdir;
+    use super::{
+        FileStore,
+        MetaData,
+    };
+    use crate::digest;
+    use crate::store::tests::{
+        check_put_get_delete,
+        check_search,
+        check_aliases,
+        check_conflict_policy,
+        check_put_all,
+        check_replace_records,
+    };
+    use std::io::Write;
+
+    #[test]
+    fn test_writer() {
+        let mut digest = Vec::with_capacity(64);
+        digest.resize(64, 0x2a);
+        let digest_sha = digest::from_vec(Vec::from(digest)).unwrap();
+        let m = MetaData::new("foo", "bar", EntryType::Article, digest_sha, None);
+        let dir = tempdir().unwrap();
+        let fp = dir.path();
+        let fs = FileStore::new(&fp);
+        let mut w = fs.writer(&m).unwrap();
+        w.write(m.title().as_bytes());
+    }
+
+    #[test]
+    fn test_put_get_delete() {
+        let dir = tempdir().unwrap();
+        let fs = FileStore::new(&dir.path().join("idx"));
+        check_put_get_delete(&fs);
+    }
+
+    #[test]
+    fn test_search() {
+        let dir = tempdir().unwrap();
+        let fs = FileStore::new(dir.path());
+        check_search(&fs);
+    }
+
+    #[test]
+    fn test_aliases() {
+        let dir = tempdir().unwrap();
+        let fs = FileStore::new(dir.path());
+        check_aliases(&fs);
+    }
+
+    #[test]
+    fn test_conflict_policy() {
+        let dir = tempdir().unwrap();
+        let mut fs = FileStore::new(dir.path());
+        check_conflict_policy(&mut fs);
+    }
+
+    #[test]
+    fn test_put_all() {
+        let dir = tempdir().unwrap();
+        let mut fs = FileStore::new(dir.path());
+        check_put_all(&mut fs);
+    }
+
+    #[test]
+    fn test_replace_records() {
+        let dir = tempdir().unwrap();
+        let fs = FileStore::new(dir.path());
+        check_replace_records(&fs);
+    }
+}
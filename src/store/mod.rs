@@ -0,0 +1,414 @@
+//! Storage backends for metadata records.
+//!
+//! A store is selected with a URL-like locator string, see [open](open).
+use std::path::Path;
+use std::str::FromStr;
+
+use log::{
+    debug,
+};
+
+use crate::meta::{
+    MetaData,
+    MergeConflict,
+};
+use crate::digest::RecordDigest;
+use crate::search::Query;
+use crate::error::Error;
+
+pub mod file;
+
+#[cfg(feature = "store_sqlite")]
+pub mod sqlite;
+
+pub use file::FileStore;
+
+#[cfg(feature = "store_sqlite")]
+pub use sqlite::SqliteStore;
+
+/// How to add an entry to a store which already has a record for one of the entry's digests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictPolicy {
+    /// Replace the existing record with the entry. This is the default.
+    Overwrite,
+    /// Leave the existing record unchanged, and discard the entry.
+    Keep,
+    /// Merge the entry into the existing record, see [MetaData::merge](crate::meta::MetaData::merge).
+    Merge,
+    /// Refuse to add the entry, with [Error::AlreadyExists](crate::Error::AlreadyExists).
+    Fail,
+}
+
+impl Default for ConflictPolicy {
+    fn default() -> ConflictPolicy {
+        ConflictPolicy::Overwrite
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<ConflictPolicy, Self::Err> {
+        match s {
+            "overwrite" => {
+                Ok(ConflictPolicy::Overwrite)
+            },
+            "keep" => {
+                Ok(ConflictPolicy::Keep)
+            },
+            "merge" => {
+                Ok(ConflictPolicy::Merge)
+            },
+            "fail" => {
+                Ok(ConflictPolicy::Fail)
+            },
+            _ => {
+                Err(Error::field("conflict policy", s))
+            },
+        }
+    }
+}
+
+/// Operations common to all metadata storage backends.
+pub trait Store {
+    /// Add a metadata entry to the store, replacing any record sharing one of its digests.
+    ///
+    /// Unlike [Store::put](Store::put), this does not apply the conflict policy of the store.
+    ///
+    /// Returns [Error::Conflict](crate::Error::Conflict) if the digests of the entry belong to
+    /// more than one record, as only one of them could be replaced.
+    fn replace(&self, entry: &MetaData) -> Result<(), Error>;
+
+    /// Returns the policy applied by [Store::put](Store::put) to entries already in the store.
+    fn policy(&self) -> ConflictPolicy;
+
+    /// Set the policy applied by [Store::put](Store::put) to entries already in the store.
+    fn set_policy(&mut self, policy: ConflictPolicy);
+
+    /// Add a metadata entry to the store, according to the [policy](ConflictPolicy) of the store.
+    ///
+    /// The entry can afterwards be retrieved by any of its digests.
+    ///
+    /// Returns the field values that were discarded when merging with an existing record.
+    fn put(&self, entry: &MetaData) -> Result<Vec<MergeConflict>, Error> {
+        let mut existing: Option<MetaData> = None;
+        for v in entry.digests() {
+            if let Ok(m) = self.get(&v) {
+                existing = Some(m);
+                break;
+            }
+        }
+        let mut existing = match existing {
+            Some(v) => {
+                v
+            },
+            None => {
+                self.replace(entry)?;
+                return Ok(vec!());
+            },
+        };
+
+        match self.policy() {
+            ConflictPolicy::Overwrite => {
+                let mut m = entry.clone();
+                for v in existing.digests() {
+                    m.add_digest(v);
+                }
+                self.replace(&m)?;
+                Ok(vec!())
+            },
+            ConflictPolicy::Keep => {
+                debug!("keeping existing record {:?}, skipping {:?}", existing, entry);
+                Ok(vec!())
+            },
+            ConflictPolicy::Merge => {
+                let r = existing.merge(entry);
+                self.replace(&existing)?;
+                Ok(r)
+            },
+            ConflictPolicy::Fail => {
+                Err(Error::AlreadyExists(existing.urn()))
+            },
+        }
+    }
+
+    /// Add several metadata entries to the store, as with [Store::put](Store::put).
+    ///
+    /// Returns the result of each entry, in the order given. Stops after the first entry refused
+    /// with [Error::AlreadyExists](crate::Error::AlreadyExists), so that the remaining entries
+    /// are neither added nor in the result. Backends may add the entries in a single transaction.
+    fn put_all(&self, entries: &[MetaData]) -> Vec<Result<Vec<MergeConflict>, Error>> {
+        let mut r: Vec<Result<Vec<MergeConflict>, Error>> = vec!();
+        for v in entries {
+            let rr = self.put(v);
+            let refused = matches!(rr, Err(Error::AlreadyExists(_)));
+            r.push(rr);
+            if refused {
+                break;
+            }
+        }
+        r
+    }
+
+    /// Retrieve the metadata entry having the given digest as one of its digests.
+    ///
+    /// Returns [Error::NotFound](crate::Error::NotFound) if the store has no entry for the digest.
+    fn get(&self, digest: &RecordDigest) -> Result<MetaData, Error>;
+
+    /// Check whether the store has an entry for the given digest.
+    fn contains(&self, digest: &RecordDigest) -> bool;
+
+    /// Remove the metadata entry having the given digest from the store, for all of its digests.
+    fn delete(&self, digest: &RecordDigest) -> Result<(), Error>;
+
+    /// Iterate over all metadata entries in the store.
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item=MetaData> + 'a>;
+
+    /// Return all metadata entries in the store matching the given query.
+    fn search(&self, q: &Query) -> Vec<MetaData> {
+        self.iter()
+            .filter(|m| {
+                q.matches(m)
+            })
+            .collect()
+    }
+}
+
+/// Open the store identified by the given locator.
+///
+/// The store uses the default [ConflictPolicy::Overwrite](ConflictPolicy::Overwrite).
+///
+/// Valid locators are:
+///
+/// * `file://<path>` or a plain `<path>` -> [FileStore](FileStore) directory.
+/// * `sqlite://<path>` -> [SqliteStore](SqliteStore) database file, if built with the
+///   `store_sqlite` feature.
+pub fn open(locator: &str) -> Result<Box<dyn Store>, Error> {
+    match locator.split_once("://") {
+        Some(("file", p)) => {
+            Ok(Box::new(FileStore::new(Path::new(p))))
+        },
+        #[cfg(feature = "store_sqlite")]
+        Some(("sqlite", p)) => {
+            let st = SqliteStore::open(Path::new(p))?;
+            Ok(Box::new(st))
+        },
+        Some((scheme, _)) => {
+            Err(Error::field("store scheme", scheme))
+        },
+        None => {
+            Ok(Box::new(FileStore::new(Path::new(locator))))
+        },
+    }
+}
+
+/// Test suite run against every storage backend.
+#[cfg(test)]
+pub(crate) mod tests {
+    use biblatex::EntryType;
+    use super::{
+        Store,
+        MetaData,
+        Query,
+        ConflictPolicy,
+    };
+    use crate::digest;
+    use crate::error::Error;
+
+    fn metadata(title: &str, author: &str, typ: EntryType, fill: u8) -> MetaData {
+        let mut digest = Vec::with_capacity(64);
+        digest.resize(64, fill);
+        let digest_sha = digest::from_vec(digest).unwrap();
+        MetaData::new(title, author, typ, digest_sha, None)
+    }
+
+    pub fn check_put_get_delete(st: &dyn Store) {
+        let mut m = metadata("foo", "bar", EntryType::Article, 0x2a);
+        m.set_subject("baz");
+        m.set_mime_str("application/pdf").unwrap();
+        m.set_language("nb-NO").unwrap();
+        let digest_sha = digest::from_urn(m.urn().as_str()).unwrap();
+
+        assert!(!st.contains(&digest_sha));
+        assert!(matches!(st.get(&digest_sha), Err(Error::NotFound(_))));
+
+        st.put(&m).unwrap();
+        assert!(st.contains(&digest_sha));
+        let m_check = st.get(&digest_sha).unwrap();
+        assert_eq!(m_check.title(), "foo");
+        assert_eq!(m_check.author(), "bar");
+        assert_eq!(m_check.typ(), EntryType::Article);
+        assert_eq!(m_check.subject().unwrap(), "baz");
+        assert_eq!(m_check.mime().unwrap(), "application/pdf");
+        assert_eq!(m_check.language().unwrap(), "nb-NO");
+        assert_eq!(m_check.urn(), m.urn());
+        assert_eq!(st.iter().count(), 1);
+
+        m.set_title("xyzzy");
+        st.put(&m).unwrap();
+        assert_eq!(st.get(&digest_sha).unwrap().title(), "xyzzy");
+        assert_eq!(st.iter().count(), 1);
+
+        st.delete(&digest_sha).unwrap();
+        assert!(!st.contains(&digest_sha));
+        assert!(st.delete(&digest_sha).is_err());
+        assert_eq!(st.iter().count(), 0);
+    }
+
+    pub fn check_search(st: &dyn Store) {
+        let mut m = metadata("foo", "bar", EntryType::Article, 0x2a);
+        m.set_subject("baz,xyzzy");
+        st.put(&m).unwrap();
+
+        let m = metadata("inky pinky", "ponky", EntryType::Book, 0x2b);
+        st.put(&m).unwrap();
+
+        let mut q = Query::new();
+        assert_eq!(st.search(&q).len(), 2);
+
+        q.subject = Some(String::from("xyzzy"));
+        let r = st.search(&q);
+        assert_eq!(r.len(), 1);
+        assert_eq!(r[0].title(), "foo");
+
+        let mut q = Query::new();
+        q.typ = Some(String::from("book"));
+        let r = st.search(&q);
+        assert_eq!(r.len(), 1);
+        assert_eq!(r[0].title(), "inky pinky");
+
+        let mut m = metadata("Über Pinky", "Baz", EntryType::Book, 0x2c);
+        m.set_mime_str("application/pdf").unwrap();
+        m.set_language("nb-NO").unwrap();
+        st.put(&m).unwrap();
+
+        let mut q = Query::new();
+        q.title = Some(String::from("PINKY"));
+        assert_eq!(st.search(&q).len(), 2);
+        q.title = Some(String::from("über"));
+        q.creator = Some(String::from("BAZ"));
+        assert_eq!(st.search(&q).len(), 1);
+
+        let mut q = Query::new();
+        q.language = Some(String::from("nb"));
+        q.mime = Some(String::from("application"));
+        assert_eq!(st.search(&q).len(), 1);
+        q.language = Some(String::from("NB-no"));
+        q.mime = Some(String::from("application/pdf"));
+        assert_eq!(st.search(&q).len(), 1);
+        q.language = Some(String::from("n"));
+        assert_eq!(st.search(&q).len(), 0);
+    }
+
+    pub fn check_put_all(st: &mut dyn Store) {
+        let m = metadata("foo", "bar", EntryType::Article, 0x2a);
+        st.put(&m).unwrap();
+
+        st.set_policy(ConflictPolicy::Fail);
+        let entries = vec!(
+            metadata("inky", "pinky", EntryType::Book, 0x2b),
+            metadata("xyzzy", "bar", EntryType::Article, 0x2a),
+            metadata("ponky", "pinky", EntryType::Book, 0x2c),
+        );
+        let r = st.put_all(&entries);
+        assert_eq!(r.len(), 2);
+        assert!(r[0].is_ok());
+        assert!(matches!(r[1], Err(Error::AlreadyExists(_))));
+        assert_eq!(st.iter().count(), 2);
+        assert_eq!(st.get(m.digest()).unwrap().title(), "foo");
+        assert!(!st.contains(entries[2].digest()));
+
+        st.set_policy(ConflictPolicy::Keep);
+        let r = st.put_all(&entries);
+        assert_eq!(r.len(), 3);
+        assert_eq!(st.iter().count(), 3);
+    }
+
+    pub fn check_replace_records(st: &dyn Store) {
+        let m = metadata("foo", "bar", EntryType::Article, 0x2a);
+        st.put(&m).unwrap();
+        let m_other = metadata("inky", "pinky", EntryType::Book, 0x2b);
+        st.put(&m_other).unwrap();
+
+        let mut m_both = metadata("xyzzy", "baz", EntryType::Article, 0x2a);
+        m_both.add_digest(m_other.digest().clone());
+        assert!(matches!(st.replace(&m_both), Err(Error::Conflict(_))));
+        assert!(matches!(st.put(&m_both), Err(Error::Conflict(_))));
+
+        // not a refusal by the conflict policy, so the remaining entries are still added
+        let m_new = metadata("ponky", "pinky", EntryType::Book, 0x2c);
+        let r = st.put_all(&[m_both, m_new.clone()]);
+        assert_eq!(r.len(), 2);
+        assert!(matches!(r[0], Err(Error::Conflict(_))));
+        assert!(r[1].is_ok());
+        assert!(st.contains(m_new.digest()));
+        st.delete(m_new.digest()).unwrap();
+
+        assert_eq!(st.get(m.digest()).unwrap().title(), "foo");
+        assert_eq!(st.get(m_other.digest()).unwrap().title(), "inky");
+        assert_eq!(st.get(m_other.digest()).unwrap().digests(), m_other.digests());
+        assert_eq!(st.iter().count(), 2);
+    }
+
+    pub fn check_aliases(st: &dyn Store) {
+        let mut m = metadata("foo", "bar", EntryType::Article, 0x2a);
+        let digest_sha = m.digest().clone();
+        let digest_alias = digest::RecordDigest::Sha256(vec!(0x2b; 32));
+        m.add_digest(digest_alias.clone());
+        st.put(&m).unwrap();
+
+        assert!(st.contains(&digest_sha));
+        assert!(st.contains(&digest_alias));
+        let m_check = st.get(&digest_alias).unwrap();
+        assert_eq!(m_check.title(), "foo");
+        assert_eq!(m_check.digests(), m.digests());
+        assert_eq!(st.iter().count(), 1);
+
+        m.set_title("xyzzy");
+        st.put(&m).unwrap();
+        assert_eq!(st.get(&digest_alias).unwrap().title(), "xyzzy");
+        assert_eq!(st.iter().count(), 1);
+
+        st.delete(&digest_alias).unwrap();
+        assert!(!st.contains(&digest_sha));
+        assert!(!st.contains(&digest_alias));
+        assert_eq!(st.iter().count(), 0);
+    }
+
+    pub fn check_conflict_policy(st: &mut dyn Store) {
+        let mut m = metadata("foo", "bar", EntryType::Article, 0x2a);
+        let digest_sha = m.digest().clone();
+        st.put(&m).unwrap();
+
+        let mut m_other = metadata("foo", "baz", EntryType::Article, 0x2a);
+        m_other.set_subject("xyzzy");
+        let digest_alias = digest::RecordDigest::Sha256(vec!(0x2b; 32));
+        m_other.add_digest(digest_alias.clone());
+
+        st.set_policy(ConflictPolicy::Fail);
+        let e = st.put(&m_other).unwrap_err();
+        assert!(matches!(e, Error::AlreadyExists(_)));
+
+        st.set_policy(ConflictPolicy::Keep);
+        assert_eq!(st.put(&m_other).unwrap().len(), 0);
+        let m_check = st.get(&digest_sha).unwrap();
+        assert_eq!(m_check.author(), "bar");
+        assert!(!st.contains(&digest_alias));
+
+        st.set_policy(ConflictPolicy::Merge);
+        let r = st.put(&m_other).unwrap();
+        assert_eq!(r.len(), 1);
+        assert_eq!(r[0].field, "creator");
+        let m_check = st.get(&digest_alias).unwrap();
+        assert_eq!(m_check.author(), "bar");
+        assert_eq!(m_check.subject().unwrap(), "xyzzy");
+        assert_eq!(st.iter().count(), 1);
+
+        m.set_title("inky");
+        st.set_policy(ConflictPolicy::Overwrite);
+        assert_eq!(st.put(&m).unwrap().len(), 0);
+        let m_check = st.get(&digest_alias).unwrap();
+        assert_eq!(m_check.title(), "inky");
+        assert_eq!(m_check.subject(), None);
+        assert_eq!(st.iter().count(), 1);
+    }
+}
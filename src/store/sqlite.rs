@@ -0,0 +1,503 @@
+use std::path::Path;
+use std::fs::create_dir_all;
+
+use rusqlite::{
+    Connection,
+    OptionalExtension,
+    params,
+    params_from_iter,
+};
+
+use log::{
+    debug,
+};
+
+use crate::meta::{
+    MetaData,
+    MergeConflict,
+};
+use crate::digest::RecordDigest;
+use crate::search::Query;
+use crate::rdf;
+use crate::error::Error;
+use crate::store::{
+    Store,
+    ConflictPolicy,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS record (
+    id INTEGER PRIMARY KEY,
+    title TEXT NOT NULL,
+    creator TEXT NOT NULL,
+    typ TEXT NOT NULL,
+    subject TEXT,
+    mime TEXT,
+    language TEXT,
+    data BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS digest (
+    urn TEXT PRIMARY KEY,
+    record INTEGER NOT NULL REFERENCES record(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS digest_record ON digest(record);
+CREATE TABLE IF NOT EXISTS keyword (
+    record INTEGER NOT NULL REFERENCES record(id) ON DELETE CASCADE,
+    keyword TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS keyword_keyword ON keyword(keyword);
+CREATE INDEX IF NOT EXISTS keyword_record ON keyword(record);
+CREATE INDEX IF NOT EXISTS record_typ ON record(typ);
+CREATE INDEX IF NOT EXISTS record_language ON record(language);
+CREATE INDEX IF NOT EXISTS record_mime ON record(mime);
+";
+
+/// Version of the indexed columns, kept in `PRAGMA user_version`.
+///
+/// Databases with an older version have their indexed columns rebuilt from the record data when
+/// opened.
+const SCHEMA_VERSION: i64 = 1;
+
+/// Values of a record as kept in the indexed columns, lowercased for case-insensitive search.
+struct IndexValues {
+    title: String,
+    creator: String,
+    typ: String,
+    subject: Option<String>,
+    mime: Option<String>,
+    language: Option<String>,
+    keywords: Vec<String>,
+}
+
+impl IndexValues {
+    fn new(entry: &MetaData) -> IndexValues {
+        IndexValues{
+            title: entry.title().to_lowercase(),
+            creator: entry.author().to_lowercase(),
+            typ: entry.typ().to_string().to_lowercase(),
+            subject: entry.subject().map(|v| v.to_lowercase()),
+            mime: entry.mime().map(|v| v.essence_str().to_lowercase()),
+            language: entry.language().map(|v| v.to_string().to_lowercase()),
+            keywords: entry.subjects().iter().map(|v| v.to_lowercase()).collect(),
+        }
+    }
+}
+
+fn to_store_error(e: rusqlite::Error) -> Error {
+    Error::Store(e.to_string())
+}
+
+/// Represents an [SQLite](https://sqlite.org) database storage location for metadata.
+///
+/// Each record is kept in the native rdf-turtle format, alongside indexed columns for the Dublin
+/// Core fields. Records are looked up by the URN of any of their digests.
+///
+/// When an entry is added, any existing record sharing one of its digests is handled according
+/// to the [ConflictPolicy](ConflictPolicy) of the store.
+pub struct SqliteStore {
+    conn: Connection,
+    policy: ConflictPolicy,
+}
+
+impl SqliteStore {
+    /// Open the database in the file at `p`, creating it if it does not exist.
+    pub fn open(p: &Path) -> Result<Self, Error> {
+        if let Some(v) = p.parent() {
+            if !v.as_os_str().is_empty() {
+                create_dir_all(v)?;
+            }
+        }
+        let conn = Connection::open(p).map_err(to_store_error)?;
+        SqliteStore::from_connection(conn)
+    }
+
+    /// Open a database which only exists in memory.
+    pub fn open_in_memory() -> Result<Self, Error> {
+        let conn = Connection::open_in_memory().map_err(to_store_error)?;
+        SqliteStore::from_connection(conn)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, Error> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;").map_err(to_store_error)?;
+        conn.execute_batch(SCHEMA).map_err(to_store_error)?;
+        let st = SqliteStore{
+            conn,
+            policy: ConflictPolicy::default(),
+        };
+        let version: i64 = st.conn.query_row("PRAGMA user_version", [], |row| row.get(0)).map_err(to_store_error)?;
+        if version < SCHEMA_VERSION {
+            st.savepoint(|| {
+                st.reindex()
+            })?;
+        }
+        Ok(st)
+    }
+
+    /// Run `f` in a savepoint, which is rolled back if `f` returns an error.
+    ///
+    /// Outside of a transaction, this is a transaction of its own.
+    fn savepoint<T>(&self, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        self.conn.execute_batch("SAVEPOINT kitab").map_err(to_store_error)?;
+        match f() {
+            Ok(v) => {
+                self.conn.execute_batch("RELEASE kitab").map_err(to_store_error)?;
+                Ok(v)
+            },
+            Err(e) => {
+                if let Err(e_rollback) = self.conn.execute_batch("ROLLBACK TO kitab; RELEASE kitab") {
+                    debug!("sqlite rollback failed: {:?}", e_rollback);
+                }
+                Err(e)
+            },
+        }
+    }
+
+    /// Set the indexed columns of the record with the given id.
+    fn index(&self, id: i64, v: &IndexValues) -> Result<(), Error> {
+        self.conn.execute(
+            "UPDATE record SET title = ?1, creator = ?2, typ = ?3, subject = ?4, mime = ?5, language = ?6 WHERE id = ?7",
+            params![v.title, v.creator, v.typ, v.subject, v.mime, v.language, id],
+        ).map_err(to_store_error)?;
+        self.conn.execute("DELETE FROM keyword WHERE record = ?1", params![id]).map_err(to_store_error)?;
+        for k in v.keywords.iter() {
+            self.conn.execute(
+                "INSERT INTO keyword (record, keyword) VALUES (?1, ?2)",
+                params![id, k],
+            ).map_err(to_store_error)?;
+        }
+        Ok(())
+    }
+
+    /// Rebuild the indexed columns of all records from their data.
+    fn reindex(&self) -> Result<(), Error> {
+        let mut rows: Vec<(i64, Vec<u8>)> = vec!();
+        {
+            let mut stmt = self.conn.prepare("SELECT id, data FROM record").map_err(to_store_error)?;
+            let r = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).map_err(to_store_error)?;
+            for v in r {
+                rows.push(v.map_err(to_store_error)?);
+            }
+        }
+        for (id, data) in rows {
+            let m = rdf::read(data.as_slice())?;
+            self.index(id, &IndexValues::new(&m))?;
+        }
+        self.conn.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION)).map_err(to_store_error)?;
+        debug!("reindexed sqlite store to version {}", SCHEMA_VERSION);
+        Ok(())
+    }
+
+    /// Returns the data of the records matching the query, as far as it can be expressed in SQL.
+    fn query_data(&self, q: &Query) -> Result<Vec<Vec<u8>>, Error> {
+        let mut clauses: Vec<String> = vec!();
+        let mut values: Vec<String> = vec!();
+        if let Some(v) = &q.title {
+            values.push(v.to_lowercase());
+            clauses.push(format!("instr(title, ?{}) > 0", values.len()));
+        }
+        if let Some(v) = &q.creator {
+            values.push(v.to_lowercase());
+            clauses.push(format!("instr(creator, ?{}) > 0", values.len()));
+        }
+        if let Some(v) = &q.subject {
+            values.push(v.split_whitespace().collect::<Vec<&str>>().join(" ").to_lowercase());
+            clauses.push(format!("id IN (SELECT record FROM keyword WHERE keyword = ?{})", values.len()));
+        }
+        if let Some(v) = &q.language {
+            values.push(v.to_lowercase());
+            clauses.push(format!("(language = ?{0} OR substr(language, 1, length(?{0}) + 1) = ?{0} || '-')", values.len()));
+        }
+        if let Some(v) = &q.typ {
+            values.push(v.to_lowercase());
+            clauses.push(format!("typ = ?{}", values.len()));
+        }
+        match &q.mime {
+            Some(v) if v.contains("/") => {
+                values.push(v.to_lowercase());
+                clauses.push(format!("mime = ?{}", values.len()));
+            },
+            Some(v) => {
+                values.push(v.to_lowercase());
+                clauses.push(format!("substr(mime, 1, length(?{0}) + 1) = ?{0} || '/'", values.len()));
+            },
+            None => {},
+        };
+
+        let mut sql = String::from("SELECT data FROM record");
+        if !clauses.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&clauses.join(" AND "));
+        }
+        sql.push_str(" ORDER BY id");
+        debug!("sqlite search {} {:?}", sql, values);
+
+        let mut stmt = self.conn.prepare(&sql).map_err(to_store_error)?;
+        let rows = stmt.query_map(params_from_iter(values.iter()), |row| row.get::<_, Vec<u8>>(0)).map_err(to_store_error)?;
+        let mut r: Vec<Vec<u8>> = vec!();
+        for v in rows {
+            r.push(v.map_err(to_store_error)?);
+        }
+        Ok(r)
+    }
+
+    fn record_id(&self, digest: &RecordDigest) -> Result<Option<i64>, Error> {
+        self.conn.query_row(
+            "SELECT record FROM digest WHERE urn = ?1",
+            params![digest.urn()],
+            |row| row.get(0),
+        ).optional().map_err(to_store_error)
+    }
+}
+
+impl Store for SqliteStore {
+    /// The record, its digests and its indexed columns are written in a single transaction.
+    fn replace(&self, entry: &MetaData) -> Result<(), Error> {
+        let mut data: Vec<u8> = vec!();
+        rdf::write(entry, &mut data)?;
+
+        self.savepoint(|| {
+            let mut id: Option<i64> = None;
+            for v in entry.digests() {
+                match (self.record_id(&v)?, id) {
+                    (Some(v_id), Some(id)) if v_id != id => {
+                        return Err(Error::Conflict(entry.urn()));
+                    },
+                    (Some(v_id), _) => {
+                        id = Some(v_id);
+                    },
+                    _ => {},
+                };
+            }
+
+            let id = match id {
+                Some(id) => {
+                    self.conn.execute(
+                        "UPDATE record SET data = ?1 WHERE id = ?2",
+                        params![data, id],
+                    ).map_err(to_store_error)?;
+                    id
+                },
+                None => {
+                    self.conn.execute(
+                        "INSERT INTO record (title, creator, typ, data) VALUES ('', '', '', ?1)",
+                        params![data],
+                    ).map_err(to_store_error)?;
+                    self.conn.last_insert_rowid()
+                },
+            };
+            self.index(id, &IndexValues::new(entry))?;
+
+            self.conn.execute("DELETE FROM digest WHERE record = ?1", params![id]).map_err(to_store_error)?;
+            for v in entry.digests() {
+                self.conn.execute(
+                    "INSERT OR REPLACE INTO digest (urn, record) VALUES (?1, ?2)",
+                    params![v.urn(), id],
+                ).map_err(to_store_error)?;
+            }
+            self.conn.execute("DELETE FROM record WHERE id NOT IN (SELECT record FROM digest)", []).map_err(to_store_error)?;
+            Ok(())
+        })?;
+        debug!("stored {:?} in sqlite", entry);
+        Ok(())
+    }
+
+    /// All entries are added in a single transaction. An entry that fails leaves the store
+    /// unchanged for that entry only.
+    fn put_all(&self, entries: &[MetaData]) -> Vec<Result<Vec<MergeConflict>, Error>> {
+        let mut r: Vec<Result<Vec<MergeConflict>, Error>> = vec!();
+        let rr = self.savepoint(|| {
+            for v in entries {
+                let rr = self.put(v);
+                let refused = matches!(rr, Err(Error::AlreadyExists(_)));
+                r.push(rr);
+                if refused {
+                    break;
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = rr {
+            debug!("sqlite bulk put failed: {}", e);
+            let e = e.to_string();
+            return entries.iter().map(|_| Err(Error::Store(e.clone()))).collect();
+        }
+        r
+    }
+
+    /// The query is run as SQL against the indexed columns, so only matching records are read.
+    fn search(&self, q: &Query) -> Vec<MetaData> {
+        let data = match self.query_data(q) {
+            Ok(v) => {
+                v
+            },
+            Err(e) => {
+                debug!("cannot search sqlite store: {}", e);
+                return vec!();
+            },
+        };
+        data.iter()
+            .filter_map(|v| {
+                match rdf::read(v.as_slice()) {
+                    Ok(v) => {
+                        Some(v)
+                    },
+                    Err(e) => {
+                        debug!("cannot read sqlite store entry: {}", e);
+                        None
+                    },
+                }
+            })
+            .filter(|m| {
+                q.matches(m)
+            })
+            .collect()
+    }
+
+    fn policy(&self) -> ConflictPolicy {
+        self.policy
+    }
+
+    fn set_policy(&mut self, policy: ConflictPolicy) {
+        self.policy = policy;
+    }
+
+    fn get(&self, digest: &RecordDigest) -> Result<MetaData, Error> {
+        let data: Option<Vec<u8>> = self.conn.query_row(
+            "SELECT r.data FROM record r JOIN digest d ON d.record = r.id WHERE d.urn = ?1",
+            params![digest.urn()],
+            |row| row.get(0),
+        ).optional().map_err(to_store_error)?;
+        match data {
+            Some(v) => {
+                rdf::read(v.as_slice())
+            },
+            None => {
+                Err(Error::NotFound(digest.urn()))
+            },
+        }
+    }
+
+    fn contains(&self, digest: &RecordDigest) -> bool {
+        matches!(self.record_id(digest), Ok(Some(_)))
+    }
+
+    fn delete(&self, digest: &RecordDigest) -> Result<(), Error> {
+        let id = match self.record_id(digest)? {
+            Some(v) => {
+                v
+            },
+            None => {
+                return Err(Error::NotFound(digest.urn()));
+            },
+        };
+        self.conn.execute("DELETE FROM record WHERE id = ?1", params![id]).map_err(to_store_error)?;
+        debug!("deleted {:?} from sqlite", digest);
+        Ok(())
+    }
+
+    /// Entries are ordered by insertion. Entries that cannot be read are skipped.
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item=MetaData> + 'a> {
+        let mut stmt = match self.conn.prepare("SELECT data FROM record ORDER BY id") {
+            Ok(v) => {
+                v
+            },
+            Err(e) => {
+                debug!("cannot read sqlite store: {:?}", e);
+                return Box::new(std::iter::empty());
+            },
+        };
+        let r: Vec<MetaData> = match stmt.query_map([], |row| row.get::<_, Vec<u8>>(0)) {
+            Ok(rows) => {
+                rows.filter_map(Result::ok)
+                    .filter_map(|v| {
+                        match rdf::read(v.as_slice()) {
+                            Ok(v) => {
+                                Some(v)
+                            },
+                            Err(e) => {
+                                debug!("cannot read sqlite store entry: {}", e);
+                                None
+                            },
+                        }
+                    })
+                    .collect()
+            },
+            Err(e) => {
+                debug!("cannot read sqlite store: {:?}", e);
+                vec!()
+            },
+        };
+        Box::new(r.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+    use super::SqliteStore;
+    use crate::store::tests::{
+        check_put_get_delete,
+        check_search,
+        check_aliases,
+        check_conflict_policy,
+        check_put_all,
+        check_replace_records,
+    };
+    use crate::search::Query;
+    use crate::store::Store;
+
+    #[test]
+    fn test_put_get_delete() {
+        let dir = tempdir().unwrap();
+        let st = SqliteStore::open(&dir.path().join("idx.sqlite")).unwrap();
+        check_put_get_delete(&st);
+    }
+
+    #[test]
+    fn test_search() {
+        let st = SqliteStore::open_in_memory().unwrap();
+        check_search(&st);
+    }
+
+    #[test]
+    fn test_aliases() {
+        let st = SqliteStore::open_in_memory().unwrap();
+        check_aliases(&st);
+    }
+
+    #[test]
+    fn test_conflict_policy() {
+        let mut st = SqliteStore::open_in_memory().unwrap();
+        check_conflict_policy(&mut st);
+    }
+
+    #[test]
+    fn test_put_all() {
+        let mut st = SqliteStore::open_in_memory().unwrap();
+        check_put_all(&mut st);
+    }
+
+    #[test]
+    fn test_replace_records() {
+        let st = SqliteStore::open_in_memory().unwrap();
+        check_replace_records(&st);
+    }
+
+    #[test]
+    fn test_reindex() {
+        let dir = tempdir().unwrap();
+        let p = dir.path().join("idx.sqlite");
+        let st = SqliteStore::open(&p).unwrap();
+        check_search(&st);
+        st.conn.execute_batch("UPDATE record SET title = 'xyzzy'; DELETE FROM keyword; PRAGMA user_version = 0;").unwrap();
+        drop(st);
+
+        let st = SqliteStore::open(&p).unwrap();
+        let mut q = Query::new();
+        q.title = Some(String::from("Pinky"));
+        assert_eq!(st.search(&q).len(), 2);
+        let mut q = Query::new();
+        q.subject = Some(String::from("xyzzy"));
+        assert_eq!(st.search(&q).len(), 1);
+    }
+}
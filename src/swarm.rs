@@ -0,0 +1,195 @@
+//! Swarm content address calculation.
+//!
+//! Data is split into chunks of 4096 bytes. The address of a chunk is the keccak256 of its span,
+//! the number of data bytes it covers as a 64-bit little-endian integer, followed by the binary
+//! merkle tree (BMT) root of its payload zero-padded to 4096 bytes.
+//!
+//! The addresses of the data chunks are themselves packed 128 at a time into intermediate chunks,
+//! up to a single root chunk whose address is the Swarm hash of the data. A single address left
+//! over on a level is carried up to the next level as it is, without wrapping it in a chunk.
+use sha3::{
+    Keccak256,
+    Digest,
+};
+
+/// Size of a chunk payload in bytes.
+pub const CHUNK_SIZE: usize = 4096;
+
+/// Size of a chunk address in bytes.
+pub const REF_SIZE: usize = 32;
+
+const SEGMENT_PAIR_SIZE: usize = REF_SIZE * 2;
+
+/// Calculates the BMT root of a chunk payload.
+fn bmt_root(payload: &[u8]) -> Vec<u8> {
+    let mut level: Vec<u8> = vec!(0; CHUNK_SIZE);
+    level[..payload.len()].copy_from_slice(payload);
+    while level.len() > REF_SIZE {
+        level = level.chunks(SEGMENT_PAIR_SIZE)
+            .flat_map(|v| {
+                Keccak256::digest(v).to_vec()
+            })
+            .collect();
+    }
+    level
+}
+
+/// Calculates the address of a chunk with the given span and payload.
+///
+/// The payload must be no larger than [CHUNK_SIZE](CHUNK_SIZE).
+pub fn chunk_address(span: u64, payload: &[u8]) -> Vec<u8> {
+    let mut h = Keccak256::new();
+    h.update(span.to_le_bytes());
+    h.update(bmt_root(payload));
+    h.finalize().to_vec()
+}
+
+struct Level {
+    refs: Vec<u8>,
+    span: u64,
+}
+
+/// Calculates the Swarm hash of data in a single pass, keeping at most one chunk per tree level
+/// in memory.
+pub struct SwarmHasher {
+    buf: Vec<u8>,
+    levels: Vec<Level>,
+    length: u64,
+}
+
+impl Default for SwarmHasher {
+    fn default() -> Self {
+        SwarmHasher::new()
+    }
+}
+
+impl SwarmHasher {
+    pub fn new() -> SwarmHasher {
+        SwarmHasher{
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            levels: vec!(),
+            length: 0,
+        }
+    }
+
+    fn add_ref(&mut self, level: usize, address: Vec<u8>, span: u64) {
+        if self.levels.len() == level {
+            self.levels.push(Level{
+                refs: vec!(),
+                span: 0,
+            });
+        }
+        let l = &mut self.levels[level];
+        l.refs.extend(address);
+        l.span += span;
+        if l.refs.len() == CHUNK_SIZE {
+            let span = l.span;
+            let address = chunk_address(span, &l.refs);
+            l.refs.clear();
+            l.span = 0;
+            self.add_ref(level + 1, address, span);
+        }
+    }
+
+    fn flush_chunk(&mut self) {
+        let span = self.buf.len() as u64;
+        let address = chunk_address(span, &self.buf);
+        self.buf.clear();
+        self.add_ref(0, address, span);
+    }
+
+    /// Add data to the digest.
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        while !data.is_empty() {
+            let c = (CHUNK_SIZE - self.buf.len()).min(data.len());
+            self.buf.extend_from_slice(&data[..c]);
+            data = &data[c..];
+            if self.buf.len() == CHUNK_SIZE {
+                self.flush_chunk();
+            }
+        }
+    }
+
+    /// Returns the Swarm hash of all data added.
+    pub fn finalize(mut self) -> Vec<u8> {
+        if !self.buf.is_empty() || self.length == 0 {
+            self.flush_chunk();
+        }
+        let mut i: usize = 0;
+        loop {
+            let top = i + 1 == self.levels.len();
+            let l = &mut self.levels[i];
+            if top && l.refs.len() == REF_SIZE {
+                return l.refs.clone();
+            }
+            let span = l.span;
+            let refs = std::mem::take(&mut l.refs);
+            l.span = 0;
+            match refs.len() {
+                0 => {},
+                REF_SIZE => {
+                    self.add_ref(i + 1, refs, span);
+                },
+                _ => {
+                    let address = chunk_address(span, &refs);
+                    self.add_ref(i + 1, address, span);
+                },
+            };
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        SwarmHasher,
+        CHUNK_SIZE,
+        chunk_address,
+    };
+
+    fn vector(length: usize) -> String {
+        let data: Vec<u8> = (0..length).map(|v| (v % 255) as u8).collect();
+        let mut h = SwarmHasher::new();
+        for v in data.chunks(1000) {
+            h.update(v);
+        }
+        hex::encode(h.finalize())
+    }
+
+    #[test]
+    fn test_swarm_hash() {
+        assert_eq!(vector(31), "ece86edb20669cc60d142789d464d57bdf5e33cb789d443f608cbd81cfa5697d");
+        assert_eq!(vector(32), "0be77f0bb7abc9cd0abed640ee29849a3072ccfd1020019fe03658c38f087e02");
+        assert_eq!(vector(33), "3463b46d4f9d5bfcbf9a23224d635e51896c1daef7d225b86679db17c5fd868e");
+        assert_eq!(vector(63), "95510c2ff18276ed94be2160aed4e69c9116573b6f69faaeed1b426fea6a3db8");
+        assert_eq!(vector(64), "490072cc55b8ad381335ff882ac51303cc069cbcb8d8d3f7aa152d9c617829fe");
+        assert_eq!(vector(65), "541552bae05e9a63a6cb561f69edf36ffe073e441667dbf7a0e9a3864bb744ea");
+        assert_eq!(vector(CHUNK_SIZE), "c10090961e7682a10890c334d759a28426647141213abda93b096b892824d2ef");
+        assert_eq!(vector(CHUNK_SIZE + 31), "91699c83ed93a1f87e326a29ccd8cc775323f9e7260035a5f014c975c5f3cd28");
+    }
+
+    #[test]
+    fn test_swarm_hash_tree() {
+        // 128 full chunks and one dangling byte: the first 128 addresses are wrapped in an
+        // intermediate chunk, the last address is carried up and joins it in the root chunk.
+        let length = CHUNK_SIZE * 128 + 1;
+        let data: Vec<u8> = (0..length).map(|v| (v % 255) as u8).collect();
+        let mut refs: Vec<u8> = vec!();
+        for v in data[..CHUNK_SIZE * 128].chunks(CHUNK_SIZE) {
+            refs.extend(chunk_address(CHUNK_SIZE as u64, v));
+        }
+        let mut root: Vec<u8> = chunk_address((CHUNK_SIZE * 128) as u64, &refs);
+        root.extend(chunk_address(1, &data[CHUNK_SIZE * 128..]));
+        let expect = chunk_address(length as u64, &root);
+
+        let mut h = SwarmHasher::new();
+        h.update(&data);
+        assert_eq!(h.finalize(), expect);
+
+        let mut h = SwarmHasher::new();
+        h.update(&data[..CHUNK_SIZE * 128]);
+        assert_eq!(h.finalize(), chunk_address((CHUNK_SIZE * 128) as u64, &refs));
+    }
+}